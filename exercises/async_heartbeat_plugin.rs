@@ -0,0 +1,150 @@
+use falco_plugin::anyhow::Error;
+use falco_plugin::async_event::{AsyncEvent, AsyncEventPlugin, AsyncHandler, BackgroundTask};
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::{Event, EventMetadata};
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::CStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+//
+// INTRO
+// The scope of this exercise is to emit async events from a background thread,
+// the way real plugins do (the previous exercise emitted a single event right from
+// `start_async`). The SDK provides a helper for exactly this, `BackgroundTask`:
+// https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/async_event/struct.BackgroundTask.html
+//
+
+const HEARTBEAT_NAME_C_STR: &CStr = c"heartbeat";
+const HEARTBEAT_NAME: &str = "heartbeat";
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+struct Config {
+    /// How often to emit a heartbeat
+    interval_ms: u64,
+}
+
+struct HeartbeatPlugin {
+    interval: Duration,
+    /// Controls the background thread: started in `start_async`, stopped in `stop_async`
+    task: Arc<BackgroundTask>,
+    /// The background thread itself, while it's running
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Plugin for HeartbeatPlugin {
+    const NAME: &'static CStr = c"heartbeat";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"emits a heartbeat async event periodically";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<Config>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self {
+            interval: Duration::from_millis(config.interval_ms),
+            task: Arc::default(),
+            thread: None,
+        })
+    }
+}
+
+impl AsyncEventPlugin for HeartbeatPlugin {
+    const ASYNC_EVENTS: &'static [&'static str] = &[HEARTBEAT_NAME];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+
+    // `BackgroundTask::spawn` runs the closure every `interval`, on a thread of its own,
+    // until the task is asked to stop. Each heartbeat carries its sequence number
+    // (starting at zero), so that the receiving side can spot lost events.
+    fn start_async(&mut self, handler: AsyncHandler) -> Result<(), Error> {
+        // TODO: spawn a background thread with `self.task.spawn`, emitting a heartbeat
+        // event every `self.interval`. Its data should be a sequence number (a u64 in
+        // little endian byte order) starting at zero. Keep the thread handle in
+        // `self.thread`.
+        let _ = handler;
+        Ok(())
+    }
+
+    // Stopping must not wait for the next heartbeat to be due
+    fn stop_async(&mut self) -> Result<(), Error> {
+        // TODO: ask the task to stop (waking the thread up right away) and wait
+        // for the thread to exit, returning its result
+        Ok(())
+    }
+}
+
+static_plugin!(HEARTBEAT_PLUGIN = HeartbeatPlugin);
+
+fn main() {
+    // just needed to build the exercise
+}
+
+mod tests {
+    use exercises::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use exercises::trace::{ApiCall, CallResult};
+    use exercises::{EventExt, TestDriver};
+    use falco_plugin::event::events::types::PPME_ASYNCEVENT_E;
+    use std::ffi::CString;
+    use std::time::{Duration, Instant};
+
+    fn start(interval_ms: u64) -> NativeCapturingTestDriver {
+        let config = CString::new(format!(r#"{{"interval_ms": {}}}"#, interval_ms)).unwrap();
+
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.enable_tracing();
+        driver
+            .register_plugin(&super::HEARTBEAT_PLUGIN, &config)
+            .unwrap();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    fn sequence_number(evt: &falco_plugin_runner::Event) -> u64 {
+        let evt = evt.load::<PPME_ASYNCEVENT_E>().unwrap().params;
+        u64::from_le_bytes(evt.data.unwrap().try_into().unwrap())
+    }
+
+    #[test]
+    fn heartbeats_in_order() {
+        let mut driver = start(10);
+
+        let seqs: Vec<_> = (0..5)
+            .map(|_| {
+                let evt = driver
+                    .wait_for_async_event("heartbeat", Duration::from_secs(5))
+                    .unwrap();
+                sequence_number(&evt)
+            })
+            .collect();
+        assert_eq!(seqs, vec![0, 1, 2, 3, 4]);
+
+        // heartbeats keep coming, and none were skipped
+        let evt = driver
+            .wait_for_async_event("heartbeat", Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(sequence_number(&evt), 5);
+        assert_eq!(driver.async_violations(), vec![]);
+    }
+
+    #[test]
+    fn stops_promptly() {
+        // with a heartbeat every 10 seconds, waiting for the next one to stop is obvious
+        let driver = start(10_000);
+
+        let started = Instant::now();
+        let closed = driver.close();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let stop = closed
+            .trace()
+            .into_iter()
+            .find(|r| r.call == ApiCall::AsyncStop)
+            .expect("stop_async was never called");
+        assert_eq!(stop.result, CallResult::Success);
+        assert!(stop.duration < Duration::from_secs(1));
+    }
+}
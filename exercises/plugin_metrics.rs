@@ -0,0 +1,219 @@
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::{
+    Json, Metric, MetricLabel, MetricType, MetricValue, MetricsPlugin, Plugin,
+};
+use falco_plugin::event::events::types::PPME_PLUGINEVENT_E;
+use falco_plugin::extract::EventInput;
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::source::{EventBatch, SourcePlugin, SourcePluginInstance};
+use falco_plugin::static_plugin;
+use falco_plugin::strings::CStringWriter;
+use falco_plugin::tables::TablesInput;
+use rand::Rng;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+
+//
+// INTRO
+// The scope of this exercise is to introduce you the metrics capability.
+// Plugins can expose counters and gauges, which Falco collects along with its own
+// metrics (and exports e.g. to Prometheus). You may want to check the documentation at
+// https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/base/trait.MetricsPlugin.html
+// and the proper section of the Falco documentation at
+// https://falco.org/docs/concepts/metrics/
+//
+
+// Names of the metrics we expose
+const EVENTS_METRIC: &CStr = c"events";
+const REJECTED_METRIC: &CStr = c"rejected";
+const LAST_VALUE_METRIC: &CStr = c"last_value";
+
+/// Our good old random generator, this time keeping some statistics
+struct RandomGenPlugin {
+    range: u64,
+    max: u64,
+    /// How many events were generated
+    events: u64,
+    /// How many random numbers were drawn and thrown away, for being over `max`
+    rejected: u64,
+    /// The last number that made it into an event
+    last_value: u64,
+}
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+struct Config {
+    /// Random numbers are drawn from `0..range`
+    range: u64,
+    /// Numbers over `max` are rejected (and another one is drawn instead)
+    max: u64,
+}
+
+impl Plugin for RandomGenPlugin {
+    const NAME: &'static CStr = c"random_generator";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"generates a continuous stream of random numbers";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<Config>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self {
+            range: config.range,
+            max: config.max,
+            events: 0,
+            rejected: 0,
+            last_value: 0,
+        })
+    }
+}
+
+struct RandomGenPluginInstance;
+
+impl SourcePluginInstance for RandomGenPluginInstance {
+    type Plugin = RandomGenPlugin;
+
+    fn next_batch(
+        &mut self,
+        plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        let mut rng = rand::thread_rng();
+        let num = loop {
+            let num: u64 = rng.gen_range(0..plugin.range);
+            if num <= plugin.max {
+                break num;
+            }
+            // TODO: count the rejected number
+        };
+
+        let event = num.to_le_bytes().to_vec();
+        let event = Self::plugin_event(&event);
+        batch.add(event)?;
+
+        // TODO: count the event and remember its value
+        Ok(())
+    }
+}
+
+impl SourcePlugin for RandomGenPlugin {
+    type Instance = RandomGenPluginInstance;
+    const EVENT_SOURCE: &'static CStr = c"random_generator";
+    const PLUGIN_ID: u32 = 1111;
+
+    fn open(&mut self, _params: Option<&str>) -> Result<Self::Instance, Error> {
+        Ok(RandomGenPluginInstance)
+    }
+
+    fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
+        let event = event.event()?;
+        let event = event.load::<PPME_PLUGINEVENT_E>()?;
+
+        match event.params.event_data {
+            Some(payload) => {
+                let mut writer = CStringWriter::default();
+                writer.write_all(payload)?;
+                Ok(writer.into_cstring())
+            }
+            None => Ok(CString::new("<no payload>")?),
+        }
+    }
+}
+
+// Implement the metrics capability
+//
+// Falco calls `get_metrics` periodically, so it should be cheap: just report
+// the values the plugin keeps up to date anyway.
+//
+// Each metric has a type: monotonic metrics only ever go up (like event counters),
+// non-monotonic ones can go either way (like the last value seen, or a queue length).
+//
+// DOCS: https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/base/trait.MetricsPlugin.html
+impl MetricsPlugin for RandomGenPlugin {
+    fn get_metrics(&mut self) -> impl IntoIterator<Item = Metric> {
+        // TODO: report `events` and `rejected` as monotonic metrics, and `last_value`
+        // as a non-monotonic one, using the names defined at the top of the file.
+        // Check out `MetricLabel::new` and `MetricLabel::with_value`.
+        Vec::new()
+    }
+}
+
+static_plugin!(MY_SOURCE_PLUGIN = RandomGenPlugin);
+
+fn main() {
+    // just needed to build the exercise
+}
+
+mod tests {
+    use exercises::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use exercises::prometheus::MetricType;
+    use exercises::{CapturingTestDriver, EventExt, SinspMetric, TestDriver};
+    use falco_plugin::event::events::types::PPME_PLUGINEVENT_E;
+
+    fn start(config: &std::ffi::CStr) -> NativeCapturingTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&super::MY_SOURCE_PLUGIN, config)
+            .unwrap();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    fn find<'a>(metrics: &'a [SinspMetric], name: &str) -> &'a SinspMetric {
+        metrics
+            .iter()
+            .find(|m| m.name == name)
+            .unwrap_or_else(|| panic!("no metric called {}", name))
+    }
+
+    fn metric(metrics: &[SinspMetric], name: &str) -> u64 {
+        find(metrics, name).value
+    }
+
+    /// Pull `count` events, returning the number in the last one
+    fn pull(driver: &mut NativeCapturingTestDriver, count: usize) -> u64 {
+        let mut last = None;
+        for _ in 0..count {
+            let event = driver.next_event().unwrap();
+            let event = event.load::<PPME_PLUGINEVENT_E>().unwrap();
+            let data = event.params.event_data.unwrap();
+            last = Some(u64::from_le_bytes(data.try_into().unwrap()));
+        }
+        last.unwrap()
+    }
+
+    #[test]
+    fn count_events() {
+        let mut driver = start(cr#"{"range": 10, "max": 9}"#);
+
+        let metrics = driver.get_metrics().unwrap();
+        assert_eq!(metric(&metrics, "events"), 0);
+        assert_eq!(metric(&metrics, "rejected"), 0);
+
+        let last = pull(&mut driver, 20);
+        let metrics = driver.get_metrics().unwrap();
+        assert_eq!(metric(&metrics, "events"), 20);
+        // nothing is out of range
+        assert_eq!(metric(&metrics, "rejected"), 0);
+        assert_eq!(metric(&metrics, "last_value"), last);
+
+        // the counts only ever go up, while the last value can go either way
+        assert_eq!(find(&metrics, "events").metric_type, MetricType::Counter);
+        assert_eq!(find(&metrics, "rejected").metric_type, MetricType::Counter);
+        assert_eq!(find(&metrics, "last_value").metric_type, MetricType::Gauge);
+    }
+
+    #[test]
+    fn count_rejections() {
+        let mut driver = start(cr#"{"range": 10, "max": 4}"#);
+
+        let last = pull(&mut driver, 100);
+        assert!(last <= 4);
+
+        // half the numbers are out of range, so having none at all is practically impossible
+        let metrics = driver.get_metrics().unwrap();
+        assert_eq!(metric(&metrics, "events"), 100);
+        assert!(metric(&metrics, "rejected") > 0);
+        assert_eq!(metric(&metrics, "last_value"), last);
+    }
+}
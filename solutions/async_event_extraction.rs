@@ -0,0 +1,308 @@
+use falco_plugin::anyhow::{anyhow, Error};
+use falco_plugin::async_event::{AsyncEvent, AsyncEventPlugin, AsyncHandler};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType::ASYNCEVENT_E;
+use falco_plugin::event::events::types::{EventType, PPME_ASYNCEVENT_E};
+use falco_plugin::event::events::{Event, EventMetadata};
+use falco_plugin::extract::{field, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::serde::Deserialize;
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::{CStr, CString};
+
+//
+// INTRO
+// The scope of this exercise is to extract fields from async events. You already know
+// how to send them (check the `async_events_plugin` exercise); this time the same
+// sender emits an event with a JSON payload, and you write an extract plugin
+// that lets Falco rules look inside it.
+//
+// DOCS:
+// * https://falcosecurity.github.io/plugin-sdk-rs/falco_event/events/types/struct.PPME_ASYNCEVENT_E.html
+// * https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/extract/trait.ExtractPlugin.html
+//
+
+// Have some static data to check in the test
+const TEST_EVENT_NAME_C_STR: &CStr = c"async";
+const TEST_EVENT_NAME: &str = "async";
+const TEST_DATA: &[u8] = br#"{"message": "hello world", "count": 3}"#;
+const OTHER_EVENT_NAME_C_STR: &CStr = c"other";
+const OTHER_EVENT_NAME: &str = "other";
+const OTHER_DATA: &[u8] = b"not\0json";
+
+// The sender, just like in the `async_events_plugin` exercise (except for the payload)
+struct AsyncSenderPlugin;
+
+impl Plugin for AsyncSenderPlugin {
+    const NAME: &'static CStr = c"async_sender";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"sends an async event with a JSON payload";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl AsyncEventPlugin for AsyncSenderPlugin {
+    const ASYNC_EVENTS: &'static [&'static str] = &[TEST_EVENT_NAME];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+
+    fn start_async(&mut self, handler: AsyncHandler) -> Result<(), Error> {
+        let event = Event {
+            metadata: EventMetadata::default(),
+            params: AsyncEvent {
+                plugin_id: None,
+                name: Some(TEST_EVENT_NAME_C_STR),
+                data: Some(TEST_DATA),
+            },
+        };
+        handler.emit(event)?;
+        Ok(())
+    }
+
+    fn stop_async(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(ASYNC_SENDER_PLUGIN = AsyncSenderPlugin);
+
+// Another sender, whose events have a different name and a payload that isn't even text
+struct OtherSenderPlugin;
+
+impl Plugin for OtherSenderPlugin {
+    const NAME: &'static CStr = c"other_sender";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"sends an async event with a binary payload";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl AsyncEventPlugin for OtherSenderPlugin {
+    const ASYNC_EVENTS: &'static [&'static str] = &[OTHER_EVENT_NAME];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+
+    fn start_async(&mut self, handler: AsyncHandler) -> Result<(), Error> {
+        let event = Event {
+            metadata: EventMetadata::default(),
+            params: AsyncEvent {
+                plugin_id: None,
+                name: Some(OTHER_EVENT_NAME_C_STR),
+                data: Some(OTHER_DATA),
+            },
+        };
+        handler.emit(event)?;
+        Ok(())
+    }
+
+    fn stop_async(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(OTHER_SENDER_PLUGIN = OtherSenderPlugin);
+
+/// The JSON payload of our async events
+#[derive(Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+struct Payload {
+    message: String,
+    count: u64,
+}
+
+struct AsyncExtractPlugin;
+
+impl Plugin for AsyncExtractPlugin {
+    const NAME: &'static CStr = c"async_extract";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"extracts fields from async events";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl AsyncExtractPlugin {
+    // Async events are all PPME_ASYNCEVENT_E, with the name and data (and the ID of
+    // the plugin that sent them) as parameters
+    fn load_async<'a>(
+        req: &'a ExtractRequest<Self>,
+    ) -> Result<Event<PPME_ASYNCEVENT_E<'a>>, Error> {
+        Ok(req.event.event()?.load::<PPME_ASYNCEVENT_E>()?)
+    }
+
+    fn extract_name(&mut self, req: ExtractRequest<Self>) -> Result<CString, Error> {
+        let event = Self::load_async(&req)?;
+        let name = event.params.name.ok_or_else(|| anyhow!("no event name"))?;
+        Ok(name.to_owned())
+    }
+
+    fn extract_data(&mut self, req: ExtractRequest<Self>) -> Result<CString, Error> {
+        let event = Self::load_async(&req)?;
+        let data = event.params.data.ok_or_else(|| anyhow!("no event data"))?;
+        // the data can be any bytes, but a C string can't hold NUL bytes, so escape them
+        let data = data
+            .split(|&b| b == 0)
+            .collect::<Vec<_>>()
+            .join(&b"\\0"[..]);
+        Ok(CString::new(data)?)
+    }
+
+    // Decoding the payload (rather than just passing it on) lets rules match
+    // on individual values, with the right types
+    fn decode_payload(req: &ExtractRequest<Self>) -> Result<Payload, Error> {
+        let event = Self::load_async(req)?;
+        let data = event.params.data.ok_or_else(|| anyhow!("no event data"))?;
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    // Only our own events carry the JSON payload, the fields have no value for others
+    fn is_ours(req: &ExtractRequest<Self>) -> Result<bool, Error> {
+        Ok(Self::load_async(req)?.params.name == Some(TEST_EVENT_NAME_C_STR))
+    }
+
+    fn extract_message(&mut self, req: ExtractRequest<Self>) -> Result<Option<CString>, Error> {
+        if !Self::is_ours(&req)? {
+            return Ok(None);
+        }
+        Ok(Some(CString::new(Self::decode_payload(&req)?.message)?))
+    }
+
+    fn extract_count(&mut self, req: ExtractRequest<Self>) -> Result<Option<u64>, Error> {
+        if !Self::is_ours(&req)? {
+            return Ok(None);
+        }
+        Ok(Some(Self::decode_payload(&req)?.count))
+    }
+}
+
+impl ExtractPlugin for AsyncExtractPlugin {
+    // Only async events, from any source
+    const EVENT_TYPES: &'static [EventType] = &[ASYNCEVENT_E];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] = &[
+        field("async.name", &Self::extract_name),
+        field("async.data", &Self::extract_data),
+        field("async.message", &Self::extract_message),
+        field("async.count", &Self::extract_count),
+    ];
+}
+
+static_plugin!(ASYNC_EXTRACT_PLUGIN = AsyncExtractPlugin);
+
+fn main() {
+    // just needed to build the exercise
+}
+
+mod tests {
+    use exercises::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use exercises::{CapturingTestDriver, TestDriver};
+    use std::ffi::CStr;
+    use std::time::Duration;
+
+    fn start() -> NativeCapturingTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&super::ASYNC_SENDER_PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&super::ASYNC_EXTRACT_PLUGIN, c"")
+            .unwrap();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    fn extract(
+        driver: &mut NativeCapturingTestDriver,
+        evt: &falco_plugin_runner::Event,
+        field: &CStr,
+    ) -> Option<String> {
+        driver.event_field_as_string(field, evt).unwrap()
+    }
+
+    #[test]
+    fn name_and_data() {
+        let mut driver = start();
+        let evt = driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.name").as_deref(),
+            Some("async")
+        );
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.data").as_deref(),
+            Some(r#"{"message": "hello world", "count": 3}"#)
+        );
+    }
+
+    #[test]
+    fn json_payload() {
+        let mut driver = start();
+        let evt = driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.message").as_deref(),
+            Some("hello world")
+        );
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.count").as_deref(),
+            Some("3")
+        );
+
+        // the plugin was only ever asked about async events
+        assert_eq!(driver.filter_violations(), vec![]);
+    }
+
+    #[test]
+    fn other_sender() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&super::ASYNC_SENDER_PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&super::OTHER_SENDER_PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&super::ASYNC_EXTRACT_PLUGIN, c"")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let evt = driver
+            .wait_for_async_event("other", Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.name").as_deref(),
+            Some("other")
+        );
+        // NUL bytes come out escaped
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.data").as_deref(),
+            Some("not\\0json")
+        );
+        // not our payload, so no message or count
+        assert_eq!(extract(&mut driver, &evt, c"async.message"), None);
+        assert_eq!(extract(&mut driver, &evt, c"async.count"), None);
+
+        // our own events are still decoded
+        let evt = driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(
+            extract(&mut driver, &evt, c"async.count").as_deref(),
+            Some("3")
+        );
+        assert_eq!(driver.filter_violations(), vec![]);
+    }
+}
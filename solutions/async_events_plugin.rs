@@ -76,15 +76,14 @@ fn main() {
     // just needed to build the exercise
 }
 
-// These are the tests your plugin needs to pass. For now, we have just one: it should
-// successfully load into the test harness (emulating Falco plugin API) and get a valid event
+// This is the test your plugin needs to pass: it should successfully load into the test
+// harness (emulating Falco plugin API) and get a valid event
 mod tests {
     use crate::{TEST_DATA, TEST_EVENT_NAME_C_STR};
 
     use exercises::native::NativeTestDriver;
-    use exercises::{CapturingTestDriver, TestDriver};
+    use exercises::{CapturingTestDriver, EventExt, TestDriver};
     use falco_plugin::event::events::types::PPME_ASYNCEVENT_E;
-    use falco_plugin::event::events::RawEvent;
 
     #[test]
     fn get_event() {
@@ -97,12 +96,8 @@ mod tests {
         let next = driver.next_event();
         assert!(next.is_ok());
 
-        // NativeTestDriver doesn't have much features, in order to be
-        // as much indepentent as possible from the SDK, so here we
-        // manually convert the falco_plugin_runner::event::Event to
-        // falco_event::events::event
-        let evt = next.unwrap().to_event_input();
-        let evt = unsafe { RawEvent::from_ptr(evt.evt as *const _) }.unwrap();
+        // Parse the event we got from the driver as an async event
+        let evt = next.unwrap();
         let evt = evt.load::<PPME_ASYNCEVENT_E>();
         assert!(evt.is_ok());
         // Check the fields we set in the event
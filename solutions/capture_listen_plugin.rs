@@ -0,0 +1,210 @@
+use falco_plugin::anyhow::{anyhow, Error};
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::listen::{CaptureListenInput, CaptureListenPlugin};
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::CStr;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+//
+// INTRO
+// The scope of this exercise is to introduce you the capture listen capability.
+// Plugins with this capability get notified when the capture starts and stops, which
+// is the right time to start and stop any background work, like a thread refreshing
+// a cache. You may want to check the SDK documentation at
+// https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/listen/index.html
+// and the proper section of the Falco documentation at
+// https://falco.org/docs/plugins/architecture/#capture-listening-capability
+//
+
+/// Everything our plugin does, as recorded in the journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lifecycle {
+    CaptureOpen,
+    WorkerStarted,
+    Refresh,
+    WorkerStopped,
+    CaptureClose,
+}
+
+// The plugin records what it does in this journal, under its configured name,
+// so that the tests can check it (every test uses a different name)
+static JOURNAL: Mutex<Vec<(String, Lifecycle)>> = Mutex::new(Vec::new());
+
+fn record(name: &str, what: Lifecycle) {
+    JOURNAL.lock().unwrap().push((name.to_string(), what));
+}
+
+fn journal(name: &str) -> Vec<Lifecycle> {
+    JOURNAL
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(n, _)| n == name)
+        .map(|(_, what)| *what)
+        .collect()
+}
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+struct Config {
+    /// The name to record our actions under
+    name: String,
+    /// How often to refresh the cache
+    interval_ms: u64,
+}
+
+/// A running background worker
+struct Worker {
+    /// Dropping (or sending to) this wakes the worker up and makes it exit
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+struct CacheRefreshPlugin {
+    name: String,
+    interval: Duration,
+    worker: Option<Worker>,
+}
+
+impl Plugin for CacheRefreshPlugin {
+    const NAME: &'static CStr = c"cache_refresh";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"refreshes a cache in the background while capturing";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<Config>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self {
+            name: config.name,
+            interval: Duration::from_millis(config.interval_ms),
+            worker: None,
+        })
+    }
+}
+
+// Implement the capture listen capability
+//
+// `capture_open` is called once the capture starts, and `capture_close` when it stops
+// (e.g. when Falco shuts down). Once `capture_close` returns, the plugin must not do
+// anything anymore, so it has to wait for its background threads to finish.
+//
+// DOCS: https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/listen/trait.CaptureListenPlugin.html
+impl CaptureListenPlugin for CacheRefreshPlugin {
+    fn capture_open(&mut self, _listen_input: &CaptureListenInput) -> Result<(), Error> {
+        record(&self.name, Lifecycle::CaptureOpen);
+
+        let (stop, stop_rx) = channel();
+        let name = self.name.clone();
+        let interval = self.interval;
+        let thread = std::thread::spawn(move || {
+            record(&name, Lifecycle::WorkerStarted);
+            loop {
+                // waiting on the channel (rather than sleeping) lets us stop right away
+                match stop_rx.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => record(&name, Lifecycle::Refresh),
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            record(&name, Lifecycle::WorkerStopped);
+        });
+
+        self.worker = Some(Worker { stop, thread });
+        Ok(())
+    }
+
+    fn capture_close(&mut self, _listen_input: &CaptureListenInput) -> Result<(), Error> {
+        if let Some(worker) = self.worker.take() {
+            // the worker may have exited already, so ignore send errors
+            let _ = worker.stop.send(());
+            worker
+                .thread
+                .join()
+                .map_err(|_| anyhow!("background worker panicked"))?;
+        }
+
+        record(&self.name, Lifecycle::CaptureClose);
+        Ok(())
+    }
+}
+
+static_plugin!(CACHE_REFRESH_PLUGIN = CacheRefreshPlugin);
+
+fn main() {
+    // just needed to build the exercise
+}
+
+mod tests {
+    use super::{journal, Lifecycle};
+    use exercises::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use exercises::trace::{ApiCall, TraceExt};
+    use exercises::TestDriver;
+    use std::ffi::CString;
+    use std::time::{Duration, Instant};
+
+    fn start(name: &str) -> NativeCapturingTestDriver {
+        let config = format!(r#"{{"name": "{}", "interval_ms": 10}}"#, name);
+        let config = CString::new(config).unwrap();
+
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.enable_tracing();
+        driver
+            .register_plugin(&super::CACHE_REFRESH_PLUGIN, &config)
+            .unwrap();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    fn wait_for_refresh(name: &str) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !journal(name).contains(&Lifecycle::Refresh) {
+            assert!(Instant::now() < deadline, "the cache was never refreshed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn start_and_stop() {
+        let driver = start("start_and_stop");
+        wait_for_refresh("start_and_stop");
+        let closed = driver.close();
+
+        let journal = journal("start_and_stop");
+        assert_eq!(
+            journal[..2],
+            [Lifecycle::CaptureOpen, Lifecycle::WorkerStarted]
+        );
+        assert_eq!(
+            journal[journal.len() - 2..],
+            [Lifecycle::WorkerStopped, Lifecycle::CaptureClose]
+        );
+        assert!(journal[2..journal.len() - 2]
+            .iter()
+            .all(|what| *what == Lifecycle::Refresh));
+
+        let trace = closed.trace();
+        let calls = trace.calls_to("cache_refresh");
+        let open = calls.iter().position(|c| **c == ApiCall::CaptureOpen);
+        let close = calls.iter().position(|c| **c == ApiCall::CaptureClose);
+        assert!(open.is_some() && close.is_some() && open < close);
+    }
+
+    #[test]
+    fn nothing_runs_after_close() {
+        let driver = start("nothing_runs_after_close");
+        wait_for_refresh("nothing_runs_after_close");
+        driver.close();
+
+        let closed = journal("nothing_runs_after_close");
+        assert_eq!(closed.last(), Some(&Lifecycle::CaptureClose));
+
+        // give a leftover worker plenty of time to refresh again
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(journal("nothing_runs_after_close"), closed);
+    }
+}
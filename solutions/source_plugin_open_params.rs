@@ -0,0 +1,194 @@
+use falco_plugin::anyhow::{anyhow, Error};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::PPME_PLUGINEVENT_E;
+use falco_plugin::extract::EventInput;
+use falco_plugin::source::{EventBatch, SourcePlugin, SourcePluginInstance};
+use falco_plugin::strings::CStringWriter;
+use falco_plugin::tables::TablesInput;
+use falco_plugin::{static_plugin, FailureReason};
+use serde_json::json;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+use std::ops::Range;
+
+//
+// INTRO
+// The scope of this exercise is to introduce you the open parameters of source plugins.
+// When Falco opens a source plugin, it passes it a string from its configuration
+// (`open_params` in falco.yaml), so the same plugin can e.g. read from different files
+// or connect to different servers. To help users, plugins can list the values
+// they accept (`list_open_params`).
+//
+// DOCS: https://falcosecurity.github.io/plugin-sdk-rs/falco_plugin/source/trait.SourcePlugin.html
+//
+
+/// The ranges our plugin can count through, by name
+const RANGES: &[(&str, Range<u64>)] = &[("small", 0..10), ("large", 1000..1010)];
+
+/// The range used when no open parameters are given
+const DEFAULT_RANGE: &str = "small";
+
+struct CounterPlugin {
+    /// The JSON list of `RANGES` returned by `list_open_params`
+    open_params: CString,
+}
+
+impl Plugin for CounterPlugin {
+    const NAME: &'static CStr = c"range_counter";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.0";
+    const DESCRIPTION: &'static CStr = c"counts through a range of numbers, chosen when opened";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        let params: Vec<_> = RANGES
+            .iter()
+            .map(|(name, range)| {
+                json!({
+                    "value": name,
+                    "desc": format!("count from {} to {}", range.start, range.end - 1),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            open_params: CString::new(serde_json::to_string(&params)?)?,
+        })
+    }
+}
+
+/// Counts through the range chosen when opening the plugin
+struct CounterInstance(Range<u64>);
+
+impl SourcePluginInstance for CounterInstance {
+    type Plugin = CounterPlugin;
+
+    fn next_batch(
+        &mut self,
+        _plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        match self.0.next() {
+            Some(num) => {
+                let event = Self::plugin_event(&num.to_le_bytes());
+                batch.add(event)?;
+                Ok(())
+            }
+            None => Err(FailureReason::Eof)?,
+        }
+    }
+}
+
+impl SourcePlugin for CounterPlugin {
+    type Instance = CounterInstance;
+    const EVENT_SOURCE: &'static CStr = c"range_counter";
+    const PLUGIN_ID: u32 = 1112;
+
+    // Advertise the values `open` accepts, as a JSON list of objects with a `value`
+    // and a `desc` (description)
+    fn list_open_params(&mut self) -> Result<&CStr, Error> {
+        Ok(self.open_params.as_c_str())
+    }
+
+    // The open parameters choose the range to count through; Falco passes `None`
+    // (or an empty string) when they're not configured
+    fn open(&mut self, params: Option<&str>) -> Result<Self::Instance, Error> {
+        let name = match params {
+            None | Some("") => DEFAULT_RANGE,
+            Some(name) => name,
+        };
+
+        let (_, range) = RANGES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .ok_or_else(|| anyhow!("unknown range {:?}", name))?;
+        Ok(CounterInstance(range.clone()))
+    }
+
+    fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
+        let event = event.event()?;
+        let event = event.load::<PPME_PLUGINEVENT_E>()?;
+        let data = event
+            .params
+            .event_data
+            .ok_or_else(|| anyhow!("no event data"))?;
+
+        let mut writer = CStringWriter::default();
+        write!(writer, "{}", u64::from_le_bytes(data.try_into()?))?;
+        Ok(writer.into_cstring())
+    }
+}
+
+static_plugin!(COUNTER_PLUGIN = CounterPlugin);
+
+fn main() {
+    // just needed to build the exercise
+}
+
+mod tests {
+    use exercises::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use exercises::trace::ApiCall;
+    use exercises::{CapturingTestDriver, EventExt, ScapStatus, TestDriver};
+    use falco_plugin::event::events::types::PPME_PLUGINEVENT_E;
+    use std::ffi::CStr;
+
+    fn driver() -> NativeTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.enable_tracing();
+        driver.register_plugin(&super::COUNTER_PLUGIN, c"").unwrap();
+        driver
+    }
+
+    fn start(params: &CStr) -> NativeCapturingTestDriver {
+        driver().start_capture(c"", params).unwrap()
+    }
+
+    /// All the numbers until the end of the capture
+    fn numbers(driver: &mut NativeCapturingTestDriver) -> Vec<u64> {
+        let mut numbers = Vec::new();
+        loop {
+            match driver.next_event() {
+                Ok(evt) => {
+                    let evt = evt.load::<PPME_PLUGINEVENT_E>().unwrap();
+                    let data = evt.params.event_data.unwrap();
+                    numbers.push(u64::from_le_bytes(data.try_into().unwrap()));
+                }
+                Err(ScapStatus::Eof) => return numbers,
+                Err(e) => panic!("capture failed: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn list_open_params() {
+        let params = driver().list_open_params("range_counter").unwrap();
+        let values: Vec<_> = params.iter().map(|p| p.value.as_str()).collect();
+        assert_eq!(values, vec!["small", "large"]);
+        assert!(params.iter().all(|p| !p.desc.is_empty()));
+    }
+
+    #[test]
+    fn select_range() {
+        let mut driver = start(c"large");
+        assert_eq!(numbers(&mut driver), (1000..1010).collect::<Vec<_>>());
+
+        // the parameters made it all the way to the plugin
+        let opened = driver.trace().into_iter().find_map(|r| match r.call {
+            ApiCall::Open { params } => Some(params),
+            _ => None,
+        });
+        assert_eq!(opened, Some(Some(String::from("large"))));
+    }
+
+    #[test]
+    fn default_range() {
+        let mut driver = start(c"");
+        assert_eq!(numbers(&mut driver), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unknown_range() {
+        // the plugin is opened when the capture starts, so that's what fails
+        assert!(driver().start_capture(c"", c"huge").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Table access reports
+//!
+//! The table inspector is a plugin of its own, so it sees exported tables exactly like any
+//! other plugin importing them would. While parsing the first event of a capture, it tries
+//! to read and write every field of every table, on a freshly created entry that never gets
+//! added to the table (so the probe has no effect on the plugin state). Writes store
+//! the value that was just read back. Fields of types the inspector can't handle (like
+//! nested tables) are reported as [`FieldAccess::Unprobed`], along with whether the table
+//! declares them read-only.
+//!
+//! Private fields are not advertised by the plugin API at all, so the only way to tell
+//! they're hidden is to ask for them by name, e.g. with [`TableAccessReport::access`].
+
+use crate::tables::StateType;
+
+/// What another plugin can do with a table field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldAccess {
+    /// The field can be read and written
+    Writable,
+    /// The field can be read, but writes fail
+    ReadOnly,
+    /// The field is listed, but reading it fails
+    Unreadable,
+    /// The field is not visible to other plugins
+    Hidden,
+    /// The field is listed, but its type (like a nested table) can't be probed
+    Unprobed,
+}
+
+/// The access check result for a single field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldAccessReport {
+    pub name: String,
+    pub field_type: StateType,
+    /// Whether the table advertises the field as read-only
+    pub declared_read_only: bool,
+    pub access: FieldAccess,
+}
+
+/// The access check results for all the fields of a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableAccessReport {
+    pub table: String,
+    pub fields: Vec<FieldAccessReport>,
+}
+
+impl TableAccessReport {
+    /// The access to a field, by name ([`FieldAccess::Hidden`] if the table doesn't list it)
+    pub fn access(&self, field: &str) -> FieldAccess {
+        self.fields
+            .iter()
+            .find(|f| f.name == field)
+            .map(|f| f.access)
+            .unwrap_or(FieldAccess::Hidden)
+    }
+
+    /// The names of all the fields other plugins can read
+    ///
+    /// Unprobed fields are left out, since reading them wasn't tried.
+    pub fn visible(&self) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|f| matches!(f.access, FieldAccess::Writable | FieldAccess::ReadOnly))
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+
+    /// The names of all the fields other plugins can modify
+    pub fn writable(&self) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|f| f.access == FieldAccess::Writable)
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+
+    /// Fields advertised as read-only that could still be written to
+    pub fn read_only_violations(&self) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|f| f.declared_read_only && f.access == FieldAccess::Writable)
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FieldAccess;
+    use crate::native::NativeTestDriver;
+    use crate::replay_source_plugin::{self, process_lifecycle};
+    use crate::{histogram_plugin, syscall_source_plugin, table_access_plugin};
+    use crate::{CapturingTestDriver, TestDriver};
+
+    #[test]
+    fn field_visibility() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&table_access_plugin::PLUGIN, c"")
+            .unwrap();
+        driver.enable_table_inspection();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+        driver.next_event().unwrap();
+
+        let report = driver.table_access("access").unwrap();
+        assert_eq!(report.access("public"), FieldAccess::Writable);
+        assert_eq!(report.access("readonly"), FieldAccess::ReadOnly);
+        assert_eq!(report.access("private"), FieldAccess::Hidden);
+        assert_eq!(report.access("nested"), FieldAccess::Unprobed);
+
+        let nested = report.fields.iter().find(|f| f.name == "nested").unwrap();
+        assert!(nested.declared_read_only);
+
+        assert_eq!(report.visible(), vec!["public", "readonly"]);
+        assert_eq!(report.writable(), vec!["public"]);
+        assert!(report.read_only_violations().is_empty());
+    }
+
+    #[test]
+    fn public_fields() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let config = replay_source_plugin::config(&process_lifecycle());
+        driver
+            .register_plugin(&replay_source_plugin::PLUGIN, &config)
+            .unwrap();
+        driver
+            .register_plugin(&histogram_plugin::PLUGIN, c"")
+            .unwrap();
+        driver.enable_table_inspection();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+        driver.next_event().unwrap();
+
+        // both fields are `Public`, so other plugins can read and modify them
+        let report = driver.table_access("histogram").unwrap();
+        assert_eq!(report.access("number"), FieldAccess::Writable);
+        assert_eq!(report.access("count"), FieldAccess::Writable);
+        assert_eq!(report.visible(), report.writable());
+        assert!(report.read_only_violations().is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! An async event plugin emitting the events from its config, for the harness's own tests
+//!
+//! All the events are emitted from `start_async`, in order, even if some of them get
+//! rejected. The plugin declares a single event name (`async`), and no event sources,
+//! so it may emit into any source. [`OTHER_SOURCE_PLUGIN`] is the same plugin, declaring
+//! a source called `other` instead.
+
+use falco_plugin::anyhow::Error;
+use falco_plugin::api::{plugin_api, plugin_api__bindgen_ty_4};
+use falco_plugin::async_event::{AsyncEvent, AsyncEventPlugin, AsyncHandler};
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::{Event, EventMetadata};
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::{c_char, CStr, CString};
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+pub(crate) struct AsyncEmission {
+    name: String,
+    data: String,
+}
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+pub(crate) struct AsyncEmitterConfig {
+    /// The events to emit; a single `async` event with `hello world` as data by default
+    #[serde(default = "default_events")]
+    events: Vec<AsyncEmission>,
+}
+
+fn default_events() -> Vec<AsyncEmission> {
+    vec![AsyncEmission {
+        name: String::from("async"),
+        data: String::from("hello world"),
+    }]
+}
+
+struct AsyncEmitterPlugin(Vec<(CString, Vec<u8>)>);
+
+impl Plugin for AsyncEmitterPlugin {
+    const NAME: &'static CStr = c"async-emitter";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Emits async events from its config.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<AsyncEmitterConfig>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        let events = config
+            .events
+            .into_iter()
+            .map(|e| Ok((CString::new(e.name)?, e.data.into_bytes())))
+            .collect::<Result<_, Error>>()?;
+        Ok(Self(events))
+    }
+}
+
+impl AsyncEventPlugin for AsyncEmitterPlugin {
+    const ASYNC_EVENTS: &'static [&'static str] = &["async"];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+
+    fn start_async(&mut self, handler: AsyncHandler) -> Result<(), Error> {
+        for (name, data) in &self.0 {
+            let event = Event {
+                metadata: EventMetadata::default(),
+                params: AsyncEvent {
+                    plugin_id: None,
+                    name: Some(name.as_c_str()),
+                    data: Some(data.as_slice()),
+                },
+            };
+            if let Err(e) = handler.emit(event) {
+                log::warn!("failed to emit {:?}: {}", name, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_async(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(ASYNC_EMITTER_PLUGIN = AsyncEmitterPlugin);
+
+/// Emits the async events from its config (see [`AsyncEmitterConfig`])
+pub(crate) static PLUGIN: plugin_api = ASYNC_EMITTER_PLUGIN;
+
+unsafe extern "C" fn other_source() -> *const c_char {
+    c"[\"other\"]".as_ptr()
+}
+
+/// Same as [`PLUGIN`], but declaring `other` as the only source it emits events into
+pub(crate) static OTHER_SOURCE_PLUGIN: plugin_api = plugin_api {
+    __bindgen_anon_4: plugin_api__bindgen_ty_4 {
+        get_async_event_sources: Some(other_source),
+        ..ASYNC_EMITTER_PLUGIN.__bindgen_anon_4
+    },
+    ..ASYNC_EMITTER_PLUGIN
+};
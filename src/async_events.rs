@@ -0,0 +1,168 @@
+//! Async event checks
+//!
+//! Async event plugins declare the event names they emit (`ASYNC_EVENTS`) and the sources
+//! they emit them into (`EVENT_SOURCES`). Falco rejects events with undeclared names,
+//! so the native driver does the same, and checks that every event reaching the capture:
+//!
+//! * ends up in one of the declared sources (if any were declared),
+//! * carries the plugin ID it was emitted with (0 if emitted with `plugin_id: None`).
+//!
+//! Problems are reported as [`AsyncViolation`]s.
+
+/// What went wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsyncViolationKind {
+    /// The event name is not in the plugin's `ASYNC_EVENTS` (the event was rejected)
+    UndeclaredName,
+    /// The event could not be parsed as an async event (the event was rejected)
+    Malformed,
+    /// The event reached a source the plugin didn't declare
+    WrongSource { source: Option<String> },
+    /// The event reached the capture with a different plugin ID
+    PluginIdMismatch {
+        emitted: Option<u32>,
+        delivered: Option<u32>,
+    },
+}
+
+/// An async event breaking the plugin's declarations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncViolation {
+    pub plugin: String,
+    /// The event name, if the event had one
+    pub name: Option<String>,
+    pub kind: AsyncViolationKind,
+}
+
+/// What a plugin declared about its async events
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AsyncDeclarations {
+    pub(crate) names: Vec<String>,
+    pub(crate) sources: Vec<String>,
+}
+
+/// An async event handed to the runner, to be checked when it comes out of the capture
+#[derive(Debug, Clone)]
+pub(crate) struct ExpectedAsyncEvent {
+    pub(crate) plugin: String,
+    pub(crate) name: String,
+    pub(crate) plugin_id: Option<u32>,
+    pub(crate) sources: Vec<String>,
+}
+
+impl ExpectedAsyncEvent {
+    /// Compare the event as it came out of the capture with what was emitted
+    pub(crate) fn check(
+        &self,
+        plugin_id: Option<u32>,
+        source: Option<&str>,
+    ) -> Vec<AsyncViolation> {
+        let mut violations = Vec::new();
+        let mut violation = |kind| {
+            violations.push(AsyncViolation {
+                plugin: self.plugin.clone(),
+                name: Some(self.name.clone()),
+                kind,
+            })
+        };
+
+        if plugin_id != Some(self.plugin_id.unwrap_or(0)) {
+            violation(AsyncViolationKind::PluginIdMismatch {
+                emitted: self.plugin_id,
+                delivered: plugin_id,
+            });
+        }
+
+        let source_ok = self.sources.is_empty()
+            || source.is_some_and(|source| self.sources.iter().any(|s| s == source));
+        if !source_ok {
+            violation(AsyncViolationKind::WrongSource {
+                source: source.map(String::from),
+            });
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncViolation, AsyncViolationKind};
+    use crate::native::NativeTestDriver;
+    use crate::TestDriver;
+    use crate::{async_emitter_plugin, syscall_source_plugin};
+    use std::time::Duration;
+
+    #[test]
+    fn declared_event() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&async_emitter_plugin::PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+
+        // the event matches the plugin's declarations (name and sources), and the
+        // `plugin_id: None` it was emitted with became 0
+        assert_eq!(driver.async_violations(), vec![]);
+    }
+
+    #[test]
+    fn undeclared_name() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let config = cr#"{"events": [
+            {"name": "undeclared", "data": "rejected"},
+            {"name": "async", "data": "accepted"}
+        ]}"#;
+        driver
+            .register_plugin(&async_emitter_plugin::PLUGIN, config)
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let timeout = Duration::from_secs(5);
+        driver.wait_for_async_event("async", timeout).unwrap();
+
+        // the undeclared event was rejected, so it never reached the capture
+        assert!(driver.drain_async_events(timeout).unwrap().is_empty());
+        assert_eq!(
+            driver.async_violations(),
+            vec![AsyncViolation {
+                plugin: String::from("async-emitter"),
+                name: Some(String::from("undeclared")),
+                kind: AsyncViolationKind::UndeclaredName,
+            }]
+        );
+    }
+
+    #[test]
+    fn wrong_source() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&async_emitter_plugin::OTHER_SOURCE_PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+
+        // the plugin only declared the `other` source, which is not part of the capture
+        let violations = driver.async_violations();
+        assert_eq!(violations.len(), 1, "{:?}", violations);
+        assert_eq!(violations[0].name.as_deref(), Some("async"));
+        assert!(
+            matches!(
+                &violations[0].kind,
+                AsyncViolationKind::WrongSource { source } if source.as_deref() != Some("other")
+            ),
+            "{:?}",
+            violations
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::prometheus::MetricType;
 use falco_plugin::anyhow;
 use falco_plugin::anyhow::Context;
 pub use falco_plugin_runner::ScapStatus;
@@ -13,6 +14,7 @@ pub struct Api(pub falco_plugin::api::plugin_api);
 
 pub struct SinspMetric {
     pub name: String,
+    pub metric_type: MetricType,
     pub value: u64, // TODO: this is... taking shortcuts
 }
 
@@ -66,4 +68,35 @@ pub trait CapturingTestDriver {
         };
         self.event_field_as_string(c"evt.plugininfo", &event)
     }
+
+    /// Render an event the way `sysdig` and Falco do, i.e. using the
+    /// `%evt.num %evt.outputtime %evt.cpu %proc.name (%thread.tid) %evt.dir %evt.type %evt.args`
+    /// format, with `<NA>` in place of missing values
+    ///
+    /// Plugin events carry an opaque payload, so their arguments are rendered
+    /// by the owning plugin's `event_to_string` (via `evt.plugininfo`) instead.
+    fn format_event(&mut self, event: &Self::Event) -> anyhow::Result<String> {
+        let mut field = |name: &CStr| -> anyhow::Result<String> {
+            Ok(self
+                .event_field_as_string(name, event)?
+                .unwrap_or_else(|| String::from("<NA>")))
+        };
+
+        let num = field(c"evt.num")?;
+        let time = field(c"evt.outputtime")?;
+        let cpu = field(c"evt.cpu")?;
+        let proc_name = field(c"proc.name")?;
+        let tid = field(c"thread.tid")?;
+        let dir = field(c"evt.dir")?;
+        let evt_type = field(c"evt.type")?;
+        let args = match evt_type.as_str() {
+            "pluginevent" => field(c"evt.plugininfo")?,
+            _ => field(c"evt.args")?,
+        };
+
+        Ok(format!(
+            "{} {} {} {} ({}) {} {} {}",
+            num, time, cpu, proc_name, tid, dir, evt_type, args
+        ))
+    }
 }
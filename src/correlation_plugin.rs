@@ -0,0 +1,130 @@
+//! A `tick` source and a plugin correlating its events with `syscall` ones, for the
+//! harness's own tests
+//!
+//! [`TICK_SOURCE`] emits a plugin event at each of [`TICKS`]. [`PLUGIN`] parses events
+//! of both sources and serves `correlation.ticks`, the number of ticks it parsed so far,
+//! which only goes up if it shares a capture with the `tick` source.
+
+use crate::replay_source_plugin::{event, u32_param};
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::{field, EventInput, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::source::{EventBatch, SourcePlugin, SourcePluginInstance};
+use falco_plugin::tables::TablesInput;
+use falco_plugin::{static_plugin, FailureReason};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+
+/// The timestamps of the tick events
+pub(crate) const TICKS: [u64; 3] = [2, 5, 8];
+
+const TICK_PLUGIN_ID: u32 = 2999;
+
+struct TickSourcePlugin;
+
+impl Plugin for TickSourcePlugin {
+    const NAME: &'static CStr = c"tick";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Emits a few plugin events at fixed timestamps.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl SourcePlugin for TickSourcePlugin {
+    type Instance = TickSourcePluginInstance;
+    const EVENT_SOURCE: &'static CStr = c"tick";
+    const PLUGIN_ID: u32 = TICK_PLUGIN_ID;
+
+    fn open(&mut self, _params: Option<&str>) -> Result<Self::Instance, Error> {
+        let ticks = TICKS
+            .iter()
+            .map(|&ts| {
+                let params = [u32_param(TICK_PLUGIN_ID), ts.to_le_bytes().to_vec()];
+                event(ts, 0, EventType::PLUGINEVENT_E, &params)
+            })
+            .collect();
+        Ok(TickSourcePluginInstance(ticks))
+    }
+
+    fn event_to_string(&mut self, _event: &EventInput) -> Result<CString, Error> {
+        Ok(CString::from(c"tick"))
+    }
+}
+
+struct TickSourcePluginInstance(VecDeque<Vec<u8>>);
+
+impl SourcePluginInstance for TickSourcePluginInstance {
+    type Plugin = TickSourcePlugin;
+
+    fn next_batch(
+        &mut self,
+        _plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        match self.0.pop_front() {
+            Some(event) => {
+                batch.add(&*event)?;
+                Ok(())
+            }
+            None => Err(FailureReason::Eof)?,
+        }
+    }
+}
+
+static_plugin!(TICK_SOURCE_PLUGIN = TickSourcePlugin);
+
+/// The `tick` source (see the module docs)
+pub(crate) static TICK_SOURCE: falco_plugin::api::plugin_api = TICK_SOURCE_PLUGIN;
+
+struct CorrelationPlugin {
+    ticks: u64,
+}
+
+impl Plugin for CorrelationPlugin {
+    const NAME: &'static CStr = c"correlation";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Counts the ticks seen by syscall events.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self { ticks: 0 })
+    }
+}
+
+impl CorrelationPlugin {
+    fn extract_ticks(&mut self, _req: ExtractRequest<Self>) -> Result<u64, Error> {
+        Ok(self.ticks)
+    }
+}
+
+impl ExtractPlugin for CorrelationPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field("correlation.ticks", &Self::extract_ticks)];
+}
+
+impl ParsePlugin for CorrelationPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall", "tick"];
+
+    fn parse_event(&mut self, event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        if event.event()?.event_type == EventType::PLUGINEVENT_E as u16 {
+            self.ticks += 1;
+        }
+        Ok(())
+    }
+}
+
+static_plugin!(CORRELATION_PLUGIN = CorrelationPlugin);
+
+/// Serves `correlation.ticks` on `syscall` events (see the module docs)
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = CORRELATION_PLUGIN;
@@ -0,0 +1,499 @@
+//! Decoding events captured by the native runner
+//!
+//! [`EventExt`] gives tests safe, typed access to the events returned by
+//! [`CapturingTestDriver::next_event`](crate::CapturingTestDriver::next_event).
+//!
+//! The [`params`] function decodes an event of any type into a list of named parameters
+//! with type-aware values, which the harness uses to serve `evt.*` fields.
+
+use crate::event_params::{capture, payload, Captured};
+use falco_plugin::anyhow;
+use falco_plugin::event::events;
+use falco_plugin::event::events::types::AnyEvent;
+use falco_plugin::event::events::{FromRawEvent, RawEvent};
+use falco_plugin::event::fields::types::{
+    PT_ENUMFLAGS32_socket_family, PT_FLAGS32_clone_flags, PT_FLAGS32_file_flags, PT_ERRNO, PT_FD,
+    PT_FSPATH, PT_PID, PT_SIGTYPE, PT_SOCKADDR, PT_SOCKTUPLE,
+};
+use falco_plugin::event::fields::{FromBytes, ToBytes};
+use falco_plugin_runner::Event;
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+
+/// Parse the header of an event captured by the native runner
+pub(crate) fn raw_event(event: &Event) -> anyhow::Result<RawEvent<'_>> {
+    let input = event.to_event_input();
+
+    // SAFETY: the event input points into the buffer owned by `event`, which outlives
+    // the returned RawEvent
+    Ok(unsafe { RawEvent::from_ptr(input.evt as *const u8) }?)
+}
+
+/// Safe access to the contents of events captured by the native runner
+///
+/// ```ignore
+/// use exercises::EventExt;
+///
+/// let event = driver.next_event().unwrap();
+/// let event = event.load::<PPME_ASYNCEVENT_E>().unwrap();
+/// assert_eq!(event.params.name, Some(c"async"));
+/// ```
+pub trait EventExt {
+    /// Parse the event header, leaving the parameters as a raw byte buffer
+    fn raw(&self) -> anyhow::Result<RawEvent<'_>>;
+
+    /// Parse the event as a specific event type, failing if it's of any other type
+    fn load<'a, T: FromRawEvent<'a>>(&'a self) -> anyhow::Result<events::Event<T>>;
+
+    /// Parse the event as whatever type it is
+    fn load_any(&self) -> anyhow::Result<events::Event<AnyEvent<'_>>>;
+}
+
+impl EventExt for Event {
+    fn raw(&self) -> anyhow::Result<RawEvent<'_>> {
+        raw_event(self)
+    }
+
+    fn load<'a, T: FromRawEvent<'a>>(&'a self) -> anyhow::Result<events::Event<T>> {
+        Ok(raw_event(self)?.load::<T>()?)
+    }
+
+    fn load_any(&self) -> anyhow::Result<events::Event<AnyEvent<'_>>> {
+        Ok(raw_event(self)?.load_any()?)
+    }
+}
+
+/// Get the name of the source an event came from
+pub(crate) fn event_source(event: &Event) -> Option<String> {
+    let input = event.to_event_input();
+    if input.evtsrc.is_null() {
+        return None;
+    }
+
+    // SAFETY: a non-null source name is a NUL-terminated string owned by the runner
+    let source = unsafe { CStr::from_ptr(input.evtsrc) };
+    Some(source.to_string_lossy().into_owned())
+}
+
+/// A decoded event parameter value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I32(i32),
+    I64(i64),
+    Fd(i64),
+    Errno(i64),
+    Pid(i64),
+    /// A bitmask (or enum) with the symbolic names of the bits that are set
+    Flags {
+        raw: u64,
+        width: usize,
+        names: Vec<String>,
+    },
+    Path(String),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// Socket addresses keep their raw encoding, next to the human-readable form
+    SockAddr {
+        display: String,
+        raw: Vec<u8>,
+    },
+    SockTuple {
+        display: String,
+        raw: Vec<u8>,
+    },
+}
+
+/// Render a buffer the way sinsp does: printable ASCII as-is, everything else as a dot
+fn render_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+impl ParamValue {
+    /// The raw (numeric where possible) value, as served by `evt.rawarg.*`
+    pub fn raw(&self) -> String {
+        match self {
+            ParamValue::Flags { raw, .. } => raw.to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl Display for ParamValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamValue::U8(v) => write!(f, "{}", v),
+            ParamValue::U16(v) => write!(f, "{}", v),
+            ParamValue::U32(v) => write!(f, "{}", v),
+            ParamValue::U64(v) => write!(f, "{}", v),
+            ParamValue::I32(v) => write!(f, "{}", v),
+            ParamValue::I64(v) | ParamValue::Fd(v) | ParamValue::Errno(v) | ParamValue::Pid(v) => {
+                write!(f, "{}", v)
+            }
+            ParamValue::Flags { raw, names, .. } if names.is_empty() => write!(f, "{}", raw),
+            ParamValue::Flags { names, .. } => f.write_str(&names.join("|")),
+            ParamValue::Path(s) | ParamValue::Str(s) => f.write_str(s),
+            ParamValue::Bytes(b) => f.write_str(&render_bytes(b)),
+            ParamValue::SockAddr { display, .. } | ParamValue::SockTuple { display, .. } => {
+                f.write_str(display)
+            }
+        }
+    }
+}
+
+/// Conversion from the strongly-typed event fields to [`ParamValue`]
+pub trait IntoParamValue {
+    fn into_param_value(self) -> ParamValue;
+}
+
+macro_rules! impl_into_param_value {
+    ($($ty:ty => |$v:ident| $conv:expr),* $(,)?) => {
+        $(
+            impl IntoParamValue for $ty {
+                fn into_param_value(self) -> ParamValue {
+                    let $v = self;
+                    $conv
+                }
+            }
+        )*
+    };
+}
+
+/// Get the raw bits and symbolic names of a flags value
+///
+/// The names come from the SDK's flag definitions (via the `bitflags` API), in the order
+/// they are declared there, rather than from any string representation.
+macro_rules! flags_value {
+    ($v:expr) => {{
+        let v = $v;
+        ParamValue::Flags {
+            raw: u64::from(v.bits()),
+            width: std::mem::size_of_val(&v.bits()),
+            names: v.iter_names().map(|(name, _)| name.to_string()).collect(),
+        }
+    }};
+}
+
+impl_into_param_value!(
+    u8 => |v| ParamValue::U8(v),
+    u16 => |v| ParamValue::U16(v),
+    u32 => |v| ParamValue::U32(v),
+    u64 => |v| ParamValue::U64(v),
+    i32 => |v| ParamValue::I32(v),
+    i64 => |v| ParamValue::I64(v),
+    PT_FD => |v| ParamValue::Fd(v.0),
+    PT_ERRNO => |v| ParamValue::Errno(v.0),
+    PT_PID => |v| ParamValue::Pid(v.0),
+    &CStr => |v| ParamValue::Str(v.to_string_lossy().into_owned()),
+    &[u8] => |v| ParamValue::Bytes(v.to_vec()),
+    PT_FSPATH<'_> => |v| ParamValue::Path(String::from_utf8_lossy(v.as_bytes()).into_owned()),
+    PT_SIGTYPE => |v| ParamValue::U8(v.0),
+    PT_FLAGS32_file_flags => |v| flags_value!(v),
+    PT_FLAGS32_clone_flags => |v| flags_value!(v),
+    PT_ENUMFLAGS32_socket_family => |v| socket_family_value(v),
+    PT_SOCKADDR<'_> => |v| ParamValue::SockAddr {
+        display: render_sockaddr(&v),
+        raw: to_bytes(&v),
+    },
+    PT_SOCKTUPLE<'_> => |v| ParamValue::SockTuple {
+        display: render_socktuple(&v),
+        raw: to_bytes(&v),
+    },
+);
+
+fn to_bytes<T: ToBytes>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(value.binary_size());
+    value.write(&mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// Get the raw value and name of a socket family
+///
+/// Unlike bitmasks, enum flags name a single value, so we look it up rather than
+/// splitting it into bits.
+fn socket_family_value(v: PT_ENUMFLAGS32_socket_family) -> ParamValue {
+    let families = [
+        PT_ENUMFLAGS32_socket_family::AF_UNSPEC,
+        PT_ENUMFLAGS32_socket_family::AF_UNIX,
+        PT_ENUMFLAGS32_socket_family::AF_INET,
+        PT_ENUMFLAGS32_socket_family::AF_INET6,
+        PT_ENUMFLAGS32_socket_family::AF_NETLINK,
+    ];
+    let names = ["AF_UNSPEC", "AF_UNIX", "AF_INET", "AF_INET6", "AF_NETLINK"];
+
+    let raw = to_bytes(&v);
+    let name = families
+        .iter()
+        .zip(names)
+        .find(|(family, _)| to_bytes(*family) == raw)
+        .map(|(_, name)| name.to_string());
+
+    let mut raw_bytes = [0u8; 8];
+    let width = raw.len().min(8);
+    raw_bytes[..width].copy_from_slice(&raw[..width]);
+    ParamValue::Flags {
+        raw: u64::from_le_bytes(raw_bytes),
+        width,
+        names: name.into_iter().collect(),
+    }
+}
+
+/// Render a socket address the way sinsp does, e.g. `10.0.0.1:8080` or a unix socket path
+fn render_sockaddr(addr: &PT_SOCKADDR) -> String {
+    match addr {
+        PT_SOCKADDR::V4((ip, port)) => format!("{}:{}", ip, port.0),
+        PT_SOCKADDR::V6((ip, port)) => format!("{}:{}", ip, port.0),
+        PT_SOCKADDR::Unix(path) => String::from_utf8_lossy(path.as_bytes()).into_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Render a socket tuple the way sinsp does, e.g. `10.0.0.1:40000->10.0.0.2:8080`,
+/// or `c11e47->5e47e4 /run/app.sock` for unix sockets
+pub(crate) fn render_socktuple(tuple: &PT_SOCKTUPLE) -> String {
+    match tuple {
+        PT_SOCKTUPLE::V4 { source, dest } => {
+            format!("{}:{}->{}:{}", source.0, source.1 .0, dest.0, dest.1 .0)
+        }
+        PT_SOCKTUPLE::V6 { source, dest } => {
+            format!("{}:{}->{}:{}", source.0, source.1 .0, dest.0, dest.1 .0)
+        }
+        PT_SOCKTUPLE::Unix {
+            source_ptr,
+            dest_ptr,
+            path,
+        } => format!(
+            "{:x}->{:x} {}",
+            source_ptr,
+            dest_ptr,
+            String::from_utf8_lossy(path.as_bytes())
+        ),
+        _ => String::new(),
+    }
+}
+
+/// A single named event parameter; `None` if the parameter is empty in the event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: &'static str,
+    pub value: Option<ParamValue>,
+}
+
+/// The event type name and all the parameters of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedParams {
+    pub name: String,
+    pub params: Vec<Param>,
+}
+
+impl DecodedParams {
+    /// Find a parameter by name
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name == name)
+    }
+}
+
+/// The sinsp name of an event type, e.g. `open` for `PPME_SYSCALL_OPEN_X`
+///
+/// Versioned event types (`PPME_SYSCALL_CLONE_20_X`, `PPME_SOCKET_ACCEPT4_6_E`) share
+/// the name of their syscall.
+fn event_name(type_name: &str) -> String {
+    let name = type_name.trim_start_matches("PPME_");
+    let name = name
+        .strip_suffix("_E")
+        .or_else(|| name.strip_suffix("_X"))
+        .unwrap_or(name);
+    let name = name
+        .strip_prefix("SYSCALL_")
+        .or_else(|| name.strip_prefix("SOCKET_"))
+        .unwrap_or(name);
+    let name = match name.rsplit_once('_') {
+        Some((base, version))
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => name,
+    };
+    name.to_ascii_lowercase()
+}
+
+/// Split the payload of an event into its (still encoded) parameters
+///
+/// Most event types use 16-bit parameter lengths, a few (plugin and async events among
+/// them) use 32-bit ones; only one of the two adds up to the size of the payload.
+pub(crate) fn raw_params<'a>(raw: &RawEvent<'a>) -> Option<Vec<&'a [u8]>> {
+    let nparams = raw.nparams as usize;
+    let payload = raw.payload;
+
+    [2, 4].into_iter().find_map(|len_size| {
+        let lens_end = nparams.checked_mul(len_size)?;
+        let lens: Vec<usize> = payload
+            .get(..lens_end)?
+            .chunks(len_size)
+            .map(|len| {
+                let mut buf = [0u8; 4];
+                buf[..len_size].copy_from_slice(len);
+                u32::from_le_bytes(buf) as usize
+            })
+            .collect();
+        if lens_end + lens.iter().sum::<usize>() != payload.len() {
+            return None;
+        }
+
+        let mut offset = lens_end;
+        let params = lens
+            .into_iter()
+            .map(|len| {
+                let param = &payload[offset..offset + len];
+                offset += len;
+                param
+            })
+            .collect();
+        Some(params)
+    })
+}
+
+/// Decode the parameter types the harness renders the way sinsp does, by the name
+/// the SDK serializes them under
+macro_rules! typed_value {
+    ($type_name:expr, $raw:expr, $($ty:ident),* $(,)?) => {
+        match $type_name {
+            $(
+                stringify!($ty) => {
+                    let mut buf = $raw;
+                    $ty::from_bytes(&mut buf)
+                        .ok()
+                        .map(IntoParamValue::into_param_value)
+                }
+            )*
+            _ => None,
+        }
+    };
+}
+
+/// Turn a captured parameter into a [`ParamValue`]
+///
+/// `readable` and `compact` are the human-readable and compact serializations of the
+/// parameter, `raw` its encoding in the event. Flags show up as their names in the
+/// former and as their bits in the latter. Anything without a scalar representation
+/// keeps its raw encoding.
+fn param_value(readable: &Captured, compact: &Captured, raw: &[u8]) -> Option<ParamValue> {
+    if let Captured::Named(type_name, _) = compact {
+        let typed = typed_value!(
+            *type_name,
+            raw,
+            PT_FD,
+            PT_ERRNO,
+            PT_PID,
+            PT_FSPATH,
+            PT_SOCKADDR,
+            PT_SOCKTUPLE,
+        );
+        if typed.is_some() {
+            return typed;
+        }
+    }
+
+    let value = match (readable.inner(), compact.inner()) {
+        (_, Captured::Empty) => return None,
+        (Captured::Str(names), Captured::Unsigned(bits, width)) => ParamValue::Flags {
+            raw: *bits,
+            width: *width,
+            names: flag_names(names),
+        },
+        // enums serialize as the name of their variant either way
+        (Captured::Str(name), Captured::Str(_))
+            if matches!(compact, Captured::Named(..)) && raw.len() <= 8 =>
+        {
+            let mut bits = [0u8; 8];
+            bits[..raw.len()].copy_from_slice(raw);
+            ParamValue::Flags {
+                raw: u64::from_le_bytes(bits),
+                width: raw.len(),
+                names: flag_names(name),
+            }
+        }
+        (_, Captured::Bool(v)) => ParamValue::U8(u8::from(*v)),
+        (_, Captured::Unsigned(v, 1)) => ParamValue::U8(*v as u8),
+        (_, Captured::Unsigned(v, 2)) => ParamValue::U16(*v as u16),
+        (_, Captured::Unsigned(v, 4)) => ParamValue::U32(*v as u32),
+        (_, Captured::Unsigned(v, _)) => ParamValue::U64(*v),
+        (_, Captured::Signed(v, 8)) => ParamValue::I64(*v),
+        (_, Captured::Signed(v, _)) => ParamValue::I32(*v as i32),
+        // C strings serialize as their bytes, without the NUL terminator
+        (_, Captured::Bytes(bytes)) if raw.len() == bytes.len() + 1 && raw.ends_with(&[0]) => {
+            ParamValue::Str(String::from_utf8_lossy(bytes).into_owned())
+        }
+        (_, Captured::Str(s)) if raw.len() == s.len() + 1 && raw.ends_with(&[0]) => {
+            ParamValue::Str(s.clone())
+        }
+        _ => ParamValue::Bytes(raw.to_vec()),
+    };
+    Some(value)
+}
+
+/// Split the human-readable form of a flags value (`O_RDWR | O_CREAT`) into names,
+/// leaving out bits without one
+fn flag_names(names: &str) -> Vec<String> {
+    names
+        .split('|')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.starts_with("0x"))
+        .map(String::from)
+        .collect()
+}
+
+/// Decode all parameters of an event
+///
+/// Works for any event type the SDK can parse: the parameter names and types come from
+/// the event's `Serialize` implementation (see [`crate::event_params`]). Returns `None`
+/// for events the SDK can't parse.
+pub fn params(raw: &RawEvent) -> Option<DecodedParams> {
+    let event = raw.load_any().ok()?;
+    let readable = capture(&event.params, true).ok()?;
+    let compact = capture(&event.params, false).ok()?;
+    let (type_name, readable) = payload(&readable)?;
+    let (_, compact) = payload(&compact)?;
+    let encoded = raw_params(raw)?;
+
+    let params = readable
+        .iter()
+        .zip(compact)
+        .enumerate()
+        .map(|(i, ((name, readable), (_, compact)))| Param {
+            // `type` is a keyword in Rust, so the field is called `type_`
+            name: name.trim_end_matches('_'),
+            value: encoded
+                .get(i)
+                .and_then(|raw| param_value(readable, compact, raw)),
+        })
+        .collect();
+
+    Some(DecodedParams {
+        name: event_name(type_name),
+        params,
+    })
+}
+
+/// Get the raw bytes of an event captured by the native runner, header included
+pub(crate) fn event_bytes(event: &Event) -> anyhow::Result<&[u8]> {
+    let raw = raw_event(event)?;
+    let input = event.to_event_input();
+
+    // SAFETY: the event input points into the buffer owned by `event`,
+    // and the header we just parsed tells us its length
+    Ok(unsafe { std::slice::from_raw_parts(input.evt as *const u8, raw.len as usize) })
+}
@@ -0,0 +1,447 @@
+//! Core `evt.*` fields, served by the harness for any event (like sinsp does)
+
+use crate::event::{event_source, params, ParamValue};
+use falco_plugin::event::events::RawEvent;
+use falco_plugin_runner::Event;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// Convert days since the Unix epoch to a (year, month, day) date
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// A timestamp (in nanoseconds since the epoch), split for formatting
+///
+/// All times are rendered in UTC, to keep test output independent of the local timezone.
+struct Timestamp {
+    date: (i64, u32, u32),
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+    nanos: u64,
+}
+
+impl Timestamp {
+    fn new(ts: u64) -> Self {
+        let secs = ts / NS_PER_SEC;
+        let secs_of_day = secs % 86400;
+
+        Self {
+            date: civil_from_days((secs / 86400) as i64),
+            hours: secs_of_day / 3600,
+            minutes: (secs_of_day / 60) % 60,
+            seconds: secs_of_day % 60,
+            nanos: ts % NS_PER_SEC,
+        }
+    }
+
+    fn time_s(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds)
+    }
+
+    fn time(&self) -> String {
+        format!("{}.{:09}", self.time_s(), self.nanos)
+    }
+
+    fn date(&self) -> String {
+        let (year, month, day) = self.date;
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    fn datetime_s(&self) -> String {
+        format!("{} {}", self.date(), self.time_s())
+    }
+
+    fn datetime(&self) -> String {
+        format!("{} {}", self.date(), self.time())
+    }
+
+    fn iso8601(&self) -> String {
+        format!("{}T{}Z", self.date(), self.time())
+    }
+}
+
+/// Names of the most common error codes, as used by `evt.res`
+fn errno_name(errno: i64) -> Option<&'static str> {
+    Some(match errno {
+        1 => "EPERM",
+        2 => "ENOENT",
+        3 => "ESRCH",
+        4 => "EINTR",
+        5 => "EIO",
+        9 => "EBADF",
+        11 => "EAGAIN",
+        12 => "ENOMEM",
+        13 => "EACCES",
+        14 => "EFAULT",
+        16 => "EBUSY",
+        17 => "EEXIST",
+        20 => "ENOTDIR",
+        21 => "EISDIR",
+        22 => "EINVAL",
+        24 => "EMFILE",
+        28 => "ENOSPC",
+        32 => "EPIPE",
+        98 => "EADDRINUSE",
+        104 => "ECONNRESET",
+        110 => "ETIMEDOUT",
+        111 => "ECONNREFUSED",
+        115 => "EINPROGRESS",
+        _ => return None,
+    })
+}
+
+/// Event direction, derived from the event type: enter events have even
+/// type numbers and the matching exit events follow them
+pub(crate) fn event_dir(raw: &RawEvent) -> &'static str {
+    if raw.event_type % 2 == 0 {
+        ">"
+    } else {
+        "<"
+    }
+}
+
+/// Event type name, falling back to the numeric type for events we can't decode
+pub(crate) fn event_type_name(raw: &RawEvent) -> String {
+    match params(raw) {
+        Some(decoded) => decoded.name.to_string(),
+        None => raw.event_type.to_string(),
+    }
+}
+
+/// The return value of an event, if it has one
+fn event_res(raw: &RawEvent) -> Option<i64> {
+    let decoded = params(raw)?;
+    match decoded.get("res")?.value.as_ref()? {
+        ParamValue::Errno(v) | ParamValue::Fd(v) | ParamValue::Pid(v) | ParamValue::I64(v) => {
+            Some(*v)
+        }
+        _ => None,
+    }
+}
+
+/// Render all the event parameters as `name=value` pairs
+///
+/// Returns `None` for events the SDK can't parse, rather than making up a representation
+/// sinsp wouldn't use.
+pub(crate) fn event_args(raw: &RawEvent) -> Option<String> {
+    let decoded = params(raw)?;
+
+    let args: Vec<String> = decoded
+        .params
+        .iter()
+        .map(|p| match &p.value {
+            Some(value) => format!("{}={}", p.name, value),
+            None => format!("{}=NULL", p.name),
+        })
+        .collect();
+    Some(args.join(" "))
+}
+
+/// Extract an `evt.*` field for an event
+///
+/// Returns `None` for fields not handled here (so the plugins get a chance to provide them),
+/// `Some(None)` for known fields without a value for this event.
+pub(crate) fn extract_field(event: &Event, raw: &RawEvent, field: &str) -> Option<Option<String>> {
+    let ts = raw.metadata.ts;
+
+    if let Some(name) = field.strip_prefix("evt.arg.") {
+        let decoded = params(raw);
+        let param = decoded.as_ref().and_then(|d| d.get(name));
+        return Some(
+            param
+                .and_then(|p| p.value.as_ref())
+                .map(ParamValue::to_string),
+        );
+    }
+    if let Some(name) = field.strip_prefix("evt.rawarg.") {
+        let decoded = params(raw);
+        let param = decoded.as_ref().and_then(|d| d.get(name));
+        return Some(param.and_then(|p| p.value.as_ref()).map(ParamValue::raw));
+    }
+
+    let value = match field {
+        "evt.num" => event.evt_num.map(|num| num.to_string()),
+        "evt.time" | "evt.outputtime" => Some(Timestamp::new(ts).time()),
+        "evt.time.s" => Some(Timestamp::new(ts).time_s()),
+        "evt.time.iso8601" => Some(Timestamp::new(ts).iso8601()),
+        "evt.datetime" => Some(Timestamp::new(ts).datetime()),
+        "evt.datetime.s" => Some(Timestamp::new(ts).datetime_s()),
+        "evt.rawtime" => Some(ts.to_string()),
+        "evt.rawtime.s" => Some((ts / NS_PER_SEC).to_string()),
+        "evt.rawtime.ns" => Some((ts % NS_PER_SEC).to_string()),
+        "evt.type" => Some(event_type_name(raw)),
+        "evt.dir" => Some(event_dir(raw).to_string()),
+        "evt.tid" => Some(raw.metadata.tid.to_string()),
+        "evt.source" => event_source(event),
+        "evt.args" => event_args(raw),
+        // the runner doesn't know which CPU an event happened on (and neither does sinsp
+        // for plugin events), so everything shows up on CPU 0
+        "evt.cpu" => Some(String::from("0")),
+        "evt.rawres" => event_res(raw).map(|res| res.to_string()),
+        "evt.res" => event_res(raw).map(|res| match res {
+            res if res >= 0 => String::from("SUCCESS"),
+            res => match errno_name(-res) {
+                Some(name) => name.to_string(),
+                None => res.to_string(),
+            },
+        }),
+        "evt.failed" => event_res(raw).map(|res| (res < 0).to_string()),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use crate::replay_source_plugin::{self, i64_param, process_lifecycle, str_param};
+    use crate::{init_plugin, network_source_plugin, syscall_source_plugin};
+    use crate::{CapturingTestDriver, TestDriver};
+    use falco_plugin::event::events::types::EventType;
+    use falco_plugin::event::fields::types::PT_FLAGS32_file_flags;
+    use falco_plugin_runner::Event;
+    use std::ffi::{CStr, CString};
+
+    fn capture(
+        plugin: &'static falco_plugin::api::plugin_api,
+        config: &CStr,
+    ) -> (NativeCapturingTestDriver, Vec<Event>) {
+        let (driver, _) = init_plugin::<NativeTestDriver>(plugin, config).unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = driver.next_event() {
+            events.push(event);
+        }
+        (driver, events)
+    }
+
+    fn field(driver: &mut NativeCapturingTestDriver, event: &Event, name: &str) -> Option<String> {
+        let name = CString::new(name).unwrap();
+        driver.event_field_as_string(&name, event).unwrap()
+    }
+
+    #[test]
+    fn syscall_event_fields() {
+        let (mut driver, events) = capture(&syscall_source_plugin::PLUGIN, c"");
+        assert_eq!(events.len(), 5);
+
+        let open = &events[0];
+        let first_num: u64 = field(&mut driver, open, "evt.num")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(field(&mut driver, open, "evt.type").unwrap(), "open");
+        assert_eq!(field(&mut driver, open, "evt.dir").unwrap(), "<");
+        assert_eq!(field(&mut driver, open, "evt.tid").unwrap(), "1");
+        assert_eq!(field(&mut driver, open, "evt.source").unwrap(), "syscall");
+        assert_eq!(field(&mut driver, open, "evt.rawtime").unwrap(), "1");
+        assert_eq!(
+            field(&mut driver, open, "evt.time").unwrap(),
+            "00:00:00.000000001"
+        );
+        assert_eq!(
+            field(&mut driver, open, "evt.datetime").unwrap(),
+            "1970-01-01 00:00:00.000000001"
+        );
+        assert_eq!(
+            field(&mut driver, open, "evt.time.iso8601").unwrap(),
+            "1970-01-01T00:00:00.000000001Z"
+        );
+        assert_eq!(
+            field(&mut driver, open, "evt.args").unwrap(),
+            "fd=5 name=/etc/passwd flags=O_RDWR mode=420 dev=0 ino=0"
+        );
+        assert_eq!(field(&mut driver, open, "evt.arg.fd").unwrap(), "5");
+        assert_eq!(
+            field(&mut driver, open, "evt.arg.name").unwrap(),
+            "/etc/passwd"
+        );
+        assert_eq!(field(&mut driver, open, "evt.arg.flags").unwrap(), "O_RDWR");
+        assert_eq!(
+            field(&mut driver, open, "evt.rawarg.flags").unwrap(),
+            PT_FLAGS32_file_flags::O_RDWR.bits().to_string()
+        );
+        assert_eq!(field(&mut driver, open, "evt.arg.nonexistent"), None);
+        // open exit events carry the fd, not a return value
+        assert_eq!(field(&mut driver, open, "evt.res"), None);
+
+        let read_e = &events[1];
+        assert_eq!(
+            field(&mut driver, read_e, "evt.num").unwrap(),
+            (first_num + 1).to_string()
+        );
+        assert_eq!(field(&mut driver, read_e, "evt.type").unwrap(), "read");
+        assert_eq!(field(&mut driver, read_e, "evt.dir").unwrap(), ">");
+        assert_eq!(
+            field(&mut driver, read_e, "evt.args").unwrap(),
+            "fd=5 size=5"
+        );
+
+        let read_x = &events[2];
+        assert_eq!(
+            field(&mut driver, read_x, "evt.args").unwrap(),
+            "res=5 data=hello"
+        );
+        assert_eq!(field(&mut driver, read_x, "evt.arg.data").unwrap(), "hello");
+        assert_eq!(field(&mut driver, read_x, "evt.res").unwrap(), "SUCCESS");
+        assert_eq!(field(&mut driver, read_x, "evt.rawres").unwrap(), "5");
+        assert_eq!(field(&mut driver, read_x, "evt.failed").unwrap(), "false");
+
+        let close_x = &events[4];
+        assert_eq!(field(&mut driver, close_x, "evt.type").unwrap(), "close");
+        assert_eq!(field(&mut driver, close_x, "evt.args").unwrap(), "res=0");
+        assert_eq!(field(&mut driver, close_x, "evt.rawarg.res").unwrap(), "0");
+    }
+
+    #[test]
+    fn process_event_fields() {
+        let config = replay_source_plugin::config(&process_lifecycle());
+        let (mut driver, events) = capture(&replay_source_plugin::PLUGIN, &config);
+
+        let execve = &events[3];
+        assert_eq!(field(&mut driver, execve, "evt.type").unwrap(), "execve");
+        assert_eq!(
+            field(&mut driver, execve, "evt.arg.exe").unwrap(),
+            "/bin/cat"
+        );
+        assert_eq!(field(&mut driver, execve, "evt.arg.comm").unwrap(), "cat");
+        assert_eq!(field(&mut driver, execve, "evt.arg.ptid").unwrap(), "100");
+        assert_eq!(field(&mut driver, execve, "evt.res").unwrap(), "SUCCESS");
+
+        // the fixture only encodes the exit status
+        let procexit = &events[9];
+        assert_eq!(
+            field(&mut driver, procexit, "evt.type").unwrap(),
+            "procexit"
+        );
+        assert_eq!(
+            field(&mut driver, procexit, "evt.args").unwrap(),
+            "status=0 ret=NULL sig=NULL core=NULL reaper_tid=NULL"
+        );
+        assert_eq!(field(&mut driver, procexit, "evt.arg.sig"), None);
+    }
+
+    #[test]
+    fn format_known_event() {
+        let config = replay_source_plugin::config(&process_lifecycle());
+        let (mut driver, events) = capture(&replay_source_plugin::PLUGIN, &config);
+
+        let open = &events[4];
+        let num = field(&mut driver, open, "evt.num").unwrap();
+        assert_eq!(
+            driver.format_event(open).unwrap(),
+            format!(
+                "{} 00:00:00.000000005 0 cat (101) < open \
+                 fd=3 name=/etc/passwd flags=NULL mode=NULL dev=NULL ino=NULL",
+                num
+            )
+        );
+    }
+
+    #[test]
+    fn format_any_event_type() {
+        // cat creates a directory right after opening /etc/passwd
+        let mut events = process_lifecycle();
+        events.insert(
+            5,
+            replay_source_plugin::event(
+                5,
+                101,
+                EventType::SYSCALL_MKDIR_2_X,
+                &[i64_param(0), str_param("/tmp/rustlings")],
+            ),
+        );
+        let config = replay_source_plugin::config(&events);
+        let (mut driver, events) = capture(&replay_source_plugin::PLUGIN, &config);
+
+        let mkdir = &events[5];
+        let num = field(&mut driver, mkdir, "evt.num").unwrap();
+        assert_eq!(
+            driver.format_event(mkdir).unwrap(),
+            format!(
+                "{} 00:00:00.000000005 0 cat (101) < mkdir res=0 path=/tmp/rustlings",
+                num
+            )
+        );
+    }
+
+    #[test]
+    fn socket_event_fields() {
+        let (mut driver, events) = capture(&network_source_plugin::PLUGIN, c"{}");
+
+        let mut domains = Vec::new();
+        let mut addrs = Vec::new();
+        let mut tuples = Vec::new();
+        for event in &events {
+            match field(&mut driver, event, "evt.type").as_deref() {
+                Some("socket") => domains.extend(field(&mut driver, event, "evt.arg.domain")),
+                Some("bind") => addrs.extend(field(&mut driver, event, "evt.arg.addr")),
+                Some("connect") => tuples.extend(field(&mut driver, event, "evt.arg.tuple")),
+                _ => {}
+            }
+        }
+
+        assert_eq!(domains, vec!["AF_INET", "AF_INET6", "AF_UNIX"]);
+        assert_eq!(
+            addrs,
+            vec!["10.0.0.2:8080", "fd00::2:8080", "/run/rustlings.sock"]
+        );
+        assert_eq!(
+            tuples,
+            vec![
+                "10.0.0.1:40000->10.0.0.2:8080",
+                "fd00::1:40000->fd00::2:8080",
+                "c11e47->5e47e4 /run/rustlings.sock",
+            ]
+        );
+    }
+
+    #[test]
+    fn any_event_type_fields() {
+        // none of the fixture sources emit mkdir events
+        let events = vec![
+            replay_source_plugin::event(1, 1, EventType::SYSCALL_MKDIR_2_E, &[]),
+            replay_source_plugin::event(
+                2,
+                1,
+                EventType::SYSCALL_MKDIR_2_X,
+                &[i64_param(-17), str_param("/tmp/rustlings")],
+            ),
+        ];
+        let config = replay_source_plugin::config(&events);
+        let (mut driver, events) = capture(&replay_source_plugin::PLUGIN, &config);
+
+        let mkdir = &events[1];
+        assert_eq!(field(&mut driver, mkdir, "evt.type").unwrap(), "mkdir");
+        assert_eq!(
+            field(&mut driver, mkdir, "evt.arg.path").unwrap(),
+            "/tmp/rustlings"
+        );
+        assert_eq!(field(&mut driver, mkdir, "evt.rawarg.res").unwrap(), "-17");
+        assert_eq!(field(&mut driver, mkdir, "evt.res").unwrap(), "EEXIST");
+        assert_eq!(
+            field(&mut driver, mkdir, "evt.args").unwrap(),
+            "res=-17 path=/tmp/rustlings"
+        );
+
+        // the enter event has no parameters in this capture
+        assert_eq!(field(&mut driver, &events[0], "evt.type").unwrap(), "mkdir");
+        assert_eq!(field(&mut driver, &events[0], "evt.arg.mode"), None);
+    }
+}
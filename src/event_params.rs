@@ -0,0 +1,443 @@
+//! Generic access to the parameters of any event type
+//!
+//! The SDK's event types implement `Serialize`, which walks every parameter, by name and in
+//! wire order. [`capture`] runs that walk into a [`Captured`] tree, keeping the names of
+//! the newtypes and enums the parameters are wrapped in (`PT_FD`, `PT_SOCKADDR`, ...),
+//! so that [`crate::event::params`] can turn them into typed values without a per-event
+//! table.
+
+use falco_plugin::serde::ser::{self, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A value as seen by the serializer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Captured {
+    /// `None` or `()`, i.e. an empty parameter
+    Empty,
+    Bool(bool),
+    /// An unsigned integer, with its width in bytes
+    Unsigned(u64, usize),
+    /// A signed integer, with its width in bytes
+    Signed(i64, usize),
+    Str(String),
+    Bytes(Vec<u8>),
+    Seq(Vec<Captured>),
+    /// A newtype struct or an enum variant, with the name of its type
+    Named(&'static str, Box<Captured>),
+    /// A struct or a struct-like enum variant, with its name and its fields in order
+    Struct(&'static str, Vec<(&'static str, Captured)>),
+}
+
+impl Captured {
+    /// Strip any newtype and enum wrappers
+    pub(crate) fn inner(&self) -> &Captured {
+        match self {
+            Captured::Named(_, inner) => inner.inner(),
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CaptureError(String);
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl ser::Error for CaptureError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Run the `Serialize` implementation of `value`
+///
+/// `human_readable` is passed on to the implementation, which lets the caller see both
+/// representations of types that have two (e.g. flags, serialized as their names in
+/// human-readable form and as their raw bits otherwise).
+pub(crate) fn capture<T: Serialize + ?Sized>(
+    value: &T,
+    human_readable: bool,
+) -> Result<Captured, CaptureError> {
+    value.serialize(Capture { human_readable })
+}
+
+/// Find the struct (the event payload) inside a captured event, skipping the enum
+/// variant that wraps it
+pub(crate) fn payload(event: &Captured) -> Option<(&'static str, &[(&'static str, Captured)])> {
+    match event {
+        Captured::Struct(name, fields) => Some((name, fields)),
+        Captured::Named(_, inner) => payload(inner),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Capture {
+    human_readable: bool,
+}
+
+/// Collects the elements of sequences, tuples, structs and maps
+struct Collect {
+    capture: Capture,
+    name: &'static str,
+    variant: Option<&'static str>,
+    fields: Vec<(&'static str, Captured)>,
+}
+
+impl Collect {
+    fn new(capture: Capture, name: &'static str, variant: Option<&'static str>) -> Self {
+        Self {
+            capture,
+            name,
+            variant,
+            fields: Vec::new(),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), CaptureError> {
+        self.fields.push((name, value.serialize(self.capture)?));
+        Ok(())
+    }
+
+    fn seq(self) -> Captured {
+        let seq = Captured::Seq(self.fields.into_iter().map(|(_, v)| v).collect());
+        match self.variant {
+            Some(_) => Captured::Named(self.name, Box::new(seq)),
+            None => seq,
+        }
+    }
+
+    fn structure(self) -> Captured {
+        let structure = Captured::Struct(self.variant.unwrap_or(self.name), self.fields);
+        match self.variant {
+            Some(_) => Captured::Named(self.name, Box::new(structure)),
+            None => structure,
+        }
+    }
+}
+
+impl ser::Serializer for Capture {
+    type Ok = Captured;
+    type Error = CaptureError;
+    type SerializeSeq = Collect;
+    type SerializeTuple = Collect;
+    type SerializeTupleStruct = Collect;
+    type SerializeTupleVariant = Collect;
+    type SerializeMap = Collect;
+    type SerializeStruct = Collect;
+    type SerializeStructVariant = Collect;
+
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Captured, CaptureError> {
+        Ok(Captured::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Captured, CaptureError> {
+        Ok(Captured::Signed(v.into(), 1))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Captured, CaptureError> {
+        Ok(Captured::Signed(v.into(), 2))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Captured, CaptureError> {
+        Ok(Captured::Signed(v.into(), 4))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Captured, CaptureError> {
+        Ok(Captured::Signed(v, 8))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Captured, CaptureError> {
+        Ok(Captured::Unsigned(v.into(), 1))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Captured, CaptureError> {
+        Ok(Captured::Unsigned(v.into(), 2))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Captured, CaptureError> {
+        Ok(Captured::Unsigned(v.into(), 4))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Captured, CaptureError> {
+        Ok(Captured::Unsigned(v, 8))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Captured, CaptureError> {
+        Ok(Captured::Str(v.to_string()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Captured, CaptureError> {
+        Ok(Captured::Str(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Captured, CaptureError> {
+        Ok(Captured::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Captured, CaptureError> {
+        Ok(Captured::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Captured, CaptureError> {
+        Ok(Captured::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Captured, CaptureError> {
+        Ok(Captured::Empty)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Captured, CaptureError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Captured, CaptureError> {
+        Ok(Captured::Empty)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Captured, CaptureError> {
+        Ok(Captured::Empty)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Captured, CaptureError> {
+        Ok(Captured::Named(
+            name,
+            Box::new(Captured::Str(variant.to_string())),
+        ))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Captured, CaptureError> {
+        Ok(Captured::Named(name, Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<Captured, CaptureError> {
+        Ok(Captured::Named(name, Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, "", None))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, "", None))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, name, None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, name, Some(variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, "", None))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, name, None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Collect, CaptureError> {
+        Ok(Collect::new(self, name, Some(variant)))
+    }
+}
+
+impl ser::SerializeSeq for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CaptureError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.seq())
+    }
+}
+
+impl ser::SerializeTuple for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CaptureError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.seq())
+    }
+}
+
+impl ser::SerializeTupleStruct for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CaptureError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        let name = self.name;
+        let seq = self.seq();
+        Ok(Captured::Named(name, Box::new(seq)))
+    }
+}
+
+impl ser::SerializeTupleVariant for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CaptureError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.seq())
+    }
+}
+
+impl ser::SerializeMap for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    // parameters never serialize as maps; keep whatever shows up as a flat sequence
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), CaptureError> {
+        self.push("", key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CaptureError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.seq())
+    }
+}
+
+impl ser::SerializeStruct for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), CaptureError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.structure())
+    }
+}
+
+impl ser::SerializeStructVariant for Collect {
+    type Ok = Captured;
+    type Error = CaptureError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), CaptureError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Captured, CaptureError> {
+        Ok(self.structure())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, payload, Captured};
+    use falco_plugin::serde::Serialize;
+
+    #[derive(Serialize)]
+    #[serde(crate = "falco_plugin::serde")]
+    struct Fd(i64);
+
+    #[derive(Serialize)]
+    #[serde(crate = "falco_plugin::serde")]
+    struct Payload<'a> {
+        fd: Fd,
+        name: Option<&'a str>,
+        mode: Option<u32>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "falco_plugin::serde")]
+    enum Any<'a> {
+        Open(Payload<'a>),
+    }
+
+    #[test]
+    fn fields_in_order() {
+        let event = Any::Open(Payload {
+            fd: Fd(3),
+            name: Some("/etc/passwd"),
+            mode: None,
+        });
+
+        let captured = capture(&event, false).unwrap();
+        let (name, fields) = payload(&captured).unwrap();
+        assert_eq!(name, "Payload");
+        assert_eq!(
+            fields,
+            &[
+                (
+                    "fd",
+                    Captured::Named("Fd", Box::new(Captured::Signed(3, 8)))
+                ),
+                ("name", Captured::Str(String::from("/etc/passwd"))),
+                ("mode", Captured::Empty),
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use falco_plugin::anyhow::{anyhow, Error};
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::types::{EventType, PPME_PLUGINEVENT_E};
+use falco_plugin::extract::{field, EventInput, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::source::{EventBatch, PluginEvent, SourcePlugin, SourcePluginInstance};
+use falco_plugin::tables::TablesInput;
+use falco_plugin::{static_plugin, FailureReason};
+use std::ffi::{CStr, CString};
+
+/// The failure the plugin injects
+///
+/// A panic can't unwind through the `extern "C"` plugin API, so [`Fault::ExtractPanic`]
+/// aborts the whole process rather than failing a single test: run tests using it in
+/// a child process.
+#[derive(JsonSchema, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// Behave like a well-mannered plugin
+    #[default]
+    None,
+    /// Fail in `Plugin::new`
+    Init,
+    /// Fail in `SourcePlugin::open`
+    Open,
+    /// Return `FailureReason::Timeout` from `next_batch`
+    Timeout,
+    /// Return `FailureReason::Failure` from `next_batch`
+    Failure,
+    /// Emit an event whose declared parameters do not fit in the event
+    MalformedEvent,
+    /// Return `oversized_batch_len` events from a single `next_batch` call
+    OversizedBatch,
+    /// Panic when extracting `faulty.num`
+    ExtractPanic,
+}
+
+/// Which fault to inject, and when
+///
+/// All fields are optional, so an empty JSON object (`{}`) gives a plugin that
+/// never fails.
+#[derive(JsonSchema, Deserialize, Default)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(default)]
+pub struct FaultyConfig {
+    /// The failure to inject
+    pub fault: Fault,
+    /// Number of well-formed events emitted before `next_batch` faults kick in
+    pub healthy_events: u64,
+    /// Number of events in an oversized batch (defaults to 100000)
+    pub oversized_batch_len: Option<u64>,
+}
+
+/// The event header of a `PPME_PLUGINEVENT_E` that claims two 100-byte params
+/// but carries only the lengths, not the param data
+fn malformed_event() -> Vec<u8> {
+    let lengths = [100u32, 100u32];
+    let len = 26 + 4 * lengths.len();
+
+    let mut buf = Vec::with_capacity(len);
+    buf.extend_from_slice(&0u64.to_le_bytes()); // ts
+    buf.extend_from_slice(&0u64.to_le_bytes()); // tid
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    buf.extend_from_slice(&(EventType::PLUGINEVENT_E as u16).to_le_bytes());
+    buf.extend_from_slice(&(lengths.len() as u32).to_le_bytes());
+    for param_len in lengths {
+        buf.extend_from_slice(&param_len.to_le_bytes());
+    }
+    buf
+}
+
+struct FaultyPlugin {
+    config: FaultyConfig,
+}
+
+impl Plugin for FaultyPlugin {
+    const NAME: &'static CStr = c"faulty";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Plugin failing in configurable ways.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<FaultyConfig>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        if config.fault != Fault::None {
+            log::warn!("injecting fault: {:?}", config.fault);
+        }
+        if config.fault == Fault::Init {
+            falco_plugin::anyhow::bail!("injected failure in Plugin::new");
+        }
+
+        Ok(Self { config })
+    }
+}
+
+impl SourcePlugin for FaultyPlugin {
+    type Instance = FaultyPluginInstance;
+    const EVENT_SOURCE: &'static CStr = c"faulty";
+    const PLUGIN_ID: u32 = 1999;
+
+    fn open(&mut self, _params: Option<&str>) -> Result<Self::Instance, Error> {
+        if self.config.fault != Fault::None {
+            log::warn!("opening with fault: {:?}", self.config.fault);
+        }
+        if self.config.fault == Fault::Open {
+            falco_plugin::anyhow::bail!("injected failure in SourcePlugin::open");
+        }
+
+        Ok(FaultyPluginInstance { next: 0 })
+    }
+
+    fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
+        let event = event.event()?;
+        let event = event.load::<PPME_PLUGINEVENT_E>()?;
+        let num = decode_num(event.params.event_data)?;
+
+        Ok(CString::new(format!("faulty event {}", num))?)
+    }
+}
+
+/// Each healthy event carries its sequence number as a little-endian u64
+fn decode_num(payload: Option<&[u8]>) -> Result<u64, Error> {
+    let payload = payload.ok_or_else(|| anyhow!("Missing event data"))?;
+    Ok(u64::from_le_bytes(payload.try_into()?))
+}
+
+struct FaultyPluginInstance {
+    next: u64,
+}
+
+impl FaultyPluginInstance {
+    fn add_healthy_event(&mut self, batch: &mut EventBatch) -> Result<(), Error> {
+        let event = Self::plugin_event(&self.next.to_le_bytes());
+        batch.add(event)?;
+        self.next += 1;
+        Ok(())
+    }
+}
+
+impl SourcePluginInstance for FaultyPluginInstance {
+    type Plugin = FaultyPlugin;
+
+    fn next_batch(
+        &mut self,
+        plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        if self.next < plugin.config.healthy_events {
+            return self.add_healthy_event(batch);
+        }
+
+        match plugin.config.fault {
+            Fault::Timeout => Err(FailureReason::Timeout)?,
+            Fault::Failure => Err(FailureReason::Failure)?,
+            Fault::MalformedEvent => {
+                batch.add(&*malformed_event())?;
+                Ok(())
+            }
+            Fault::OversizedBatch => {
+                for _ in 0..plugin.config.oversized_batch_len.unwrap_or(100_000) {
+                    self.add_healthy_event(batch)?;
+                }
+                Ok(())
+            }
+            Fault::None | Fault::Init | Fault::Open | Fault::ExtractPanic => {
+                self.add_healthy_event(batch)
+            }
+        }
+    }
+}
+
+impl FaultyPlugin {
+    fn extract_num(&mut self, req: ExtractRequest<Self>) -> Result<u64, Error> {
+        if self.config.fault == Fault::ExtractPanic {
+            panic!("injected panic in field extraction");
+        }
+
+        let event = req.event.event()?;
+        let event = event.load::<PluginEvent>()?;
+        decode_num(event.params.event_data)
+    }
+}
+
+impl ExtractPlugin for FaultyPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["faulty"];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field("faulty.num", &Self::extract_num)];
+}
+
+static_plugin!(FAULTY_PLUGIN = FaultyPlugin);
+
+/// A `faulty` source (with a `faulty.num` field) failing as requested by its config
+///
+/// Requires a JSON config (see [`FaultyConfig`]), e.g. `{"fault": "timeout", "healthy_events": 3}`
+/// emits three events numbered 0 to 2, then times out on every subsequent `next_batch`.
+/// The plugin logs a warning naming the fault as it starts, and again when it's opened.
+pub static PLUGIN: falco_plugin::api::plugin_api = FAULTY_PLUGIN;
+
+#[cfg(test)]
+mod tests {
+    use crate::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use crate::{CapturingTestDriver, EventExt, ScapStatus, TestDriver};
+    use std::ffi::CStr;
+    use std::process::Command;
+
+    fn start(config: &CStr) -> NativeCapturingTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.register_plugin(&super::PLUGIN, config).unwrap();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    /// Read `count` events, checking they're the healthy ones numbered from 0
+    fn expect_healthy_events(driver: &mut NativeCapturingTestDriver, count: u64) {
+        for num in 0..count {
+            let event = driver.next_event().unwrap();
+            let field = driver.event_field_as_string(c"faulty.num", &event);
+            assert_eq!(field.unwrap(), Some(num.to_string()));
+        }
+    }
+
+    #[test]
+    fn healthy() {
+        let mut driver = start(c"{}");
+        expect_healthy_events(&mut driver, 10);
+    }
+
+    #[test]
+    fn init_failure() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let res = driver.register_plugin(&super::PLUGIN, c"{\"fault\": \"init\"}");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn open_failure() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&super::PLUGIN, c"{\"fault\": \"open\"}")
+            .unwrap();
+        assert!(driver.start_capture(c"", c"").is_err());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut driver = start(c"{\"fault\": \"timeout\", \"healthy_events\": 3}");
+        expect_healthy_events(&mut driver, 3);
+
+        // a timeout is not the end of the capture, so it's reported every time
+        assert!(matches!(driver.next_event(), Err(ScapStatus::Timeout)));
+        assert!(matches!(driver.next_event(), Err(ScapStatus::Timeout)));
+    }
+
+    #[test]
+    fn failure() {
+        let mut driver = start(c"{\"fault\": \"failure\", \"healthy_events\": 3}");
+        expect_healthy_events(&mut driver, 3);
+        assert!(matches!(driver.next_event(), Err(ScapStatus::Failure)));
+    }
+
+    #[test]
+    fn malformed_event() {
+        let mut driver = start(c"{\"fault\": \"malformed_event\", \"healthy_events\": 1}");
+        expect_healthy_events(&mut driver, 1);
+
+        // the runner passes the event on as-is, with a valid header...
+        let event = driver.next_event().unwrap();
+        let raw = event.raw().unwrap();
+        assert_eq!(raw.nparams, 2);
+
+        // ...but its params can't be decoded, by the harness or by the plugin
+        assert!(event.load_any().is_err());
+        assert!(driver.event_field_as_string(c"faulty.num", &event).is_err());
+    }
+
+    #[test]
+    fn oversized_batch() {
+        let mut driver = start(c"{\"fault\": \"oversized_batch\", \"oversized_batch_len\": 5000}");
+
+        // all the events from the batch come out one by one, followed by the next batch
+        expect_healthy_events(&mut driver, 5001);
+    }
+
+    #[test]
+    fn extract_panic() {
+        // the panic takes the whole process down, so the actual test runs in a child
+        // process running just this test
+        if std::env::var_os("FAULTY_PLUGIN_EXTRACT_PANIC").is_some() {
+            let mut driver = start(c"{\"fault\": \"extract_panic\"}");
+            let event = driver.next_event().unwrap();
+            let _ = driver.event_field_as_string(c"faulty.num", &event);
+            return;
+        }
+
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "faulty_plugin::tests::extract_panic",
+                "--nocapture",
+            ])
+            .env("FAULTY_PLUGIN_EXTRACT_PANIC", "1")
+            .output()
+            .unwrap();
+
+        // the child aborts (rather than reporting a failed test) as the panic can't
+        // unwind out of the extraction callback
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("injected panic in field extraction"),
+            "{}",
+            stderr
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            // SIGABRT
+            assert_eq!(output.status.signal(), Some(6), "{}", stderr);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Event filtering checks
+//!
+//! Extract and parse plugins declare which event types and sources they can handle
+//! (`EVENT_TYPES` and `EVENT_SOURCES` in the SDK). Falco never calls a plugin for other
+//! events, and fields extracted from events a plugin can't see have no value.
+//!
+//! The native driver checks both sides of that contract: every call into a plugin is compared against its declarations
+//! (a mismatch is a [`FilterViolation`]), and
+//! [`event_field_as_string`](crate::CapturingTestDriver::event_field_as_string) returns
+//! `None` for fields of plugins that can't see the event.
+
+use crate::async_events::AsyncDeclarations;
+use crate::open_params::{parse_open_params, OpenParam};
+use falco_plugin::api::{plugin_api, ss_plugin_rc_SS_PLUGIN_SUCCESS};
+use std::ffi::{c_char, CStr};
+
+/// What went wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The plugin was asked to extract fields from an event it didn't declare
+    UndeclaredExtract,
+    /// The plugin was asked to parse an event it didn't declare
+    UndeclaredParse,
+    /// The plugin returned a value for a field, on an event it can't see
+    FieldOnHiddenEvent { field: String },
+}
+
+/// A plugin called for (or extracting from) an event outside its declared filters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterViolation {
+    pub plugin: String,
+    pub kind: ViolationKind,
+    pub event_type: u16,
+    pub source: Option<String>,
+    pub evt_num: u64,
+}
+
+/// The event types and sources a plugin capability accepts (empty lists accept anything)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventFilter {
+    pub(crate) types: Vec<u16>,
+    pub(crate) sources: Vec<String>,
+}
+
+impl EventFilter {
+    pub(crate) fn accepts(&self, event_type: u16, source: Option<&str>) -> bool {
+        let type_ok = self.types.is_empty() || self.types.contains(&event_type);
+        let source_ok = self.sources.is_empty()
+            || source.is_some_and(|source| self.sources.iter().any(|s| s == source));
+        type_ok && source_ok
+    }
+}
+
+/// Parse a JSON array of strings, as returned by the `get_*_event_sources` entry points
+///
+/// # Safety
+/// `json` must be null or point to a NUL-terminated string
+unsafe fn string_list(json: *const c_char) -> Vec<String> {
+    if json.is_null() {
+        return Vec::new();
+    }
+
+    let json = CStr::from_ptr(json).to_string_lossy();
+    serde_json::from_str(&json).unwrap_or_default()
+}
+
+/// Copy an event type list, as returned by the `get_*_event_types` entry points
+///
+/// # Safety
+/// `types` must be null or point to `num_types` event types
+unsafe fn type_list(types: *const u16, num_types: u32) -> Vec<u16> {
+    if types.is_null() {
+        return Vec::new();
+    }
+
+    std::slice::from_raw_parts(types, num_types as usize).to_vec()
+}
+
+/// What a plugin declared about the events it handles, the fields it provides
+/// and the open parameters it accepts
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Declarations {
+    pub(crate) extract: Option<EventFilter>,
+    pub(crate) parse: Option<EventFilter>,
+    pub(crate) async_events: Option<AsyncDeclarations>,
+    pub(crate) fields: Vec<String>,
+    /// `None` if the plugin can't list its open parameters, an error if it failed to
+    pub(crate) open_params: Option<Result<Vec<OpenParam>, String>>,
+}
+
+impl Declarations {
+    /// Query the declarations of an initialized plugin
+    ///
+    /// Plugins with a source capability and no declared sources only get events
+    /// from their own source, just like in Falco.
+    ///
+    /// # Safety
+    /// `plugin` must be a state returned by `api.init`
+    pub(crate) unsafe fn query(
+        api: &plugin_api,
+        plugin: *mut falco_plugin::api::ss_plugin_t,
+    ) -> Self {
+        let own_source = match api.__bindgen_anon_1.get_event_source {
+            Some(get_event_source) => source_name(get_event_source()),
+            None => Vec::new(),
+        };
+        let default_sources = |sources: Vec<String>| {
+            if sources.is_empty() {
+                own_source.clone()
+            } else {
+                sources
+            }
+        };
+
+        let extraction = &api.__bindgen_anon_2;
+        let extract = extraction.extract_fields.map(|_| {
+            let mut num_types = 0;
+            let types = match extraction.get_extract_event_types {
+                Some(f) => type_list(f(&mut num_types, plugin), num_types),
+                None => Vec::new(),
+            };
+            let sources = match extraction.get_extract_event_sources {
+                Some(f) => string_list(f()),
+                None => Vec::new(),
+            };
+            EventFilter {
+                types,
+                sources: default_sources(sources),
+            }
+        });
+
+        let parsing = &api.__bindgen_anon_3;
+        let parse = parsing.parse_event.map(|_| {
+            let mut num_types = 0;
+            let types = match parsing.get_parse_event_types {
+                Some(f) => type_list(f(&mut num_types, plugin), num_types),
+                None => Vec::new(),
+            };
+            let sources = match parsing.get_parse_event_sources {
+                Some(f) => string_list(f()),
+                None => Vec::new(),
+            };
+            EventFilter {
+                types,
+                sources: default_sources(sources),
+            }
+        });
+
+        let async_api = &api.__bindgen_anon_4;
+        let async_events = async_api
+            .set_async_event_handler
+            .map(|_| AsyncDeclarations {
+                names: match async_api.get_async_events {
+                    Some(f) => string_list(f()),
+                    None => Vec::new(),
+                },
+                sources: match async_api.get_async_event_sources {
+                    Some(f) => string_list(f()),
+                    None => Vec::new(),
+                },
+            });
+
+        let fields = match extraction.get_fields {
+            Some(get_fields) => field_names(get_fields()),
+            None => Vec::new(),
+        };
+
+        let open_params = api.__bindgen_anon_1.list_open_params.map(|f| {
+            let mut rc = ss_plugin_rc_SS_PLUGIN_SUCCESS;
+            let json = f(plugin, &mut rc);
+            if rc != ss_plugin_rc_SS_PLUGIN_SUCCESS {
+                return Err(format!("failed with {}: {}", rc, last_error(api, plugin)));
+            }
+            if json.is_null() {
+                return Ok(Vec::new());
+            }
+            parse_open_params(&CStr::from_ptr(json).to_string_lossy())
+                .map_err(|e| format!("invalid JSON: {}", e))
+        });
+
+        Self {
+            extract,
+            parse,
+            async_events,
+            fields,
+            open_params,
+        }
+    }
+}
+
+/// The last error message of a plugin
+///
+/// # Safety
+/// `plugin` must be a state returned by `api.init`
+unsafe fn last_error(api: &plugin_api, plugin: *mut falco_plugin::api::ss_plugin_t) -> String {
+    let err = match api.get_last_error {
+        Some(get_last_error) => get_last_error(plugin),
+        None => std::ptr::null(),
+    };
+    if err.is_null() {
+        return String::from("no error message");
+    }
+
+    CStr::from_ptr(err).to_string_lossy().into_owned()
+}
+
+/// The event source of a source plugin, as a list with (at most) one element
+///
+/// # Safety
+/// `name` must be null or point to a NUL-terminated string
+unsafe fn source_name(name: *const c_char) -> Vec<String> {
+    if name.is_null() {
+        return Vec::new();
+    }
+
+    let name = CStr::from_ptr(name).to_string_lossy();
+    if name.is_empty() {
+        Vec::new()
+    } else {
+        vec![name.into_owned()]
+    }
+}
+
+/// The names of all fields in a `get_fields` JSON schema
+///
+/// # Safety
+/// `json` must be null or point to a NUL-terminated string
+unsafe fn field_names(json: *const c_char) -> Vec<String> {
+    if json.is_null() {
+        return Vec::new();
+    }
+
+    let json = CStr::from_ptr(json).to_string_lossy();
+    let fields: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap_or_default();
+    fields
+        .iter()
+        .filter_map(|f| f["name"].as_str().map(String::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ViolationKind;
+    use crate::native::NativeTestDriver;
+    use crate::{misdeclared_plugin, syscall_extract_plugin, syscall_source_plugin};
+    use crate::{CapturingTestDriver, TestDriver};
+    use falco_plugin::event::events::types::EventType;
+
+    #[test]
+    fn declared_event_types() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&syscall_extract_plugin::PLUGIN, c"")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut fds = Vec::new();
+        while let Ok(evt) = driver.next_event() {
+            fds.push(driver.event_field_as_string(c"rustlings.fd", &evt).unwrap());
+        }
+
+        // the undeclared events have no value, and the plugin was never asked about them
+        let fd = || Some(String::from("5"));
+        assert_eq!(fds, vec![fd(), fd(), None, fd(), None]);
+        assert_eq!(driver.filter_violations(), vec![]);
+    }
+
+    #[test]
+    fn misdeclared_event_types() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&misdeclared_plugin::PLUGIN, c"")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut types = Vec::new();
+        while let Ok(evt) = driver.next_event() {
+            types.push(
+                driver
+                    .event_field_as_string(c"misdeclared.type", &evt)
+                    .unwrap(),
+            );
+        }
+
+        // only the declared event has a value
+        let open_x = EventType::SYSCALL_OPEN_X as u16;
+        assert_eq!(
+            types,
+            vec![Some(open_x.to_string()), None, None, None, None]
+        );
+
+        // the plugin parsed and extracted from the four undeclared events
+        let hidden = [
+            EventType::SYSCALL_READ_E as u16,
+            EventType::SYSCALL_READ_X as u16,
+            EventType::SYSCALL_CLOSE_E as u16,
+            EventType::SYSCALL_CLOSE_X as u16,
+        ];
+        let violations = driver.filter_violations();
+        assert!(violations.iter().all(|v| v.plugin == "misdeclared"));
+        let of_kind = |kind: ViolationKind| {
+            violations
+                .iter()
+                .filter(|v| v.kind == kind)
+                .map(|v| v.event_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(of_kind(ViolationKind::UndeclaredParse), hidden);
+        assert_eq!(of_kind(ViolationKind::UndeclaredExtract), hidden);
+        let field = String::from("misdeclared.type");
+        assert_eq!(of_kind(ViolationKind::FieldOnHiddenEvent { field }), hidden);
+    }
+}
@@ -0,0 +1,111 @@
+//! A `syscall` parse plugin counting the events of every thread in tables, for the
+//! harness's own tests
+//!
+//! The plugin exports three tables, all updated for every event:
+//! - `histogram`, keyed by thread id, with the thread id in `number` and the number
+//!   of events in `count` (like the histogram of the table exercise)
+//! - `unkeyed`, with the same keys and counts, but no field holding the key
+//! - `sources`, keyed by event source name (always `syscall`), counting all events
+
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::EventInput;
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::export::{Entry, Public};
+use falco_plugin::tables::{export, TablesInput};
+use std::ffi::{CStr, CString};
+
+#[derive(Entry)]
+struct HistogramEntry {
+    number: Public<u64>,
+    count: Public<u64>,
+}
+
+type HistogramTable = export::Table<u64, HistogramEntry>;
+
+#[derive(Entry)]
+struct UnkeyedEntry {
+    count: Public<u32>,
+}
+
+type UnkeyedTable = export::Table<u64, UnkeyedEntry>;
+
+#[derive(Entry)]
+struct SourceEntry {
+    count: Public<u64>,
+}
+
+type SourcesTable = export::Table<CString, SourceEntry>;
+
+struct HistogramPlugin {
+    histogram: Box<HistogramTable>,
+    unkeyed: Box<UnkeyedTable>,
+    sources: Box<SourcesTable>,
+}
+
+impl Plugin for HistogramPlugin {
+    const NAME: &'static CStr = c"histogram";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Counts the events of every thread in tables.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        let Some(input) = input else {
+            falco_plugin::anyhow::bail!("Table input not provided");
+        };
+
+        Ok(Self {
+            histogram: input.add_table(HistogramTable::new(c"histogram")?)?,
+            unkeyed: input.add_table(UnkeyedTable::new(c"unkeyed")?)?,
+            sources: input.add_table(SourcesTable::new(c"sources")?)?,
+        })
+    }
+}
+
+impl ParsePlugin for HistogramPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+
+    fn parse_event(&mut self, event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        let tid = event.event()?.metadata.tid;
+
+        match self.histogram.lookup(&tid) {
+            Some(mut entry) => *entry.count += 1,
+            None => {
+                let mut entry = self.histogram.create_entry()?;
+                *entry.number = tid;
+                *entry.count = 1;
+                self.histogram.insert(&tid, entry);
+            }
+        }
+
+        match self.unkeyed.lookup(&tid) {
+            Some(mut entry) => *entry.count += 1,
+            None => {
+                let mut entry = self.unkeyed.create_entry()?;
+                *entry.count = 1;
+                self.unkeyed.insert(&tid, entry);
+            }
+        }
+
+        let source = CString::from(c"syscall");
+        match self.sources.lookup(&source) {
+            Some(mut entry) => *entry.count += 1,
+            None => {
+                let mut entry = self.sources.create_entry()?;
+                *entry.count = 1;
+                self.sources.insert(&source, entry);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+static_plugin!(HISTOGRAM_PLUGIN = HistogramPlugin);
+
+/// Exports the `histogram`, `unkeyed` and `sources` tables (see the module docs)
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = HISTOGRAM_PLUGIN;
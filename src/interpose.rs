@@ -0,0 +1,1046 @@
+//! Plugin API interposition
+//!
+//! The native runner calls plugins directly through their `plugin_api` vtables, so to see
+//! what happens between the framework and a plugin, the native driver can register a copy
+//! of each vtable whose entry points forward to the original ones. Every plugin of the
+//! native driver is registered this way, while tracing (see
+//! [`NativeTestDriver::enable_tracing`](crate::native::NativeTestDriver::enable_tracing))
+//! only decides whether the calls get recorded.
+//!
+//! Entry points taking a plugin state get a [`Wrapped`] state instead, which holds the
+//! original vtable and state. `get_init_schema` and `init` run before there's any state,
+//! so they pick the original vtable from a thread-local, set up by [`register`] around
+//! the actual plugin registration. Entry points without a plugin state are copied as-is,
+//! and any other entry point (e.g. one added in a newer API version) is left out, since
+//! it would get a wrapped state it doesn't know about.
+//!
+//! Besides tracing, the interposed entry points check every event passed to a plugin
+//! against the plugin's declarations (see [`crate::filter`]). `init` also replaces
+//! the logging callback, to capture log messages (see [`crate::logs`]), and
+//! `set_async_event_handler` puts the harness between the plugin and the runner's
+//! async event handler, so the driver can hold back and count async events, and
+//! check them against the plugin's declarations (see [`crate::async_events`]).
+
+use crate::async_events::{
+    AsyncDeclarations, AsyncViolation, AsyncViolationKind, ExpectedAsyncEvent,
+};
+use crate::filter::{Declarations, EventFilter, FilterViolation, ViolationKind};
+use crate::logs::{LogRecord, LogSeverity};
+use crate::open_params::OpenParam;
+use crate::trace::{ApiCall, CallResult, TraceRecord};
+use falco_plugin::anyhow;
+use falco_plugin::api::{
+    plugin_api, plugin_api__bindgen_ty_1, plugin_api__bindgen_ty_2, plugin_api__bindgen_ty_3,
+    plugin_api__bindgen_ty_4, plugin_api__bindgen_ty_5, ss_instance_t,
+    ss_plugin_async_event_handler_t, ss_plugin_capture_listen_input, ss_plugin_event,
+    ss_plugin_event_input, ss_plugin_event_parse_input, ss_plugin_field_extract_input,
+    ss_plugin_init_input, ss_plugin_log_fn_t, ss_plugin_log_severity, ss_plugin_metric,
+    ss_plugin_owner_t, ss_plugin_rc, ss_plugin_rc_SS_PLUGIN_EOF, ss_plugin_rc_SS_PLUGIN_FAILURE,
+    ss_plugin_rc_SS_PLUGIN_SUCCESS, ss_plugin_schema_type, ss_plugin_set_config_input, ss_plugin_t,
+};
+use falco_plugin::event::events::types::PPME_ASYNCEVENT_E;
+use falco_plugin::event::events::RawEvent;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Lock `mutex`, even if a thread panicked while holding it
+///
+/// Most of the locking happens in entry points called from C, which must not panic,
+/// and the state behind the locks stays consistent anyway.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The (non-null) async event handler provided by the runner
+type AsyncHandlerFn = unsafe extern "C" fn(
+    *mut ss_plugin_owner_t,
+    *const ss_plugin_event,
+    *mut c_char,
+) -> ss_plugin_rc;
+
+/// An async event on its way to the runner
+struct AsyncDelivery {
+    owner: usize,
+    handler: AsyncHandlerFn,
+    event: Vec<u8>,
+    expected: ExpectedAsyncEvent,
+}
+
+impl AsyncDelivery {
+    /// Pass the event on to the runner
+    fn deliver(&self) -> ss_plugin_rc {
+        let mut err = [0 as c_char; 1024];
+        // SAFETY: the handler was provided by the runner for this owner, and the event
+        // is a copy of a complete event made when the plugin emitted it
+        unsafe {
+            (self.handler)(
+                self.owner as *mut ss_plugin_owner_t,
+                self.event.as_ptr() as *const ss_plugin_event,
+                err.as_mut_ptr(),
+            )
+        }
+    }
+}
+
+#[derive(Default)]
+struct AsyncState {
+    hold: bool,
+    held: Vec<AsyncDelivery>,
+    /// The number of events handed to the runner so far
+    delivered: u64,
+}
+
+/// State shared between the driver and all the plugins it registered
+#[derive(Default)]
+pub(crate) struct Shared {
+    tracing: AtomicBool,
+    trace: Mutex<Vec<TraceRecord>>,
+    plugins: Mutex<Vec<(String, Declarations)>>,
+    violations: Mutex<Vec<FilterViolation>>,
+    logs: Mutex<Vec<LogRecord>>,
+    async_state: Mutex<AsyncState>,
+    async_delivered: Condvar,
+    /// Events handed to the runner, in order, to be checked once they come out
+    expected_async: Mutex<VecDeque<ExpectedAsyncEvent>>,
+    async_violations: Mutex<Vec<AsyncViolation>>,
+    /// Passed to `open` instead of whatever the runner passes
+    open_params: Mutex<Option<CString>>,
+    /// The sources whose plugin ran out of events
+    ended_sources: Mutex<Vec<String>>,
+}
+
+impl Shared {
+    pub(crate) fn set_tracing(&self, enabled: bool) {
+        self.tracing.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn trace(&self) -> Vec<TraceRecord> {
+        lock(&self.trace).clone()
+    }
+
+    pub(crate) fn take_trace(&self) -> Vec<TraceRecord> {
+        std::mem::take(&mut *lock(&self.trace))
+    }
+
+    pub(crate) fn set_open_params(&self, params: Option<CString>) {
+        *lock(&self.open_params) = params;
+    }
+
+    /// The open parameters `plugin` listed
+    pub(crate) fn advertised_open_params(&self, plugin: &str) -> anyhow::Result<Vec<OpenParam>> {
+        let plugins = lock(&self.plugins);
+        let Some((_, decl)) = plugins.iter().find(|(name, _)| name == plugin) else {
+            anyhow::bail!("no plugin called {} registered", plugin);
+        };
+        match &decl.open_params {
+            Some(Ok(params)) => Ok(params.clone()),
+            Some(Err(e)) => anyhow::bail!("{} failed to list its open parameters: {}", plugin, e),
+            None => anyhow::bail!("{} can't list open parameters", plugin),
+        }
+    }
+
+    /// Find the plugin providing `field` (with any argument stripped)
+    pub(crate) fn field_owner(&self, field: &str) -> Option<(String, Option<EventFilter>)> {
+        let field = field.split_once('[').map_or(field, |(name, _)| name);
+        let plugins = lock(&self.plugins);
+        plugins
+            .iter()
+            .find(|(_, decl)| decl.fields.iter().any(|f| f == field))
+            .map(|(name, decl)| (name.clone(), decl.extract.clone()))
+    }
+
+    pub(crate) fn add_violation(&self, violation: FilterViolation) {
+        lock(&self.violations).push(violation);
+    }
+
+    pub(crate) fn violations(&self) -> Vec<FilterViolation> {
+        lock(&self.violations).clone()
+    }
+
+    pub(crate) fn logs(&self) -> Vec<LogRecord> {
+        lock(&self.logs).clone()
+    }
+
+    pub(crate) fn take_logs(&self) -> Vec<LogRecord> {
+        std::mem::take(&mut *lock(&self.logs))
+    }
+
+    /// Keep async events in the harness until released (or pass them on right away)
+    pub(crate) fn set_hold_async(&self, hold: bool) {
+        lock(&self.async_state).hold = hold;
+    }
+
+    /// Pass all held async events on to the runner, returning how many there were
+    pub(crate) fn release_async(&self) -> usize {
+        let held = std::mem::take(&mut lock(&self.async_state).held);
+        for delivery in &held {
+            self.deliver_async(delivery);
+        }
+        held.len()
+    }
+
+    fn deliver_async(&self, delivery: &AsyncDelivery) -> ss_plugin_rc {
+        // the capture may return the event before `deliver` does, so it has to be
+        // expected by then
+        lock(&self.expected_async).push_back(delivery.expected.clone());
+        let rc = delivery.deliver();
+        if rc == ss_plugin_rc_SS_PLUGIN_SUCCESS {
+            lock(&self.async_state).delivered += 1;
+            self.async_delivered.notify_all();
+        } else {
+            lock(&self.expected_async).pop_back();
+        }
+        rc
+    }
+
+    /// Whether the plugin of `source` ran out of events, so the runner won't return
+    /// any more of them
+    pub(crate) fn source_ended(&self, source: &str) -> bool {
+        lock(&self.ended_sources).iter().any(|s| s == source)
+    }
+
+    /// The next async event we expect to come out of the capture
+    pub(crate) fn next_expected_async(&self) -> Option<ExpectedAsyncEvent> {
+        lock(&self.expected_async).pop_front()
+    }
+
+    pub(crate) fn add_async_violations(&self, violations: Vec<AsyncViolation>) {
+        lock(&self.async_violations).extend(violations);
+    }
+
+    pub(crate) fn async_violations(&self) -> Vec<AsyncViolation> {
+        lock(&self.async_violations).clone()
+    }
+
+    /// The number of async events handed to the runner so far
+    pub(crate) fn async_delivered(&self) -> u64 {
+        lock(&self.async_state).delivered
+    }
+
+    /// Wait until more than `seen` async events have been handed to the runner,
+    /// for at most `timeout`
+    pub(crate) fn wait_async(&self, seen: u64, timeout: Duration) {
+        let state = lock(&self.async_state);
+        let _ = self
+            .async_delivered
+            .wait_timeout_while(state, timeout, |state| state.delivered <= seen)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Run `f`, recording the call described by `describe` if tracing is enabled
+    ///
+    /// Messages logged during the call are attributed to `plugin` (see [`log_fn`]).
+    fn traced<T>(
+        &self,
+        plugin: &str,
+        f: impl FnOnce() -> T,
+        describe: impl FnOnce(&T) -> (ApiCall, CallResult),
+    ) -> T {
+        let _call = CallScope::enter(Caller {
+            plugin: plugin.to_string(),
+            shared: self as *const Shared as usize,
+        });
+        if !self.tracing.load(Ordering::Relaxed) {
+            return f();
+        }
+
+        let start = Instant::now();
+        let ret = f();
+        let duration = start.elapsed();
+
+        let (call, result) = describe(&ret);
+        lock(&self.trace).push(TraceRecord {
+            plugin: plugin.to_string(),
+            call,
+            duration,
+            result,
+        });
+        ret
+    }
+}
+
+/// The plugin being registered on this thread
+struct Pending {
+    api: &'static plugin_api,
+    shared: Arc<Shared>,
+}
+
+thread_local! {
+    static PENDING: RefCell<Option<Pending>> = const { RefCell::new(None) };
+}
+
+fn pending() -> Option<(&'static plugin_api, Arc<Shared>)> {
+    PENDING.with_borrow(|p| p.as_ref().map(|p| (p.api, p.shared.clone())))
+}
+
+/// Where to send log messages from a plugin, identified by its owner pointer
+struct LogTarget {
+    owner: usize,
+    plugin: String,
+    shared: Weak<Shared>,
+    original: ss_plugin_log_fn_t,
+}
+
+impl LogTarget {
+    fn is(&self, caller: &Caller) -> bool {
+        // a dropped driver's address may be reused by a new one
+        self.plugin == caller.plugin
+            && self.shared.as_ptr() as usize == caller.shared
+            && self.shared.strong_count() > 0
+    }
+}
+
+// The logging callback may be called from any thread (e.g. by async event workers),
+// so the owners are tracked globally
+static LOG_TARGETS: Mutex<Vec<LogTarget>> = Mutex::new(Vec::new());
+
+/// A plugin the current thread runs code of, identified by its name and driver
+/// (the address of its [`Shared`])
+#[derive(Clone)]
+struct Caller {
+    plugin: String,
+    shared: usize,
+}
+
+thread_local! {
+    /// The plugin called by the interposer on this thread, if any
+    static CALL: RefCell<Option<Caller>> = const { RefCell::new(None) };
+    /// The plugin this thread belongs to, if it emitted async events
+    static THREAD: RefCell<Option<Caller>> = const { RefCell::new(None) };
+}
+
+/// Sets [`CALL`] until dropped
+struct CallScope(Option<Caller>);
+
+impl CallScope {
+    fn enter(caller: Caller) -> Self {
+        Self(CALL.replace(Some(caller)))
+    }
+}
+
+impl Drop for CallScope {
+    fn drop(&mut self) {
+        CALL.set(self.0.take());
+    }
+}
+
+/// The plugin the current thread runs code of, if known
+fn caller() -> Option<Caller> {
+    CALL.with_borrow(Clone::clone)
+        .or_else(|| THREAD.with_borrow(Clone::clone))
+}
+
+fn add_log_target(target: LogTarget) {
+    let mut targets = lock(&LOG_TARGETS);
+    targets.retain(|t| t.owner != target.owner && t.shared.strong_count() > 0);
+    targets.push(target);
+}
+
+/// The logging callback handed to the interposed plugins
+///
+/// Records go to the plugin the thread runs code of (see [`crate::logs`]), not to
+/// `owner`: the SDK passes the owner of the plugin initialized last.
+unsafe extern "C" fn log_fn(
+    owner: *mut ss_plugin_owner_t,
+    component: *const c_char,
+    msg: *const c_char,
+    sev: ss_plugin_log_severity,
+) {
+    let (target, owner, original) = {
+        let targets = lock(&LOG_TARGETS);
+        match caller().and_then(|c| targets.iter().find(|t| t.is(&c))) {
+            Some(t) => (
+                Some((t.plugin.clone(), t.shared.upgrade())),
+                t.owner as *mut ss_plugin_owner_t,
+                t.original,
+            ),
+            None => match targets.iter().find(|t| t.owner == owner as usize) {
+                Some(t) => (None, owner, t.original),
+                None => return,
+            },
+        }
+    };
+
+    if let Some((plugin, Some(shared))) = target {
+        let component = (!component.is_null())
+            .then(|| CStr::from_ptr(component).to_string_lossy().into_owned());
+        let message = if msg.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(msg).to_string_lossy().into_owned()
+        };
+        lock(&shared.logs).push(LogRecord {
+            plugin,
+            component,
+            severity: LogSeverity::from_raw(sev),
+            message,
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    if let Some(original) = original {
+        original(owner, component, msg, sev);
+    }
+}
+
+/// Where to send async events from a plugin, identified by its owner pointer
+struct AsyncTarget {
+    owner: usize,
+    plugin: String,
+    decl: AsyncDeclarations,
+    shared: Weak<Shared>,
+    handler: AsyncHandlerFn,
+}
+
+// Async events are emitted from the plugins' own threads, so just like with logging,
+// the owners are tracked globally
+static ASYNC_TARGETS: Mutex<Vec<AsyncTarget>> = Mutex::new(Vec::new());
+
+unsafe extern "C" fn async_handler(
+    owner: *mut ss_plugin_owner_t,
+    evt: *const ss_plugin_event,
+    err: *mut c_char,
+) -> ss_plugin_rc {
+    let target = {
+        let targets = lock(&ASYNC_TARGETS);
+        targets.iter().find(|t| t.owner == owner as usize).map(|t| {
+            (
+                t.plugin.clone(),
+                t.decl.clone(),
+                t.shared.upgrade(),
+                t.handler,
+            )
+        })
+    };
+    let Some((plugin, decl, shared, handler)) = target else {
+        return ss_plugin_rc_SS_PLUGIN_FAILURE;
+    };
+    let Some(shared) = shared else {
+        return handler(owner, evt, err);
+    };
+
+    // outside of calls into the plugin, this is one of its own threads, so whatever
+    // it logs is the plugin's too
+    if CALL.with_borrow(Option::is_none) {
+        THREAD.set(Some(Caller {
+            plugin: plugin.clone(),
+            shared: Arc::as_ptr(&shared) as usize,
+        }));
+    }
+
+    let (name, plugin_id) = match RawEvent::from_ptr(evt as *const u8)
+        .ok()
+        .and_then(|raw| raw.load::<PPME_ASYNCEVENT_E>().ok())
+    {
+        Some(event) => (
+            event.params.name.map(|n| n.to_string_lossy().into_owned()),
+            event.params.plugin_id,
+        ),
+        None => {
+            shared.add_async_violations(vec![AsyncViolation {
+                plugin,
+                name: None,
+                kind: AsyncViolationKind::Malformed,
+            }]);
+            write_error(err, "malformed async event");
+            return ss_plugin_rc_SS_PLUGIN_FAILURE;
+        }
+    };
+
+    // Falco refuses events the plugin didn't declare
+    let name = match name {
+        Some(name) if decl.names.contains(&name) => name,
+        name => {
+            write_error(err, &format!("undeclared async event {:?}", name));
+            shared.add_async_violations(vec![AsyncViolation {
+                plugin,
+                name,
+                kind: AsyncViolationKind::UndeclaredName,
+            }]);
+            return ss_plugin_rc_SS_PLUGIN_FAILURE;
+        }
+    };
+
+    // the event is only valid during this call, so keep a copy in case it's held back
+    let len = std::ptr::addr_of!((*evt).len).read_unaligned() as usize;
+    let delivery = AsyncDelivery {
+        owner: owner as usize,
+        handler,
+        event: std::slice::from_raw_parts(evt as *const u8, len).to_vec(),
+        expected: ExpectedAsyncEvent {
+            plugin,
+            name,
+            plugin_id,
+            sources: decl.sources,
+        },
+    };
+
+    let mut state = lock(&shared.async_state);
+    if state.hold {
+        state.held.push(delivery);
+        return ss_plugin_rc_SS_PLUGIN_SUCCESS;
+    }
+    drop(state);
+
+    shared.deliver_async(&delivery)
+}
+
+/// Copy an error message to a framework-provided error buffer
+///
+/// # Safety
+/// `err` must be null or point to a buffer of (at least) 1024 bytes
+unsafe fn write_error(err: *mut c_char, msg: &str) {
+    if err.is_null() {
+        return;
+    }
+
+    let len = msg.len().min(1023);
+    std::ptr::copy_nonoverlapping(msg.as_ptr() as *const c_char, err, len);
+    *err.add(len) = 0;
+}
+
+/// The state we hand to the framework in place of the plugin's own
+struct Wrapped {
+    api: &'static plugin_api,
+    plugin: *mut ss_plugin_t,
+    name: String,
+    decl: Declarations,
+    shared: Arc<Shared>,
+}
+
+impl Wrapped {
+    /// Record a violation if `filter` doesn't accept the event
+    ///
+    /// # Safety
+    /// `evt` must point to a valid event input
+    unsafe fn check_event(
+        &self,
+        filter: Option<&EventFilter>,
+        evt: *const ss_plugin_event_input,
+        kind: ViolationKind,
+    ) {
+        let (event_type, evt_num) = event_info(evt);
+        let source = event_source(evt);
+        if filter.is_some_and(|f| !f.accepts(event_type, source.as_deref())) {
+            self.shared.add_violation(FilterViolation {
+                plugin: self.name.clone(),
+                kind,
+                event_type,
+                source,
+                evt_num,
+            });
+        }
+    }
+}
+
+/// # Safety
+/// `s` must be a state returned by [`init`]
+unsafe fn wrapped<'a>(s: *mut ss_plugin_t) -> &'a Wrapped {
+    &*(s as *const Wrapped)
+}
+
+/// The original entry point, which we only interpose if it exists
+fn original<F>(f: Option<F>) -> F {
+    f.expect("interposed a missing entry point")
+}
+
+fn plugin_name(api: &plugin_api) -> String {
+    let Some(get_name) = api.get_name else {
+        return String::from("<unnamed>");
+    };
+
+    // SAFETY: get_name returns a static NUL-terminated string (or null)
+    let name = unsafe { get_name() };
+    if name.is_null() {
+        return String::from("<unnamed>");
+    }
+    unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Get the event type and number from an event input
+///
+/// # Safety
+/// `evt` must point to a valid event input
+unsafe fn event_info(evt: *const ss_plugin_event_input) -> (u16, u64) {
+    let input = &*evt;
+    // the event header is packed, so the fields may be unaligned
+    let event_type = std::ptr::addr_of!((*input.evt).type_).read_unaligned();
+    (event_type, input.evtnum)
+}
+
+/// Get the source name from an event input
+///
+/// # Safety
+/// `evt` must point to a valid event input
+unsafe fn event_source(evt: *const ss_plugin_event_input) -> Option<String> {
+    let input = &*evt;
+    (!input.evtsrc.is_null()).then(|| CStr::from_ptr(input.evtsrc).to_string_lossy().into_owned())
+}
+
+/// Get the names of the fields requested in an extraction call, as `name` or `name[arg]`
+///
+/// # Safety
+/// `input` must point to a valid field extraction input
+unsafe fn requested_fields(input: *const ss_plugin_field_extract_input) -> Vec<String> {
+    let input = &*input;
+    if input.fields.is_null() {
+        return Vec::new();
+    }
+
+    std::slice::from_raw_parts(input.fields, input.num_fields as usize)
+        .iter()
+        .map(|f| {
+            let name = CStr::from_ptr(f.field).to_string_lossy();
+            if !f.arg_present {
+                name.into_owned()
+            } else if f.arg_key.is_null() {
+                format!("{}[{}]", name, f.arg_index)
+            } else {
+                format!("{}[{}]", name, CStr::from_ptr(f.arg_key).to_string_lossy())
+            }
+        })
+        .collect()
+}
+
+unsafe extern "C" fn get_init_schema(schema_type: *mut ss_plugin_schema_type) -> *const c_char {
+    let Some((api, shared)) = pending() else {
+        return std::ptr::null();
+    };
+    let inner = original(api.get_init_schema);
+
+    shared.traced(
+        &plugin_name(api),
+        || inner(schema_type),
+        |_| (ApiCall::GetInitSchema, CallResult::Success),
+    )
+}
+
+unsafe extern "C" fn init(
+    input: *const ss_plugin_init_input,
+    rc: *mut ss_plugin_rc,
+) -> *mut ss_plugin_t {
+    let Some((api, shared)) = pending() else {
+        *rc = ss_plugin_rc_SS_PLUGIN_FAILURE;
+        return std::ptr::null_mut();
+    };
+    let inner = original(api.init);
+    let name = plugin_name(api);
+
+    let mut input = *input;
+    add_log_target(LogTarget {
+        owner: input.owner as usize,
+        plugin: name.clone(),
+        shared: Arc::downgrade(&shared),
+        original: input.log_fn,
+    });
+    input.log_fn = Some(log_fn);
+
+    let plugin = shared.traced(
+        &name,
+        || inner(&input, rc),
+        |_| (ApiCall::Init, CallResult::from_rc(*rc)),
+    );
+    if plugin.is_null() {
+        return plugin;
+    }
+
+    // even a failed init may return a state, for get_last_error and destroy,
+    // but only a successful one can tell us what it handles
+    let decl = if *rc == ss_plugin_rc_SS_PLUGIN_SUCCESS {
+        let decl = Declarations::query(api, plugin);
+        lock(&shared.plugins).push((name.clone(), decl.clone()));
+        decl
+    } else {
+        Declarations::default()
+    };
+
+    let wrapped = Wrapped {
+        api,
+        plugin,
+        name,
+        decl,
+        shared,
+    };
+    Box::into_raw(Box::new(wrapped)) as *mut ss_plugin_t
+}
+
+unsafe extern "C" fn destroy(s: *mut ss_plugin_t) {
+    let w = Box::from_raw(s as *mut Wrapped);
+    if let Some(inner) = w.api.destroy {
+        w.shared.traced(
+            &w.name,
+            || inner(w.plugin),
+            |_| (ApiCall::Destroy, CallResult::Success),
+        );
+    }
+}
+
+unsafe extern "C" fn get_last_error(s: *mut ss_plugin_t) -> *const c_char {
+    let w = wrapped(s);
+    match w.api.get_last_error {
+        Some(inner) => inner(w.plugin),
+        None => std::ptr::null(),
+    }
+}
+
+unsafe extern "C" fn set_config(
+    s: *mut ss_plugin_t,
+    input: *const ss_plugin_set_config_input,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.set_config);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, input),
+        |rc| (ApiCall::SetConfig, CallResult::from_rc(*rc)),
+    )
+}
+
+unsafe extern "C" fn get_metrics(
+    s: *mut ss_plugin_t,
+    num_metrics: *mut u32,
+) -> *mut ss_plugin_metric {
+    let w = wrapped(s);
+    let inner = original(w.api.get_metrics);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, num_metrics),
+        |_| (ApiCall::GetMetrics, CallResult::Success),
+    )
+}
+
+unsafe extern "C" fn open(
+    s: *mut ss_plugin_t,
+    params: *const c_char,
+    rc: *mut ss_plugin_rc,
+) -> *mut ss_instance_t {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.open);
+    // keep the override alive until `open` returns
+    let params_override = lock(&w.shared.open_params).clone();
+    let params = params_override.as_ref().map_or(params, |p| p.as_ptr());
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, params, rc),
+        |_| {
+            let params =
+                (!params.is_null()).then(|| CStr::from_ptr(params).to_string_lossy().into_owned());
+            (ApiCall::Open { params }, CallResult::from_rc(*rc))
+        },
+    )
+}
+
+unsafe extern "C" fn close(s: *mut ss_plugin_t, h: *mut ss_instance_t) {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.close);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, h),
+        |_| (ApiCall::Close, CallResult::Success),
+    )
+}
+
+unsafe extern "C" fn list_open_params(s: *mut ss_plugin_t, rc: *mut ss_plugin_rc) -> *const c_char {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.list_open_params);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, rc),
+        |_| (ApiCall::ListOpenParams, CallResult::from_rc(*rc)),
+    )
+}
+
+unsafe extern "C" fn get_progress(
+    s: *mut ss_plugin_t,
+    h: *mut ss_instance_t,
+    progress_pct: *mut u32,
+) -> *const c_char {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.get_progress);
+    inner(w.plugin, h, progress_pct)
+}
+
+unsafe extern "C" fn event_to_string(
+    s: *mut ss_plugin_t,
+    evt: *const ss_plugin_event_input,
+) -> *const c_char {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.event_to_string);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, evt),
+        |ret| {
+            let (event_type, _) = event_info(evt);
+            (
+                ApiCall::EventToString { event_type },
+                CallResult::from_ptr(*ret),
+            )
+        },
+    )
+}
+
+unsafe extern "C" fn next_batch(
+    s: *mut ss_plugin_t,
+    h: *mut ss_instance_t,
+    nevts: *mut u32,
+    evts: *mut *mut *mut ss_plugin_event,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_1.next_batch);
+    let rc = w.shared.traced(
+        &w.name,
+        || inner(w.plugin, h, nevts, evts),
+        |rc| {
+            let batch_size = if nevts.is_null() { 0 } else { *nevts };
+            (ApiCall::NextBatch { batch_size }, CallResult::from_rc(*rc))
+        },
+    );
+    if rc == ss_plugin_rc_SS_PLUGIN_EOF {
+        if let Some(get_event_source) = w.api.__bindgen_anon_1.get_event_source {
+            let source = CStr::from_ptr(get_event_source()).to_string_lossy();
+            lock(&w.shared.ended_sources).push(source.into_owned());
+        }
+    }
+    rc
+}
+
+unsafe extern "C" fn get_extract_event_types(numtypes: *mut u32, s: *mut ss_plugin_t) -> *mut u16 {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_2.get_extract_event_types);
+    inner(numtypes, w.plugin)
+}
+
+unsafe extern "C" fn extract_fields(
+    s: *mut ss_plugin_t,
+    evt: *const ss_plugin_event_input,
+    input: *const ss_plugin_field_extract_input,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_2.extract_fields);
+    w.check_event(
+        w.decl.extract.as_ref(),
+        evt,
+        ViolationKind::UndeclaredExtract,
+    );
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, evt, input),
+        |rc| {
+            let (event_type, evt_num) = event_info(evt);
+            let call = ApiCall::ExtractFields {
+                event_type,
+                evt_num,
+                fields: requested_fields(input),
+            };
+            (call, CallResult::from_rc(*rc))
+        },
+    )
+}
+
+unsafe extern "C" fn get_parse_event_types(numtypes: *mut u32, s: *mut ss_plugin_t) -> *mut u16 {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_3.get_parse_event_types);
+    inner(numtypes, w.plugin)
+}
+
+unsafe extern "C" fn parse_event(
+    s: *mut ss_plugin_t,
+    evt: *const ss_plugin_event_input,
+    input: *const ss_plugin_event_parse_input,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_3.parse_event);
+    w.check_event(w.decl.parse.as_ref(), evt, ViolationKind::UndeclaredParse);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, evt, input),
+        |rc| {
+            let (event_type, evt_num) = event_info(evt);
+            (
+                ApiCall::ParseEvent {
+                    event_type,
+                    evt_num,
+                },
+                CallResult::from_rc(*rc),
+            )
+        },
+    )
+}
+
+unsafe extern "C" fn set_async_event_handler(
+    s: *mut ss_plugin_t,
+    owner: *mut ss_plugin_owner_t,
+    handler: ss_plugin_async_event_handler_t,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_4.set_async_event_handler);
+
+    // route the events through the harness
+    let interposed: ss_plugin_async_event_handler_t = match handler {
+        Some(handler) => {
+            let mut targets = lock(&ASYNC_TARGETS);
+            targets.retain(|t| t.owner != owner as usize && t.shared.strong_count() > 0);
+            targets.push(AsyncTarget {
+                owner: owner as usize,
+                plugin: w.name.clone(),
+                decl: w.decl.async_events.clone().unwrap_or_default(),
+                shared: Arc::downgrade(&w.shared),
+                handler,
+            });
+            Some(async_handler)
+        }
+        None => None,
+    };
+
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, owner, interposed),
+        |rc| {
+            // a null handler asks the plugin to stop emitting events
+            let call = match handler {
+                Some(_) => ApiCall::AsyncStart,
+                None => ApiCall::AsyncStop,
+            };
+            (call, CallResult::from_rc(*rc))
+        },
+    )
+}
+
+unsafe extern "C" fn capture_open(
+    s: *mut ss_plugin_t,
+    input: *const ss_plugin_capture_listen_input,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_5.capture_open);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, input),
+        |rc| (ApiCall::CaptureOpen, CallResult::from_rc(*rc)),
+    )
+}
+
+unsafe extern "C" fn capture_close(
+    s: *mut ss_plugin_t,
+    input: *const ss_plugin_capture_listen_input,
+) -> ss_plugin_rc {
+    let w = wrapped(s);
+    let inner = original(w.api.__bindgen_anon_5.capture_close);
+    w.shared.traced(
+        &w.name,
+        || inner(w.plugin, input),
+        |rc| (ApiCall::CaptureClose, CallResult::from_rc(*rc)),
+    )
+}
+
+/// Build a vtable forwarding to `api`, keeping the same set of capabilities
+// the bindings may have more entry points than the ones listed here, or not
+#[allow(clippy::needless_update)]
+fn wrap_api(api: &'static plugin_api) -> plugin_api {
+    let sourcing = &api.__bindgen_anon_1;
+    let extraction = &api.__bindgen_anon_2;
+    let parsing = &api.__bindgen_anon_3;
+    let async_events = &api.__bindgen_anon_4;
+    let capture_listen = &api.__bindgen_anon_5;
+
+    // SAFETY (all the `zeroed` calls below): the vtable only holds optional function
+    // pointers, for which all zeroes is a valid value (`None`)
+    plugin_api {
+        get_required_api_version: api.get_required_api_version,
+        get_version: api.get_version,
+        get_name: api.get_name,
+        get_description: api.get_description,
+        get_contact: api.get_contact,
+        get_init_schema: api.get_init_schema.map(|_| get_init_schema as _),
+        init: api.init.map(|_| init as _),
+        destroy: api.destroy.map(|_| destroy as _),
+        get_last_error: Some(get_last_error),
+        set_config: api.set_config.map(|_| set_config as _),
+        get_metrics: api.get_metrics.map(|_| get_metrics as _),
+        __bindgen_anon_1: plugin_api__bindgen_ty_1 {
+            get_id: sourcing.get_id,
+            get_event_source: sourcing.get_event_source,
+            open: sourcing.open.map(|_| open as _),
+            close: sourcing.close.map(|_| close as _),
+            list_open_params: sourcing.list_open_params.map(|_| list_open_params as _),
+            get_progress: sourcing.get_progress.map(|_| get_progress as _),
+            event_to_string: sourcing.event_to_string.map(|_| event_to_string as _),
+            next_batch: sourcing.next_batch.map(|_| next_batch as _),
+            ..unsafe { std::mem::zeroed() }
+        },
+        __bindgen_anon_2: plugin_api__bindgen_ty_2 {
+            get_extract_event_sources: extraction.get_extract_event_sources,
+            get_fields: extraction.get_fields,
+            get_extract_event_types: extraction
+                .get_extract_event_types
+                .map(|_| get_extract_event_types as _),
+            extract_fields: extraction.extract_fields.map(|_| extract_fields as _),
+            ..unsafe { std::mem::zeroed() }
+        },
+        __bindgen_anon_3: plugin_api__bindgen_ty_3 {
+            get_parse_event_sources: parsing.get_parse_event_sources,
+            get_parse_event_types: parsing
+                .get_parse_event_types
+                .map(|_| get_parse_event_types as _),
+            parse_event: parsing.parse_event.map(|_| parse_event as _),
+            ..unsafe { std::mem::zeroed() }
+        },
+        __bindgen_anon_4: plugin_api__bindgen_ty_4 {
+            get_async_event_sources: async_events.get_async_event_sources,
+            get_async_events: async_events.get_async_events,
+            set_async_event_handler: async_events
+                .set_async_event_handler
+                .map(|_| set_async_event_handler as _),
+            ..unsafe { std::mem::zeroed() }
+        },
+        __bindgen_anon_5: plugin_api__bindgen_ty_5 {
+            capture_open: capture_listen.capture_open.map(|_| capture_open as _),
+            capture_close: capture_listen.capture_close.map(|_| capture_close as _),
+            ..unsafe { std::mem::zeroed() }
+        },
+        ..unsafe { std::mem::zeroed() }
+    }
+}
+
+// The runner needs 'static vtables, so the interposed vtables are leaked, but only once
+// per original vtable (by address)
+static WRAPPED_APIS: Mutex<Vec<(usize, &'static plugin_api)>> = Mutex::new(Vec::new());
+
+/// The interposed vtable for `api`
+fn wrapped_api(api: &'static plugin_api) -> &'static plugin_api {
+    let mut apis = lock(&WRAPPED_APIS);
+    let addr = api as *const plugin_api as usize;
+    if let Some((_, wrapped)) = apis.iter().find(|(a, _)| *a == addr) {
+        return wrapped;
+    }
+
+    let wrapped: &'static plugin_api = Box::leak(Box::new(wrap_api(api)));
+    apis.push((addr, wrapped));
+    wrapped
+}
+
+/// Register a plugin through an interposed vtable
+///
+/// `register` gets the vtable to pass to the runner and must register the plugin
+/// synchronously, on the calling thread.
+pub(crate) fn register<T>(
+    api: &'static plugin_api,
+    shared: &Arc<Shared>,
+    register: impl FnOnce(&'static plugin_api) -> T,
+) -> T {
+    let wrapped = wrapped_api(api);
+    PENDING.with_borrow_mut(|p| {
+        *p = Some(Pending {
+            api,
+            shared: shared.clone(),
+        })
+    });
+    let ret = register(wrapped);
+    PENDING.with_borrow_mut(|p| *p = None);
+    ret
+}
@@ -0,0 +1,348 @@
+//! JSON representation of captured events
+//!
+//! [`to_json`] turns an event into a stable JSON object, with every parameter
+//! encoded according to its type:
+//!
+//! ```json
+//! {
+//!   "num": 1, "ts": 1, "tid": 1, "type": "open", "type_id": 3, "dir": "<",
+//!   "source": "syscall", "plugin_id": null,
+//!   "params": [
+//!     {"name": "fd", "type": "fd", "value": 5},
+//!     {"name": "name", "type": "path", "value": "/etc/passwd"},
+//!     {"name": "flags", "type": "flags", "value": ["O_RDWR"], "raw": 3, "width": 4},
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
+//! [`from_json`] parses that back into an encoded event, ready to be added to an
+//! `EventBatch`. Parameters missing from the end of an event (as written by older drivers)
+//! are left out of `"params"`, so that the round trip gives back the exact same bytes.
+//!
+//! Parameters are decoded the same way for every event type (see [`params`]). Events the
+//! SDK can't parse, and events with more parameters than their type defines, are rejected
+//! rather than stored partially.
+
+use crate::event::{event_bytes, event_source, params, raw_event, ParamValue};
+use crate::event_fields::{event_dir, event_type_name};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use falco_plugin::anyhow;
+use falco_plugin::anyhow::Context;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin_runner::Event;
+use serde_json::{json, Map, Value};
+
+/// Size of the event header: ts (u64), tid (u64), len (u32), type (u16), nparams (u32)
+const HEADER_LEN: usize = 26;
+
+/// Plugin and async events use 32-bit parameter lengths, everything else uses 16-bit ones
+fn uses_large_payload(event_type: u16) -> bool {
+    event_type == EventType::PLUGINEVENT_E as u16 || event_type == EventType::ASYNCEVENT_E as u16
+}
+
+fn param_to_json(value: &ParamValue) -> Map<String, Value> {
+    let (ty, value) = match value {
+        ParamValue::U8(v) => ("u8", json!(v)),
+        ParamValue::U16(v) => ("u16", json!(v)),
+        ParamValue::U32(v) => ("u32", json!(v)),
+        ParamValue::U64(v) => ("u64", json!(v)),
+        ParamValue::I32(v) => ("i32", json!(v)),
+        ParamValue::I64(v) => ("i64", json!(v)),
+        ParamValue::Fd(v) => ("fd", json!(v)),
+        ParamValue::Errno(v) => ("errno", json!(v)),
+        ParamValue::Pid(v) => ("pid", json!(v)),
+        ParamValue::Flags { raw, width, names } => {
+            let mut obj = Map::new();
+            obj.insert("type".into(), json!("flags"));
+            obj.insert("value".into(), json!(names));
+            obj.insert("raw".into(), json!(raw));
+            obj.insert("width".into(), json!(width));
+            return obj;
+        }
+        ParamValue::Path(v) => ("path", json!(v)),
+        ParamValue::Str(v) => ("str", json!(v)),
+        ParamValue::Bytes(v) => ("bytes", json!(BASE64.encode(v))),
+        ParamValue::SockAddr { display, raw } | ParamValue::SockTuple { display, raw } => {
+            let ty = match value {
+                ParamValue::SockAddr { .. } => "sockaddr",
+                _ => "socktuple",
+            };
+            let mut obj = Map::new();
+            obj.insert("type".into(), json!(ty));
+            obj.insert("value".into(), json!(display));
+            obj.insert("raw".into(), json!(BASE64.encode(raw)));
+            return obj;
+        }
+    };
+
+    let mut obj = Map::new();
+    obj.insert("type".into(), json!(ty));
+    obj.insert("value".into(), value);
+    obj
+}
+
+/// Serialize a captured event to JSON
+pub fn to_json(event: &Event) -> anyhow::Result<Value> {
+    let raw = raw_event(event)?;
+    let decoded = params(&raw);
+
+    let plugin_id = decoded
+        .as_ref()
+        .and_then(|d| d.get("plugin_id"))
+        .and_then(|p| match p.value {
+            Some(ParamValue::U32(id)) => Some(id),
+            _ => None,
+        });
+
+    let mut obj = Map::new();
+    obj.insert("num".into(), json!(event.evt_num));
+    obj.insert("ts".into(), json!(raw.metadata.ts));
+    obj.insert("tid".into(), json!(raw.metadata.tid));
+    obj.insert("type".into(), json!(event_type_name(&raw)));
+    obj.insert("type_id".into(), json!(raw.event_type));
+    obj.insert("dir".into(), json!(event_dir(&raw)));
+    obj.insert("source".into(), json!(event_source(event)));
+    obj.insert("plugin_id".into(), json!(plugin_id));
+
+    let Some(decoded) = decoded else {
+        anyhow::bail!(
+            "cannot serialize event type {}: the SDK cannot parse it",
+            raw.event_type
+        );
+    };
+    let nparams = raw.nparams as usize;
+    anyhow::ensure!(
+        nparams <= decoded.params.len(),
+        "cannot serialize {} event: it has {} parameters, its type only defines {}",
+        decoded.name,
+        nparams,
+        decoded.params.len()
+    );
+
+    let params: Vec<Value> = decoded.params[..nparams]
+        .iter()
+        .map(|p| {
+            let mut param = match &p.value {
+                Some(value) => param_to_json(value),
+                None => {
+                    let mut obj = Map::new();
+                    obj.insert("value".into(), Value::Null);
+                    obj
+                }
+            };
+            param.insert("name".into(), json!(p.name));
+            Value::Object(param)
+        })
+        .collect();
+    obj.insert("params".into(), Value::Array(params));
+
+    // parameters without a scalar representation are stored as their raw bytes, but
+    // check that nothing got lost on the way anyway
+    let obj = Value::Object(obj);
+    anyhow::ensure!(
+        from_json(&obj)? == event_bytes(event)?,
+        "cannot serialize {} event: its JSON form does not encode back to the same bytes",
+        event_type_name(&raw)
+    );
+
+    Ok(obj)
+}
+
+fn json_u64(value: &Value, what: &str) -> anyhow::Result<u64> {
+    value
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("{} is not an unsigned integer", what))
+}
+
+fn json_i64(value: &Value, what: &str) -> anyhow::Result<i64> {
+    value
+        .as_i64()
+        .ok_or_else(|| anyhow::anyhow!("{} is not an integer", what))
+}
+
+fn json_str<'a>(value: &'a Value, what: &str) -> anyhow::Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a string", what))
+}
+
+/// Encode a single JSON parameter (as produced by [`to_json`]) to its binary form
+fn param_from_json(param: &Value) -> anyhow::Result<Vec<u8>> {
+    let name = param["name"].as_str().unwrap_or("<unnamed>");
+    let value = &param["value"];
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+
+    let ty = json_str(&param["type"], name)?;
+    let buf = match ty {
+        "u8" => u8::try_from(json_u64(value, name)?)?.to_le_bytes().to_vec(),
+        "u16" => u16::try_from(json_u64(value, name)?)?
+            .to_le_bytes()
+            .to_vec(),
+        "u32" => u32::try_from(json_u64(value, name)?)?
+            .to_le_bytes()
+            .to_vec(),
+        "u64" => json_u64(value, name)?.to_le_bytes().to_vec(),
+        "i32" => i32::try_from(json_i64(value, name)?)?
+            .to_le_bytes()
+            .to_vec(),
+        "i64" | "fd" | "errno" | "pid" => json_i64(value, name)?.to_le_bytes().to_vec(),
+        "flags" => {
+            let raw = json_u64(&param["raw"], name)?;
+            let width = json_u64(&param["width"], name)? as usize;
+            anyhow::ensure!(width <= 8, "{}: invalid flags width {}", name, width);
+            raw.to_le_bytes()[..width].to_vec()
+        }
+        "path" | "str" => {
+            let mut buf = json_str(value, name)?.as_bytes().to_vec();
+            buf.push(0);
+            buf
+        }
+        "bytes" => BASE64.decode(json_str(value, name)?)?,
+        "sockaddr" | "socktuple" => BASE64.decode(json_str(&param["raw"], name)?)?,
+        other => anyhow::bail!("{}: unknown parameter type {}", name, other),
+    };
+
+    Ok(buf)
+}
+
+/// Parse a JSON event (as produced by [`to_json`]) back into its binary encoding
+pub fn from_json(value: &Value) -> anyhow::Result<Vec<u8>> {
+    let ts = json_u64(&value["ts"], "ts")?;
+    let tid = json_u64(&value["tid"], "tid")?;
+    let event_type = u16::try_from(json_u64(&value["type_id"], "type_id")?)?;
+    let params = value["params"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("params is not an array"))?;
+    let params = params
+        .iter()
+        .map(param_from_json)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("failed to encode event parameters")?;
+
+    let large_payload = uses_large_payload(event_type);
+    let len_size = if large_payload { 4 } else { 2 };
+    let len = HEADER_LEN + params.len() * len_size + params.iter().map(Vec::len).sum::<usize>();
+
+    let mut buf = Vec::with_capacity(len);
+    buf.extend_from_slice(&ts.to_le_bytes());
+    buf.extend_from_slice(&tid.to_le_bytes());
+    buf.extend_from_slice(&u32::try_from(len)?.to_le_bytes());
+    buf.extend_from_slice(&event_type.to_le_bytes());
+    buf.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    for param in &params {
+        if large_payload {
+            buf.extend_from_slice(&u32::try_from(param.len())?.to_le_bytes());
+        } else {
+            buf.extend_from_slice(&u16::try_from(param.len())?.to_le_bytes());
+        }
+    }
+    for param in &params {
+        buf.extend_from_slice(param);
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, to_json};
+    use crate::event::event_bytes;
+    use crate::native::NativeTestDriver;
+    use crate::replay_source_plugin::{self, i64_param, process_lifecycle, str_param};
+    use crate::{faulty_plugin, network_source_plugin, syscall_source_plugin};
+    use crate::{init_plugin, CapturingTestDriver, TestDriver};
+    use falco_plugin::event::events::types::EventType;
+    use falco_plugin_runner::Event;
+    use std::ffi::CStr;
+
+    fn capture(plugin: &'static falco_plugin::api::plugin_api, config: &CStr) -> Vec<Event> {
+        let (driver, _) = init_plugin::<NativeTestDriver>(plugin, config).unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = driver.next_event() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn round_trip_fixture_events() {
+        let lifecycle = replay_source_plugin::config(&process_lifecycle());
+        let fixtures: [(&'static falco_plugin::api::plugin_api, &CStr); 4] = [
+            (&syscall_source_plugin::PLUGIN, c""),
+            (&network_source_plugin::PLUGIN, c"{}"),
+            (&replay_source_plugin::PLUGIN, &lifecycle),
+            (
+                &faulty_plugin::PLUGIN,
+                c"{\"fault\": \"failure\", \"healthy_events\": 3}",
+            ),
+        ];
+
+        for (plugin, config) in fixtures {
+            let events = capture(plugin, config);
+            assert!(!events.is_empty());
+
+            for event in &events {
+                let json = to_json(event).unwrap();
+                let bytes = from_json(&json).unwrap();
+                assert_eq!(bytes, event_bytes(event).unwrap(), "{}", json);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_any_event_type() {
+        // none of the fixture sources emit mkdir events
+        let events = vec![
+            replay_source_plugin::event(
+                1,
+                1,
+                EventType::SYSCALL_MKDIR_2_E,
+                &[0o755u32.to_le_bytes().to_vec()],
+            ),
+            replay_source_plugin::event(
+                2,
+                1,
+                EventType::SYSCALL_MKDIR_2_X,
+                &[i64_param(0), str_param("/tmp/rustlings")],
+            ),
+        ];
+        let config = replay_source_plugin::config(&events);
+        let events = capture(&replay_source_plugin::PLUGIN, &config);
+        assert_eq!(events.len(), 2);
+
+        for event in &events {
+            let json = to_json(event).unwrap();
+            assert_eq!(json["type"], "mkdir");
+            let bytes = from_json(&json).unwrap();
+            assert_eq!(bytes, event_bytes(event).unwrap(), "{}", json);
+        }
+
+        let json = to_json(&events[1]).unwrap();
+        assert_eq!(json["params"][0]["name"], "res");
+        assert_eq!(json["params"][0]["type"], "errno");
+        assert_eq!(json["params"][1]["name"], "path");
+        assert_eq!(json["params"][1]["value"], "/tmp/rustlings");
+    }
+
+    #[test]
+    fn unknown_params() {
+        // close exit events have a single parameter
+        let events = vec![replay_source_plugin::event(
+            1,
+            1,
+            EventType::SYSCALL_CLOSE_X,
+            &[0i64.to_le_bytes().to_vec(), 0i64.to_le_bytes().to_vec()],
+        )];
+        let config = replay_source_plugin::config(&events);
+        let events = capture(&replay_source_plugin::PLUGIN, &config);
+
+        assert_eq!(events.len(), 1);
+        assert!(to_json(&events[0]).is_err());
+    }
+}
@@ -1,11 +1,48 @@
 use std::ffi::CStr;
 
+pub mod access;
+#[cfg(test)]
+mod async_emitter_plugin;
+pub mod async_events;
 pub mod native;
 
 pub mod common;
+#[cfg(test)]
+mod correlation_plugin;
+pub mod event;
+mod event_fields;
+mod event_params;
+pub mod faulty_plugin;
+pub mod filter;
+#[cfg(test)]
+mod histogram_plugin;
+mod interpose;
+pub mod json;
+pub mod logs;
+pub mod merge;
+#[cfg(test)]
+mod misdeclared_plugin;
+pub mod multi_source;
+pub mod network_source_plugin;
+pub mod open_params;
+pub mod prometheus;
+#[cfg(test)]
+mod replay_source_plugin;
+#[cfg(test)]
+mod shared_state_plugin;
+pub mod snapshot;
+pub mod state;
+#[cfg(test)]
+mod syscall_extract_plugin;
 pub mod syscall_source_plugin;
+#[cfg(test)]
+mod table_access_plugin;
+pub mod tables;
+pub mod threads_table_plugin;
+pub mod trace;
 
 pub use common::*;
+pub use event::EventExt;
 
 pub fn init_plugin<D: TestDriver>(
     api: &'static falco_plugin::api::plugin_api,
@@ -0,0 +1,155 @@
+//! Plugin log capture
+//!
+//! Plugins log through the framework (the SDK forwards `log` records to it), so the native
+//! driver hands them a logging callback of its own and keeps every record it gets,
+//! besides passing it on to the runner. This is done by the interposer.
+//!
+//! The SDK's logger is process-wide, so it can't tell which plugin a record comes from.
+//! The interposer knows which plugin each thread is running, though: every record logged
+//! while it calls into a plugin (initialization, capture, extraction...) goes to that
+//! plugin and its driver, even with other drivers running on other test threads.
+//! The same goes for the plugins' own threads once they have emitted an async event.
+//! Records logged on a plugin's own thread before that aren't captured.
+
+use falco_plugin::api::{
+    ss_plugin_log_severity, ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_CRITICAL,
+    ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_DEBUG, ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_ERROR,
+    ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_FATAL, ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_INFO,
+    ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_NOTICE,
+    ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_TRACE,
+    ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_WARNING,
+};
+use std::time::SystemTime;
+
+/// Log message severity, from the most to the least severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogSeverity {
+    Fatal,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogSeverity {
+    pub(crate) fn from_raw(raw: ss_plugin_log_severity) -> Self {
+        match raw {
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_FATAL => LogSeverity::Fatal,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_CRITICAL => LogSeverity::Critical,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_ERROR => LogSeverity::Error,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_WARNING => LogSeverity::Warning,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_NOTICE => LogSeverity::Notice,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_INFO => LogSeverity::Info,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_DEBUG => LogSeverity::Debug,
+            ss_plugin_log_severity_SS_PLUGIN_LOG_SEV_TRACE => LogSeverity::Trace,
+            // unknown severities are most likely new, more verbose levels
+            _ => LogSeverity::Trace,
+        }
+    }
+}
+
+/// A single log message from a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// The name of the plugin, as reported by `get_name`
+    pub plugin: String,
+    /// The component name passed by the plugin, if any
+    pub component: Option<String>,
+    pub severity: LogSeverity,
+    pub message: String,
+    /// When the harness received the message
+    pub timestamp: SystemTime,
+}
+
+/// Helpers for asserting on captured logs
+pub trait LogsExt {
+    /// All the messages from `plugin`
+    fn from_plugin(&self, plugin: &str) -> Vec<&LogRecord>;
+
+    /// All the messages at `severity` or more severe
+    fn at_least(&self, severity: LogSeverity) -> Vec<&LogRecord>;
+
+    /// Whether any message at `severity` or more severe contains `text`
+    fn contains(&self, severity: LogSeverity, text: &str) -> bool;
+}
+
+impl LogsExt for [LogRecord] {
+    fn from_plugin(&self, plugin: &str) -> Vec<&LogRecord> {
+        self.iter().filter(|r| r.plugin == plugin).collect()
+    }
+
+    fn at_least(&self, severity: LogSeverity) -> Vec<&LogRecord> {
+        self.iter().filter(|r| r.severity <= severity).collect()
+    }
+
+    fn contains(&self, severity: LogSeverity, text: &str) -> bool {
+        self.at_least(severity)
+            .iter()
+            .any(|r| r.message.contains(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogRecord, LogSeverity, LogsExt};
+    use crate::faulty_plugin;
+    use crate::native::NativeTestDriver;
+    use crate::TestDriver;
+    use std::ffi::CStr;
+    use std::sync::Barrier;
+
+    /// Register the faulty plugin with `config`, and get the logs of its driver
+    fn logs(config: &CStr) -> Vec<LogRecord> {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&faulty_plugin::PLUGIN, config)
+            .unwrap();
+        driver.logs()
+    }
+
+    #[test]
+    fn logs_per_driver() {
+        // both drivers initialize their plugin at the same time
+        let (timeout, failure) = std::thread::scope(|s| {
+            let timeout = s.spawn(|| logs(c"{\"fault\": \"timeout\"}"));
+            let failure = s.spawn(|| logs(c"{\"fault\": \"failure\"}"));
+            (timeout.join().unwrap(), failure.join().unwrap())
+        });
+
+        assert_eq!(timeout.from_plugin("faulty").len(), timeout.len());
+        assert!(timeout.contains(LogSeverity::Warning, "injecting fault: Timeout"));
+        assert!(!timeout.contains(LogSeverity::Trace, "Failure"));
+
+        assert_eq!(failure.from_plugin("faulty").len(), failure.len());
+        assert!(failure.contains(LogSeverity::Warning, "injecting fault: Failure"));
+        assert!(!failure.contains(LogSeverity::Trace, "Timeout"));
+    }
+
+    #[test]
+    fn capture_logs_per_driver() {
+        // both plugins are initialized before either is opened, so the SDK's logger
+        // points at the same plugin when they log from `open`
+        let barrier = Barrier::new(2);
+        let logs = |config: &CStr| {
+            let mut driver = NativeTestDriver::new().unwrap();
+            driver
+                .register_plugin(&faulty_plugin::PLUGIN, config)
+                .unwrap();
+            barrier.wait();
+            driver.start_capture(c"", c"").unwrap().logs()
+        };
+        let (timeout, failure) = std::thread::scope(|s| {
+            let timeout = s.spawn(|| logs(c"{\"fault\": \"timeout\"}"));
+            let failure = s.spawn(|| logs(c"{\"fault\": \"failure\"}"));
+            (timeout.join().unwrap(), failure.join().unwrap())
+        });
+
+        assert!(timeout.contains(LogSeverity::Warning, "opening with fault: Timeout"));
+        assert!(!timeout.contains(LogSeverity::Trace, "Failure"));
+        assert!(failure.contains(LogSeverity::Warning, "opening with fault: Failure"));
+        assert!(!failure.contains(LogSeverity::Trace, "Timeout"));
+    }
+}
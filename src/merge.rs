@@ -0,0 +1,351 @@
+//! Timestamp-ordered capture from several event sources
+//!
+//! Instead of running every source in a loop of its own (see [`crate::multi_source`]),
+//! [`MergedCapture`] reads a capture with several source plugins registered on the same
+//! [`NativeTestDriver`](crate::native::NativeTestDriver) and hands out their events
+//! in a single stream ordered by event timestamp, the way libscap merges its inputs.
+//! All the plugins share the driver, so a plugin correlating events of several sources
+//! (say, `syscall` events and those of a custom source) sees all of them:
+//!
+//! ```ignore
+//! let mut driver = NativeTestDriver::new()?;
+//! driver.register_plugin(&syscall_source_plugin::PLUGIN, c"")?;
+//! driver.register_plugin(&CUSTOM_SOURCE_PLUGIN, c"")?;
+//! driver.register_plugin(&CORRELATION_PLUGIN, c"")?;
+//! let driver = driver.start_capture(c"", c"")?;
+//!
+//! let mut capture = MergedCapture::new(driver, &["syscall", "custom"])?
+//!     .with_fields(&["correlation.field"])?;
+//! let events = capture.take_events(100);
+//! ```
+//!
+//! The runner reads the source plugins in turn, and the merge keeps the events of every
+//! source in a queue until each source has one ready, has run out of events, or the
+//! capture ends. Parse plugins
+//! see the events in the order the runner reads them; the merged stream, and the fields
+//! extracted from it, follow the timestamps. Events with the same timestamp come in
+//! the order the sources were passed to [`MergedCapture::new`], and events of a single
+//! source always keep their order, so the merged stream is the same on every run.
+
+use crate::event::event_source;
+use crate::native::{NativeCapturingTestDriver, MAX_BUFFERED_EVENTS};
+use crate::{CapturingTestDriver, EventExt, ScapStatus};
+use falco_plugin::anyhow;
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a source that has no event ready before giving up
+/// (and returning `ScapStatus::Timeout`)
+const DEFAULT_WAIT: Duration = Duration::from_secs(1);
+
+/// An event from one of the merged sources
+#[derive(Debug)]
+pub struct MergedEvent {
+    /// The event source, as reported by the runner
+    pub source: Option<String>,
+    pub ts: u64,
+    pub event: falco_plugin_runner::Event,
+    /// The fields requested with [`MergedCapture::with_fields`]
+    pub fields: BTreeMap<String, Option<String>>,
+}
+
+impl MergedEvent {
+    /// The value of a field requested with [`MergedCapture::with_fields`]
+    ///
+    /// Returns `None` both for fields without a value and for fields never requested.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name)?.as_deref()
+    }
+}
+
+/// The events of a single source, read ahead of the merge
+struct SourceQueue {
+    source: String,
+    events: VecDeque<(u64, falco_plugin_runner::Event)>,
+}
+
+/// Several event sources of a single capture, merged into a single stream
+/// (see the [module docs](self))
+pub struct MergedCapture {
+    driver: NativeCapturingTestDriver,
+    /// In tie-breaking order
+    queues: Vec<SourceQueue>,
+    /// Events of sources that aren't merged, handed out as they come
+    unmerged: VecDeque<(u64, falco_plugin_runner::Event)>,
+    fields: Vec<CString>,
+    /// How the capture ended, if it did
+    end: Option<ScapStatus>,
+    wait: Duration,
+}
+
+impl MergedCapture {
+    /// Merge the events of `sources` (in tie-breaking order) coming from `driver`
+    ///
+    /// Events of any other source (e.g. async events of another source) aren't merged:
+    /// they come out as soon as the runner has them.
+    pub fn new(driver: NativeCapturingTestDriver, sources: &[&str]) -> anyhow::Result<Self> {
+        let mut queues: Vec<SourceQueue> = Vec::new();
+        for &source in sources {
+            anyhow::ensure!(
+                queues.iter().all(|q| q.source != source),
+                "duplicate source {}",
+                source
+            );
+            queues.push(SourceQueue {
+                source: source.to_string(),
+                events: VecDeque::new(),
+            });
+        }
+
+        Ok(Self {
+            driver,
+            queues,
+            unmerged: VecDeque::new(),
+            fields: Vec::new(),
+            end: None,
+            wait: DEFAULT_WAIT,
+        })
+    }
+
+    /// Extract `fields` from every event, as it comes out of the merge
+    pub fn with_fields(mut self, fields: &[&str]) -> anyhow::Result<Self> {
+        self.fields = fields
+            .iter()
+            .map(|&f| CString::new(f))
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Wait for at most `wait` for a source without an event ready (one second by default)
+    ///
+    /// The merge can't pick the next event until every source either has one
+    /// or has run out of events.
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// The merged sources, in tie-breaking order
+    pub fn sources(&self) -> Vec<&str> {
+        self.queues.iter().map(|q| q.source.as_str()).collect()
+    }
+
+    /// How the capture ended, or `None` if it's still going
+    pub fn end(&self) -> Option<ScapStatus> {
+        self.end
+    }
+
+    /// The driver running the capture, e.g. to check its trace or tables
+    pub fn driver(&mut self) -> &mut NativeCapturingTestDriver {
+        &mut self.driver
+    }
+
+    /// Read events until every source has one ready or has run out of events
+    fn fill(&mut self) -> Result<(), ScapStatus> {
+        let deadline = Instant::now() + self.wait;
+        while self.end.is_none()
+            && self.unmerged.is_empty()
+            && self
+                .queues
+                .iter()
+                .any(|q| q.events.is_empty() && !self.driver.source_ended(&q.source))
+        {
+            let buffered: usize = self.queues.iter().map(|q| q.events.len()).sum();
+            if buffered >= MAX_BUFFERED_EVENTS {
+                return Err(ScapStatus::Failure);
+            }
+
+            match self.driver.next_event() {
+                Ok(event) => {
+                    // events without a valid header go first, where they're easy to spot
+                    let ts = event.raw().map(|raw| raw.metadata.ts).unwrap_or_default();
+                    let source = event_source(&event);
+                    let queue = self
+                        .queues
+                        .iter_mut()
+                        .find(|q| Some(&q.source) == source.as_ref());
+                    match queue {
+                        Some(queue) => queue.events.push_back((ts, event)),
+                        None => self.unmerged.push_back((ts, event)),
+                    }
+                }
+                Err(ScapStatus::Timeout) => {
+                    if Instant::now() >= deadline {
+                        return Err(ScapStatus::Timeout);
+                    }
+                    std::thread::yield_now();
+                }
+                Err(status) => self.end = Some(status),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the event with the lowest timestamp across all the sources
+    ///
+    /// Returns the status the capture ended with (`ScapStatus::Eof` once all the sources
+    /// ran out of events) after the last event, `ScapStatus::Timeout` if a source had
+    /// no event ready for too long, and `ScapStatus::Failure` if more than
+    /// [`MAX_BUFFERED_EVENTS`] events pile up waiting for one.
+    pub fn next_event(&mut self) -> Result<MergedEvent, ScapStatus> {
+        self.fill()?;
+
+        let next = match self.unmerged.pop_front() {
+            Some(next) => Some(next),
+            // `min_by_key` returns the first of equal elements, i.e. the earliest source
+            None => self
+                .queues
+                .iter_mut()
+                .filter(|q| !q.events.is_empty())
+                .min_by_key(|q| q.events.front().map(|(ts, _)| *ts))
+                .and_then(|q| q.events.pop_front()),
+        };
+        let Some((ts, event)) = next else {
+            return Err(self.end.unwrap_or(ScapStatus::Eof));
+        };
+
+        let mut fields = BTreeMap::new();
+        for field in &self.fields {
+            let value = self
+                .driver
+                .event_field_as_string(field, &event)
+                .map_err(|_| ScapStatus::Failure)?;
+            fields.insert(field.to_string_lossy().into_owned(), value);
+        }
+
+        Ok(MergedEvent {
+            source: event_source(&event),
+            ts,
+            event,
+            fields,
+        })
+    }
+
+    /// Take up to `count` events, stopping early when the capture ends
+    pub fn take_events(&mut self, count: usize) -> Vec<MergedEvent> {
+        let mut events = Vec::new();
+        while events.len() < count {
+            match self.next_event() {
+                Ok(event) => events.push(event),
+                Err(_) => break,
+            }
+        }
+        events
+    }
+
+    /// Extract a field that wasn't requested upfront
+    pub fn event_field_as_string(
+        &mut self,
+        field_name: &CStr,
+        event: &MergedEvent,
+    ) -> anyhow::Result<Option<String>> {
+        self.driver.event_field_as_string(field_name, &event.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MergedCapture;
+    use crate::native::{NativeTestDriver, MAX_BUFFERED_EVENTS};
+    use crate::replay_source_plugin::{event, i64_param, str_param};
+    use crate::{correlation_plugin, replay_source_plugin, syscall_source_plugin};
+    use crate::{ScapStatus, TestDriver};
+    use falco_plugin::event::events::types::EventType;
+
+    #[test]
+    fn merged_sources() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let events = replay_source_plugin::process_lifecycle();
+        driver
+            .register_plugin(
+                &replay_source_plugin::PLUGIN,
+                &replay_source_plugin::config(&events),
+            )
+            .unwrap();
+        driver
+            .register_plugin(&correlation_plugin::TICK_SOURCE, c"")
+            .unwrap();
+        let driver = driver.start_capture(c"", c"").unwrap();
+        let mut capture = MergedCapture::new(driver, &["syscall", "tick"]).unwrap();
+
+        let events = capture.take_events(100);
+        let ts: Vec<_> = events.iter().map(|e| e.ts).collect();
+        assert_eq!(ts, vec![1, 2, 2, 3, 4, 5, 5, 6, 7, 8, 8, 9, 10]);
+
+        // the syscall source was passed first, so it wins the ties
+        let ticks: Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.source.as_deref() == Some("tick"))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(ticks, vec![2, 6, 10]);
+        assert!(matches!(capture.next_event(), Err(ScapStatus::Eof)));
+        assert!(matches!(capture.end(), Some(ScapStatus::Eof)));
+    }
+
+    #[test]
+    fn source_ending_early() {
+        // the ticks are over long before the syscall events, which are more than
+        // the merge could ever buffer
+        let count = MAX_BUFFERED_EVENTS as u64 * 2;
+        let events: Vec<_> = (1..=count)
+            .map(|ts| {
+                let params = [i64_param(4), str_param("/etc/passwd")];
+                event(ts, 100, EventType::SYSCALL_OPEN_X, &params)
+            })
+            .collect();
+
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(
+                &replay_source_plugin::PLUGIN,
+                &replay_source_plugin::config(&events),
+            )
+            .unwrap();
+        driver
+            .register_plugin(&correlation_plugin::TICK_SOURCE, c"")
+            .unwrap();
+        let driver = driver.start_capture(c"", c"").unwrap();
+        let mut capture = MergedCapture::new(driver, &["syscall", "tick"]).unwrap();
+
+        let events = capture.take_events(usize::MAX);
+        assert_eq!(
+            events.len() as u64,
+            count + correlation_plugin::TICKS.len() as u64
+        );
+        assert!(events.windows(2).all(|w| w[0].ts <= w[1].ts));
+        assert!(matches!(capture.end(), Some(ScapStatus::Eof)));
+    }
+
+    #[test]
+    fn correlated_sources() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&correlation_plugin::TICK_SOURCE, c"")
+            .unwrap();
+        driver
+            .register_plugin(&correlation_plugin::PLUGIN, c"")
+            .unwrap();
+        let driver = driver.start_capture(c"", c"").unwrap();
+        let mut capture = MergedCapture::new(driver, &["syscall", "tick"]).unwrap();
+
+        let events = capture.take_events(100);
+        assert_eq!(events.len(), 5 + correlation_plugin::TICKS.len());
+
+        // once the capture is over, the plugin parsed the events of both sources
+        let last_syscall = events
+            .iter()
+            .rfind(|e| e.source.as_deref() == Some("syscall"))
+            .unwrap();
+        let ticks = capture
+            .event_field_as_string(c"correlation.ticks", last_syscall)
+            .unwrap();
+        let expected = correlation_plugin::TICKS.len().to_string();
+        assert_eq!(ticks.as_deref(), Some(expected.as_str()));
+    }
+}
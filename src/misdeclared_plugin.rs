@@ -0,0 +1,132 @@
+//! A `syscall` extract and parse plugin lying about its event types, for the harness's
+//! own tests
+//!
+//! The SDK plugin underneath handles all event types, but the vtable tells the first
+//! caller (the interposer, right after `init`) that it only handles `open` exit events.
+//! The runner asks later, gets the real answer, and calls the plugin for every event,
+//! which the harness reports as filter violations. The answers are tracked per plugin
+//! state and entry point.
+
+use falco_plugin::anyhow::Error;
+use falco_plugin::api::{
+    plugin_api, plugin_api__bindgen_ty_2, plugin_api__bindgen_ty_3, ss_plugin_t,
+};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::{field, EventInput, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::CStr;
+use std::sync::{Mutex, PoisonError};
+
+struct MisdeclaredPlugin;
+
+impl Plugin for MisdeclaredPlugin {
+    const NAME: &'static CStr = c"misdeclared";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Misreports the event types it handles.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl MisdeclaredPlugin {
+    fn extract_type(&mut self, req: ExtractRequest<Self>) -> Result<u64, Error> {
+        Ok(req.event.event()?.event_type as u64)
+    }
+}
+
+impl ExtractPlugin for MisdeclaredPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field("misdeclared.type", &Self::extract_type)];
+}
+
+impl ParsePlugin for MisdeclaredPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+
+    fn parse_event(&mut self, _event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(MISDECLARED_PLUGIN = MisdeclaredPlugin);
+
+/// The event types reported to the first caller
+static DECLARED_TYPES: [u16; 1] = [EventType::SYSCALL_OPEN_X as u16];
+
+/// The plugin states (and entry points) that already reported [`DECLARED_TYPES`]
+static QUERIED: Mutex<Vec<(usize, &'static str)>> = Mutex::new(Vec::new());
+
+/// Report [`DECLARED_TYPES`] on the first call for each plugin state and entry point,
+/// and the actual types afterwards
+///
+/// # Safety
+/// Same as the wrapped entry point
+unsafe fn event_types(
+    entry_point: &'static str,
+    numtypes: *mut u32,
+    s: *mut ss_plugin_t,
+    actual: Option<unsafe extern "C" fn(*mut u32, *mut ss_plugin_t) -> *mut u16>,
+) -> *mut u16 {
+    let key = (s as usize, entry_point);
+    let first = {
+        let mut queried = QUERIED.lock().unwrap_or_else(PoisonError::into_inner);
+        let first = !queried.contains(&key);
+        if first {
+            queried.push(key);
+        }
+        first
+    };
+
+    match actual {
+        Some(actual) if !first => actual(numtypes, s),
+        _ => {
+            *numtypes = DECLARED_TYPES.len() as u32;
+            DECLARED_TYPES.as_ptr() as *mut u16
+        }
+    }
+}
+
+unsafe extern "C" fn get_extract_event_types(numtypes: *mut u32, s: *mut ss_plugin_t) -> *mut u16 {
+    let actual = MISDECLARED_PLUGIN.__bindgen_anon_2.get_extract_event_types;
+    event_types("extract", numtypes, s, actual)
+}
+
+unsafe extern "C" fn get_parse_event_types(numtypes: *mut u32, s: *mut ss_plugin_t) -> *mut u16 {
+    let actual = MISDECLARED_PLUGIN.__bindgen_anon_3.get_parse_event_types;
+    event_types("parse", numtypes, s, actual)
+}
+
+unsafe extern "C" fn destroy(s: *mut ss_plugin_t) {
+    // the state's address may get reused
+    let mut queried = QUERIED.lock().unwrap_or_else(PoisonError::into_inner);
+    queried.retain(|(state, _)| *state != s as usize);
+    drop(queried);
+
+    if let Some(destroy) = MISDECLARED_PLUGIN.destroy {
+        destroy(s);
+    }
+}
+
+/// Serves `misdeclared.type` (the event type) for all `syscall` events, but claims to
+/// only handle `open` exit events (see the module docs)
+pub(crate) static PLUGIN: plugin_api = plugin_api {
+    destroy: Some(destroy),
+    __bindgen_anon_2: plugin_api__bindgen_ty_2 {
+        get_extract_event_types: Some(get_extract_event_types),
+        ..MISDECLARED_PLUGIN.__bindgen_anon_2
+    },
+    __bindgen_anon_3: plugin_api__bindgen_ty_3 {
+        get_parse_event_types: Some(get_parse_event_types),
+        ..MISDECLARED_PLUGIN.__bindgen_anon_3
+    },
+    ..MISDECLARED_PLUGIN
+};
@@ -0,0 +1,530 @@
+//! Concurrent capture from several event sources
+//!
+//! Falco runs every enabled event source in a loop of its own, on a separate thread,
+//! each with its own instances of the plugins that handle that source. Plugins that keep
+//! state outside their instance (statics, thread-locals captured once, tables they assume
+//! only one source fills) often work fine in a single-source test and break under Falco.
+//!
+//! [`MultiSourceCapture`] emulates that setup: every [`SourceLoop`] gets its own
+//! [`NativeTestDriver`](crate::native::NativeTestDriver), built and run on its own thread.
+//! All the loops start capturing at the same time and run freely, like Falco's, sending
+//! the events (with any requested fields already extracted) to per-source streams
+//! the test pulls from. Loops go by a label of the test's choosing; the event source
+//! is the one of the source plugin registered in the loop's setup:
+//!
+//! ```ignore
+//! let mut capture = MultiSourceCapture::start(vec![
+//!     SourceLoop::new("syscall", |driver| {
+//!         driver.register_plugin(&syscall_source_plugin::PLUGIN, c"")?;
+//!         driver.register_plugin(&EXTRACT_PLUGIN, c"")?;
+//!         Ok(())
+//!     })
+//!     .with_fields(&["rustlings.fd"]),
+//!     SourceLoop::new("network", |driver| { ... }),
+//! ])?;
+//!
+//! let event = capture.next_event("syscall").unwrap();
+//! assert_eq!(event.field("rustlings.fd"), Some("5"));
+//!
+//! // fails if any loop failed or panicked
+//! let reports = capture.stop()?;
+//! ```
+
+use crate::event::{event_bytes, event_source, raw_event};
+use crate::filter::FilterViolation;
+use crate::json::to_json;
+use crate::logs::LogRecord;
+use crate::native::{NativeCapturingTestDriver, NativeTestDriver};
+use crate::{CapturingTestDriver, ScapStatus, TestDriver};
+use falco_plugin::anyhow;
+use falco_plugin::anyhow::Context;
+use falco_plugin::event::events::RawEvent;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Barrier};
+use std::thread::JoinHandle;
+
+/// How many events a loop can get ahead of the test before it blocks
+const STREAM_CAPACITY: usize = 64;
+
+type SetupFn = Box<dyn FnOnce(&mut NativeTestDriver) -> anyhow::Result<()> + Send>;
+
+/// The configuration of a single event source loop
+pub struct SourceLoop {
+    label: String,
+    setup: SetupFn,
+    config: CString,
+    fields: Vec<String>,
+    limit: Option<usize>,
+}
+
+impl SourceLoop {
+    /// A loop labelled `label`
+    ///
+    /// `setup` runs on the loop's own thread and registers the plugins that handle
+    /// the source (the source plugin itself, plus any extract and parse plugins).
+    /// The label only tells the loops apart in the test, it doesn't have to match
+    /// the event source.
+    pub fn new(
+        label: &str,
+        setup: impl FnOnce(&mut NativeTestDriver) -> anyhow::Result<()> + Send + 'static,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            setup: Box::new(setup),
+            config: CString::default(),
+            fields: Vec::new(),
+            limit: None,
+        }
+    }
+
+    /// Pass `config` to `start_capture` (as the open parameters)
+    pub fn with_config(mut self, config: &str) -> anyhow::Result<Self> {
+        self.config = CString::new(config)?;
+        Ok(self)
+    }
+
+    /// Extract `fields` from every event, in the loop itself
+    ///
+    /// This is where Falco would evaluate its rules, so it's where extract plugins
+    /// run concurrently with the other sources.
+    pub fn with_fields(mut self, fields: &[&str]) -> Self {
+        self.fields = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Stop the loop after `limit` events
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// An event captured by one of the source loops
+///
+/// Events of the native runner can't leave the thread that captured them,
+/// so the loop sends a copy of the event along with everything extracted from it.
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    /// The label of the loop that captured the event
+    pub source_loop: String,
+    /// The event source, as reported by the runner
+    pub source: Option<String>,
+    pub evt_num: Option<u64>,
+    pub ts: u64,
+    pub event_type: u16,
+    /// The encoded event, header included
+    pub data: Vec<u8>,
+    /// The event as JSON (see [`crate::json`]), unless its type can't be serialized
+    pub json: Option<serde_json::Value>,
+    /// The fields requested with [`SourceLoop::with_fields`]
+    pub fields: BTreeMap<String, Option<String>>,
+}
+
+impl CapturedEvent {
+    /// Parse the event header, leaving the parameters as a raw byte buffer
+    pub fn raw(&self) -> anyhow::Result<RawEvent<'_>> {
+        // SAFETY: `data` holds a complete event, copied from the runner's buffer
+        Ok(unsafe { RawEvent::from_ptr(self.data.as_ptr()) }?)
+    }
+
+    /// The value of a field requested with [`SourceLoop::with_fields`]
+    ///
+    /// Returns `None` both for fields without a value and for fields never requested.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name)?.as_deref()
+    }
+
+    fn capture(
+        source_loop: &str,
+        driver: &mut NativeCapturingTestDriver,
+        event: &falco_plugin_runner::Event,
+        fields: &[(String, CString)],
+    ) -> anyhow::Result<Self> {
+        let raw = raw_event(event)?;
+        let mut extracted = BTreeMap::new();
+        for (name, cname) in fields {
+            let value = driver
+                .event_field_as_string(cname, event)
+                .with_context(|| format!("failed to extract {}", name))?;
+            extracted.insert(name.clone(), value);
+        }
+
+        Ok(Self {
+            source_loop: source_loop.to_string(),
+            source: event_source(event),
+            evt_num: event.evt_num,
+            ts: raw.metadata.ts,
+            event_type: raw.event_type,
+            data: event_bytes(event)?.to_vec(),
+            json: to_json(event).ok(),
+            fields: extracted,
+        })
+    }
+}
+
+/// How a source loop ended
+#[derive(Debug, Clone, Copy)]
+pub enum LoopEnd {
+    /// The test stopped the loop (or stopped listening to it)
+    Stopped,
+    /// The loop captured the number of events set with [`SourceLoop::with_limit`]
+    LimitReached,
+    /// The capture itself ended, e.g. with `ScapStatus::Eof` when the source plugin
+    /// ran out of events (or `ScapStatus::Failure` if the loop failed or panicked)
+    Capture(ScapStatus),
+}
+
+/// What a source loop left behind after it finished
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    /// The label of the loop
+    pub source_loop: String,
+    /// The number of events the loop captured
+    pub events: usize,
+    pub end: LoopEnd,
+    pub filter_violations: Vec<FilterViolation>,
+    pub logs: Vec<LogRecord>,
+}
+
+type Message = Result<CapturedEvent, LoopEnd>;
+
+struct SourceStream {
+    events: Option<Receiver<Message>>,
+    /// How the loop ended, once its stream told
+    end: Option<LoopEnd>,
+    thread: JoinHandle<anyhow::Result<SourceReport>>,
+}
+
+/// Several event sources, captured concurrently (see the [module docs](self))
+pub struct MultiSourceCapture {
+    streams: BTreeMap<String, SourceStream>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl MultiSourceCapture {
+    /// Start a loop for every source
+    ///
+    /// Returns once every loop has started its capture (so the loops are running
+    /// concurrently from their first event), or with the first setup error.
+    pub fn start(loops: Vec<SourceLoop>) -> anyhow::Result<Self> {
+        let mut labels: Vec<_> = loops.iter().map(|l| l.label.as_str()).collect();
+        labels.sort_unstable();
+        if let Some(dup) = labels.windows(2).find(|w| w[0] == w[1]) {
+            anyhow::bail!("duplicate source loop {}", dup[0]);
+        }
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let started = Arc::new(Barrier::new(loops.len() + 1));
+        let mut setup_errors = Vec::new();
+        let mut streams = BTreeMap::new();
+
+        for source_loop in loops {
+            let label = source_loop.label.clone();
+
+            let (tx, rx) = sync_channel(STREAM_CAPACITY);
+            let (setup_tx, setup_rx) = sync_channel(1);
+            let stopping = Arc::clone(&stopping);
+            let started = Arc::clone(&started);
+            let thread = std::thread::Builder::new()
+                .name(format!("source-{}", label))
+                .spawn(move || run_loop(source_loop, tx, setup_tx, started, stopping))?;
+
+            setup_errors.push((label.clone(), setup_rx));
+            streams.insert(
+                label,
+                SourceStream {
+                    events: Some(rx),
+                    end: None,
+                    thread,
+                },
+            );
+        }
+
+        started.wait();
+        let capture = Self { streams, stopping };
+        for (label, setup) in setup_errors {
+            // a closed channel means the loop panicked, which `stop` reports
+            if let Ok(Err(e)) = setup.recv() {
+                let _ = capture.stop();
+                return Err(e.context(format!("failed to start source loop {}", label)));
+            }
+        }
+
+        Ok(capture)
+    }
+
+    /// The labels of all the source loops
+    pub fn sources(&self) -> Vec<&str> {
+        self.streams.keys().map(String::as_str).collect()
+    }
+
+    /// Wait for the next event from the `source` loop
+    ///
+    /// Once the loop ends, returns how it ended (`LoopEnd::Capture(ScapStatus::Failure)`
+    /// if it failed or panicked; [`Self::stop`] tells why) on every call.
+    pub fn next_event(&mut self, source: &str) -> Result<CapturedEvent, LoopEnd> {
+        let stream = self
+            .streams
+            .get_mut(source)
+            .unwrap_or_else(|| panic!("no source loop labelled {}", source));
+        let Some(events) = &stream.events else {
+            return Err(stream.end.unwrap_or(LoopEnd::Stopped));
+        };
+
+        let end = match events.recv() {
+            Ok(Ok(event)) => return Ok(event),
+            Ok(Err(end)) => end,
+            Err(_) => LoopEnd::Capture(ScapStatus::Failure),
+        };
+        stream.events = None;
+        stream.end = Some(end);
+        Err(end)
+    }
+
+    /// Take up to `count` events from the `source` loop, stopping early when it ends
+    pub fn take_events(&mut self, source: &str, count: usize) -> Vec<CapturedEvent> {
+        let mut events = Vec::new();
+        while events.len() < count {
+            match self.next_event(source) {
+                Ok(event) => events.push(event),
+                Err(_) => break,
+            }
+        }
+        events
+    }
+
+    /// Stop all the loops and wait for them to finish
+    ///
+    /// Fails if any loop failed or panicked, which is how plugin state that isn't safe
+    /// to share between sources usually shows up.
+    pub fn stop(self) -> anyhow::Result<Vec<SourceReport>> {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        let mut reports = Vec::new();
+        let mut errors = Vec::new();
+        for (label, mut stream) in self.streams {
+            // unblock a loop waiting for room in its stream
+            drop(stream.events.take());
+            match stream.thread.join() {
+                Ok(Ok(report)) => reports.push(report),
+                Ok(Err(e)) => errors.push(format!("source loop {} failed: {:#}", label, e)),
+                Err(panic) => errors.push(format!(
+                    "source loop {} panicked: {}",
+                    label,
+                    panic_message(&*panic)
+                )),
+            }
+        }
+
+        anyhow::ensure!(errors.is_empty(), "{}", errors.join("\n"));
+        Ok(reports)
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("<unknown panic payload>")
+    }
+}
+
+/// Build a driver for a single source and start its capture
+fn start_loop(setup: SetupFn, config: &CString) -> anyhow::Result<NativeCapturingTestDriver> {
+    let mut driver = NativeTestDriver::new()?;
+    setup(&mut driver)?;
+    driver.start_capture(c"", config)
+}
+
+fn run_loop(
+    source_loop: SourceLoop,
+    events: SyncSender<Message>,
+    setup_result: SyncSender<anyhow::Result<()>>,
+    started: Arc<Barrier>,
+    stopping: Arc<AtomicBool>,
+) -> anyhow::Result<SourceReport> {
+    let SourceLoop {
+        label,
+        setup,
+        config,
+        fields,
+        limit,
+    } = source_loop;
+
+    // the test is waiting for every loop to get here, so a panic must not escape
+    let driver = std::panic::catch_unwind(AssertUnwindSafe(|| start_loop(setup, &config)))
+        .unwrap_or_else(|panic| Err(anyhow::anyhow!("panicked: {}", panic_message(&*panic))));
+    started.wait();
+    let mut driver = match driver {
+        Ok(driver) => {
+            let _ = setup_result.send(Ok(()));
+            driver
+        }
+        Err(e) => {
+            let _ = setup_result.send(Err(anyhow::anyhow!("{:#}", e)));
+            return Err(e);
+        }
+    };
+
+    let fields = fields
+        .into_iter()
+        .map(|f| Ok((f.clone(), CString::new(f)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut count = 0;
+    let mut end = LoopEnd::Stopped;
+    while !stopping.load(Ordering::SeqCst) && limit.map_or(true, |limit| count < limit) {
+        let event = match driver.next_event() {
+            Ok(event) => event,
+            Err(ScapStatus::Timeout) => {
+                std::thread::yield_now();
+                continue;
+            }
+            Err(status) => {
+                end = LoopEnd::Capture(status);
+                let _ = events.send(Err(end));
+                break;
+            }
+        };
+
+        count += 1;
+        let captured = CapturedEvent::capture(&label, &mut driver, &event, &fields);
+        let captured = match captured {
+            Ok(captured) => captured,
+            Err(e) => {
+                let _ = events.send(Err(LoopEnd::Capture(ScapStatus::Failure)));
+                return Err(e.context(format!("event #{}", count)));
+            }
+        };
+
+        if events.send(Ok(captured)).is_err() {
+            // the test stopped listening
+            break;
+        }
+    }
+
+    if matches!(end, LoopEnd::Stopped) && limit == Some(count) {
+        end = LoopEnd::LimitReached;
+        let _ = events.send(Err(end));
+    }
+
+    Ok(SourceReport {
+        source_loop: label,
+        events: count,
+        end,
+        filter_violations: driver.filter_violations(),
+        logs: driver.logs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoopEnd, MultiSourceCapture, SourceLoop};
+    use crate::{shared_state_plugin, syscall_extract_plugin, syscall_source_plugin};
+    use crate::{ScapStatus, TestDriver};
+
+    fn syscall_loop(label: &str) -> SourceLoop {
+        SourceLoop::new(label, |driver| {
+            driver.register_plugin(&syscall_source_plugin::PLUGIN, c"")?;
+            driver.register_plugin(&syscall_extract_plugin::PLUGIN, c"")?;
+            Ok(())
+        })
+        .with_fields(&["rustlings.fd"])
+    }
+
+    #[test]
+    fn concurrent_sources() {
+        let mut capture =
+            MultiSourceCapture::start(vec![syscall_loop("first"), syscall_loop("second")]).unwrap();
+
+        for source in ["first", "second"] {
+            let fds: Vec<_> = capture
+                .take_events(source, 10)
+                .iter()
+                .map(|evt| evt.field("rustlings.fd").map(String::from))
+                .collect();
+            let fd = || Some(String::from("5"));
+            assert_eq!(fds, vec![fd(), fd(), None, fd(), None]);
+            assert!(matches!(
+                capture.next_event(source),
+                Err(LoopEnd::Capture(ScapStatus::Eof))
+            ));
+        }
+
+        for report in capture.stop().unwrap() {
+            assert_eq!(report.events, 5);
+            assert!(matches!(report.end, LoopEnd::Capture(ScapStatus::Eof)));
+            assert_eq!(report.filter_violations, vec![]);
+        }
+    }
+
+    #[test]
+    fn limit_reached() {
+        let mut capture =
+            MultiSourceCapture::start(vec![syscall_loop("syscall").with_limit(2)]).unwrap();
+
+        assert_eq!(capture.take_events("syscall", 10).len(), 2);
+        // the end sticks
+        for _ in 0..2 {
+            assert!(matches!(
+                capture.next_event("syscall"),
+                Err(LoopEnd::LimitReached)
+            ));
+        }
+
+        let reports = capture.stop().unwrap();
+        assert_eq!(reports[0].events, 2);
+        assert!(matches!(reports[0].end, LoopEnd::LimitReached));
+    }
+
+    #[test]
+    fn open_params() {
+        let source_loop = syscall_loop("syscall").with_config("params").unwrap();
+        let mut capture = MultiSourceCapture::start(vec![source_loop]).unwrap();
+        assert_eq!(capture.take_events("syscall", 10).len(), 5);
+        capture.stop().unwrap();
+    }
+
+    #[test]
+    fn shared_state() {
+        let source_loop = |label| {
+            SourceLoop::new(label, |driver| {
+                driver.register_plugin(&syscall_source_plugin::PLUGIN, c"")?;
+                driver.register_plugin(&shared_state_plugin::PLUGIN, c"")?;
+                Ok(())
+            })
+            .with_fields(&["shared.parsed"])
+        };
+
+        // fine on its own...
+        let mut capture = MultiSourceCapture::start(vec![source_loop("alone")]).unwrap();
+        let parsed: Vec<_> = capture
+            .take_events("alone", 10)
+            .iter()
+            .map(|evt| evt.field("shared.parsed").map(String::from))
+            .collect();
+        let expected: Vec<_> = (1..=5).map(|n| Some(n.to_string())).collect();
+        assert_eq!(parsed, expected);
+        capture.stop().unwrap();
+
+        // ...but whichever loop parses its first event second sees the other's count
+        let mut capture =
+            MultiSourceCapture::start(vec![source_loop("first"), source_loop("second")]).unwrap();
+        for source in ["first", "second"] {
+            capture.take_events(source, 10);
+        }
+        let err = capture.stop().err().unwrap();
+        assert!(
+            format!("{:#}", err).contains("but the static says"),
+            "{:#}",
+            err
+        );
+    }
+}
@@ -1,8 +1,27 @@
-use crate::{CapturingTestDriver, ScapStatus, SinspMetric, TestDriver};
+use crate::access::TableAccessReport;
+use crate::async_events::AsyncViolation;
+use crate::event::{event_source, raw_event};
+use crate::event_fields;
+use crate::filter::{FilterViolation, ViolationKind};
+use crate::interpose::{self, Shared};
+use crate::logs::LogRecord;
+use crate::open_params::OpenParam;
+use crate::prometheus::MetricType;
+use crate::snapshot::TablesSnapshot;
+use crate::state::{EventState, ThreadState};
+use crate::tables::{
+    self, FieldValue, Mutation, Request, Response, TableEntryData, TableInfo, TableKey,
+};
+use crate::trace::TraceRecord;
+use crate::{CapturingTestDriver, EventExt, ScapStatus, SinspMetric, TestDriver};
 use falco_plugin::anyhow;
+use falco_plugin::event::events::types::PPME_ASYNCEVENT_E;
 use falco_plugin_runner::{CapturingPluginRunner, MetricValue, PluginRunner};
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::CStr;
 use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub struct NativePlugin;
 
@@ -12,9 +31,550 @@ impl Debug for NativePlugin {
     }
 }
 
-pub struct NativeTestDriver(PluginRunner);
+/// How long to wait for async events before polling the runner again
+///
+/// Async events from the test's plugins end the wait early.
+const ASYNC_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
-pub struct NativeCapturingTestDriver(CapturingPluginRunner);
+/// How many other events can pile up while looking for async events
+pub const MAX_BUFFERED_EVENTS: usize = 10_000;
+
+/// The name of an async event, or `None` for any other event
+fn async_event_name(event: &falco_plugin_runner::Event) -> Option<String> {
+    let event = event.load::<PPME_ASYNCEVENT_E>().ok()?;
+    Some(event.params.name?.to_string_lossy().into_owned())
+}
+
+/// The plugin ID an async event was delivered with
+fn async_event_plugin_id(event: &falco_plugin_runner::Event) -> Option<u32> {
+    event.load::<PPME_ASYNCEVENT_E>().ok()?.params.plugin_id
+}
+
+pub struct NativeTestDriver {
+    runner: PluginRunner,
+    shared: Arc<Shared>,
+    table_inspection: bool,
+    /// Table entries to write when the capture opens
+    seed: Vec<Mutation>,
+}
+
+impl NativeTestDriver {
+    /// Start recording all calls into the plugins registered from now on
+    ///
+    /// See [`crate::trace`] for what gets recorded. Use [`NativeTestDriver::new`] and
+    /// [`TestDriver::register_plugin`] instead of [`crate::init_plugin`] to have
+    /// the plugin's `init` traced as well.
+    ///
+    /// Tracing only controls the recording: plugins always get registered through
+    /// the interposer (see [`crate::interpose`]), which also captures their logs,
+    /// checks their events and passes the open parameters to `open`.
+    pub fn enable_tracing(&mut self) {
+        self.shared.set_tracing(true);
+    }
+
+    /// Let the capture inspect and modify the plugins' tables
+    ///
+    /// This registers an extra (harness) plugin importing every table, which is needed
+    /// for [`NativeCapturingTestDriver::tables`] and the other table methods. It's opt-in,
+    /// as the extra plugin shows up in the capture like any other.
+    pub fn enable_table_inspection(&mut self) {
+        self.table_inspection = true;
+    }
+
+    /// Create a table entry (replacing any existing one) with the given field values,
+    /// before the capture starts
+    ///
+    /// The entry is written when the capture opens, so every plugin sees it from the
+    /// first event on. The table and its fields are only known at that point, so errors
+    /// are reported by [`NativeCapturingTestDriver::take_table_errors`].
+    /// This enables table inspection.
+    pub fn seed_table_entry<'a>(
+        &mut self,
+        table: &str,
+        key: impl Into<TableKey>,
+        fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    ) {
+        self.table_inspection = true;
+        self.seed.push(Mutation::Insert {
+            table: table.to_string(),
+            key: key.into(),
+            fields: fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        });
+    }
+
+    /// Keep async events in the harness until
+    /// [`NativeCapturingTestDriver::release_async_events`] is called
+    ///
+    /// This includes events emitted while the capture starts.
+    pub fn hold_async_events(&mut self) {
+        self.shared.set_hold_async(true);
+    }
+
+    /// All the messages logged by the plugins so far
+    ///
+    /// Useful for checking why a plugin failed to initialize.
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.shared.logs()
+    }
+
+    /// The open parameters `plugin` advertises (see [`crate::open_params`])
+    ///
+    /// Fails if there's no such plugin, it has no source capability, or it failed
+    /// to list its parameters.
+    pub fn list_open_params(&self, plugin: &str) -> anyhow::Result<Vec<OpenParam>> {
+        self.shared.advertised_open_params(plugin)
+    }
+}
+
+pub struct NativeCapturingTestDriver {
+    runner: CapturingPluginRunner,
+    shared: Arc<Shared>,
+    state: ThreadState,
+    /// The state each event was processed in, by event number
+    event_states: BTreeMap<u64, EventState>,
+    tables: Vec<TableInfo>,
+    mutations: Vec<Mutation>,
+    mutation_errors: Vec<anyhow::Error>,
+    access_reports: Option<Vec<TableAccessReport>>,
+    /// Events already taken from the runner, but not returned by `next_event` yet
+    buffered: VecDeque<falco_plugin_runner::Event>,
+    /// The number of async events taken from the runner
+    async_seen: u64,
+}
+
+/// What's left of a capture after [`NativeCapturingTestDriver::close`]
+pub struct ClosedCapture {
+    shared: Arc<Shared>,
+}
+
+impl ClosedCapture {
+    /// All the plugin API calls recorded, including `capture_close` and `destroy`
+    pub fn trace(&self) -> Vec<TraceRecord> {
+        self.shared.trace()
+    }
+
+    /// All the messages logged by the plugins, including while shutting down
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.shared.logs()
+    }
+}
+
+impl NativeCapturingTestDriver {
+    /// Close the capture, the way Falco does when it shuts down
+    ///
+    /// The runner calls `capture_close` on every plugin with the capture listen capability
+    /// and then destroys all the plugins. Anything they do afterwards (e.g. from
+    /// a background thread that outlived the capture) is a bug.
+    pub fn close(self) -> ClosedCapture {
+        let Self { runner, shared, .. } = self;
+        drop(runner);
+        ClosedCapture { shared }
+    }
+
+    /// Start (or stop) recording all calls into the plugins
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.shared.set_tracing(enabled);
+    }
+
+    /// All the plugin API calls recorded so far
+    pub fn trace(&self) -> Vec<TraceRecord> {
+        self.shared.trace()
+    }
+
+    /// All the events passed to plugins that didn't declare them, and all the fields
+    /// with values on events their plugin can't see
+    ///
+    /// Falco guarantees neither ever happens, so tests can rely on an empty list.
+    pub fn filter_violations(&self) -> Vec<FilterViolation> {
+        self.shared.violations()
+    }
+
+    /// Keep async events in the harness (or let them through as they're emitted)
+    ///
+    /// Held events are passed on to the capture by [`Self::release_async_events`],
+    /// which makes it possible to choose exactly where they show up in the event stream.
+    pub fn set_hold_async_events(&mut self, hold: bool) {
+        self.shared.set_hold_async(hold);
+    }
+
+    /// Pass all held async events on to the capture, returning how many there were
+    ///
+    /// The events become available to the next [`CapturingTestDriver::next_event`] call.
+    pub fn release_async_events(&mut self) -> usize {
+        self.shared.release_async()
+    }
+
+    /// Wait (for at most `timeout`) for an async event called `name`, and return it
+    ///
+    /// Other events received in the meantime are not lost: subsequent
+    /// [`CapturingTestDriver::next_event`] calls return them in order. The wait goes on
+    /// after the source plugins reach the end of their events, as async events may
+    /// still come in, but fails if more than [`MAX_BUFFERED_EVENTS`] other events
+    /// pile up.
+    pub fn wait_for_async_event(
+        &mut self,
+        name: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<falco_plugin_runner::Event> {
+        let deadline = Instant::now() + timeout;
+
+        let buffered = self
+            .buffered
+            .iter()
+            .position(|evt| async_event_name(evt).as_deref() == Some(name));
+        if let Some(pos) = buffered {
+            return Ok(self.buffered.remove(pos).expect("position is in range"));
+        }
+
+        loop {
+            match self.pull_event() {
+                Ok(evt) if async_event_name(&evt).as_deref() == Some(name) => return Ok(evt),
+                Ok(evt) => self.buffer(evt)?,
+                Err(ScapStatus::Timeout | ScapStatus::Eof) => self.wait_async_until(deadline),
+                Err(e) => anyhow::bail!("capture failed while waiting for {}: {:?}", name, e),
+            }
+
+            anyhow::ensure!(
+                Instant::now() < deadline,
+                "timed out waiting for async event {}",
+                name
+            );
+        }
+    }
+
+    /// Take all the async events the plugins have passed on to the capture so far,
+    /// waiting (for at most `timeout`) for the ones the runner hasn't returned yet
+    ///
+    /// Like with [`Self::wait_for_async_event`], other events are kept for
+    /// [`CapturingTestDriver::next_event`].
+    pub fn drain_async_events(
+        &mut self,
+        timeout: Duration,
+    ) -> anyhow::Result<Vec<falco_plugin_runner::Event>> {
+        let deadline = Instant::now() + timeout;
+
+        let (mut events, others): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.buffered)
+            .into_iter()
+            .partition(|evt| async_event_name(evt).is_some());
+        self.buffered = others;
+
+        while self.async_seen < self.shared.async_delivered() {
+            match self.pull_event() {
+                Ok(evt) if async_event_name(&evt).is_some() => events.push_back(evt),
+                Ok(evt) => self.buffer(evt)?,
+                Err(ScapStatus::Timeout | ScapStatus::Eof) => self.wait_async_until(deadline),
+                Err(e) => anyhow::bail!("capture failed while draining async events: {:?}", e),
+            }
+
+            anyhow::ensure!(
+                Instant::now() < deadline,
+                "timed out draining async events ({} of {} received)",
+                self.async_seen,
+                self.shared.async_delivered()
+            );
+        }
+
+        Ok(events.into())
+    }
+
+    /// Keep an event for [`CapturingTestDriver::next_event`]
+    fn buffer(&mut self, evt: falco_plugin_runner::Event) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.buffered.len() < MAX_BUFFERED_EVENTS,
+            "more than {} events buffered while looking for async events",
+            MAX_BUFFERED_EVENTS
+        );
+        self.buffered.push_back(evt);
+        Ok(())
+    }
+
+    /// Wait for a new async event, for at most [`ASYNC_POLL_INTERVAL`]
+    /// (and not past `deadline`)
+    fn wait_async_until(&self, deadline: Instant) {
+        let timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .min(ASYNC_POLL_INTERVAL);
+        self.shared.wait_async(self.async_seen, timeout);
+    }
+
+    /// Compare an async event coming out of the capture with the one the plugin emitted
+    fn check_async_event(&self, event: &falco_plugin_runner::Event) {
+        // events not emitted through the harness (if any) have nothing to compare to
+        let Some(expected) = self.shared.next_expected_async() else {
+            return;
+        };
+
+        let source = event_source(event);
+        let violations = expected.check(async_event_plugin_id(event), source.as_deref());
+        self.shared.add_async_violations(violations);
+    }
+
+    /// Whether the plugin of `source` ran out of events, so the runner won't return
+    /// any more of them
+    pub(crate) fn source_ended(&self, source: &str) -> bool {
+        self.shared.source_ended(source)
+    }
+
+    /// All the async events that broke the plugins' declarations
+    ///
+    /// Events with undeclared names (or that can't be parsed) are rejected,
+    /// so they never reach the capture.
+    pub fn async_violations(&self) -> Vec<AsyncViolation> {
+        self.shared.async_violations()
+    }
+
+    /// Get the next event from the runner, keeping the harness state up to date
+    fn pull_event(&mut self) -> Result<falco_plugin_runner::Event, ScapStatus> {
+        tables::submit_mutations(std::mem::take(&mut self.mutations));
+        let next = self.runner.next_event();
+        self.mutations = tables::take_mutations();
+        self.mutation_errors.extend(tables::take_mutation_errors());
+        if let Some(reports) = tables::take_access_reports() {
+            self.access_reports = Some(reports);
+        }
+
+        match next {
+            Ok(evt) => {
+                if let Ok(raw) = raw_event(&evt) {
+                    let state = self.state.process_event(&raw);
+                    if let Some(num) = evt.evt_num {
+                        self.event_states.insert(num, state);
+                    }
+                }
+                if async_event_name(&evt).is_some() {
+                    self.async_seen += 1;
+                    self.check_async_event(&evt);
+                }
+                Ok(evt)
+            }
+            Err(e) => match e.downcast_ref::<ScapStatus>() {
+                Some(ss) => Err(*ss),
+                None => Err(ScapStatus::Failure),
+            },
+        }
+    }
+
+    /// All the messages logged by the plugins so far, including during initialization
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.shared.logs()
+    }
+
+    /// All the messages logged by the plugins so far, clearing the log
+    pub fn take_logs(&mut self) -> Vec<LogRecord> {
+        self.shared.take_logs()
+    }
+
+    /// All the plugin API calls recorded so far, clearing the trace
+    pub fn take_trace(&mut self) -> Vec<TraceRecord> {
+        self.shared.take_trace()
+    }
+
+    /// The threads and file descriptors seen in the capture so far
+    pub fn thread_state(&self) -> &ThreadState {
+        &self.state
+    }
+
+    /// All tables registered by the plugins, with their key types and field schemas
+    ///
+    /// Empty unless [`NativeTestDriver::enable_table_inspection`] was called.
+    pub fn tables(&self) -> &[TableInfo] {
+        &self.tables
+    }
+
+    /// Check that the table exists and all the fields can be written to
+    fn check_writable<'a>(
+        &self,
+        table: &str,
+        fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    ) -> anyhow::Result<TableEntryData> {
+        let info = self
+            .tables
+            .iter()
+            .find(|t| t.name == table)
+            .ok_or_else(|| anyhow::anyhow!("table {} not found", table))?;
+
+        fields
+            .into_iter()
+            .map(|(name, value)| {
+                let field = info
+                    .fields
+                    .iter()
+                    .find(|f| f.name == name)
+                    .ok_or_else(|| anyhow::anyhow!("field {}.{} not found", table, name))?;
+                anyhow::ensure!(!field.read_only, "field {}.{} is read-only", table, name);
+                Ok((name.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// Create a table entry (replacing any existing one) with the given field values
+    ///
+    /// Like all table mutations, this is applied while processing the next event,
+    /// after all the other plugins have parsed it. Errors that can only be detected
+    /// at that point are reported by [`Self::take_table_errors`].
+    pub fn insert_table_entry<'a>(
+        &mut self,
+        table: &str,
+        key: impl Into<TableKey>,
+        fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    ) -> anyhow::Result<()> {
+        let fields = self.check_writable(table, fields)?;
+        self.mutations.push(Mutation::Insert {
+            table: table.to_string(),
+            key: key.into(),
+            fields,
+        });
+        Ok(())
+    }
+
+    /// Set fields of an existing table entry
+    ///
+    /// See [`Self::insert_table_entry`] for when the change takes effect.
+    pub fn update_table_entry<'a>(
+        &mut self,
+        table: &str,
+        key: impl Into<TableKey>,
+        fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    ) -> anyhow::Result<()> {
+        let fields = self.check_writable(table, fields)?;
+        self.mutations.push(Mutation::Update {
+            table: table.to_string(),
+            key: key.into(),
+            fields,
+        });
+        Ok(())
+    }
+
+    /// Remove a table entry
+    ///
+    /// See [`Self::insert_table_entry`] for when the change takes effect.
+    pub fn remove_table_entry(
+        &mut self,
+        table: &str,
+        key: impl Into<TableKey>,
+    ) -> anyhow::Result<()> {
+        self.check_writable(table, [])?;
+        self.mutations.push(Mutation::Remove {
+            table: table.to_string(),
+            key: key.into(),
+        });
+        Ok(())
+    }
+
+    /// Get the errors from applying table mutations so far
+    pub fn take_table_errors(&mut self) -> Vec<anyhow::Error> {
+        std::mem::take(&mut self.mutation_errors)
+    }
+
+    fn inspect_tables(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        request: Request,
+    ) -> anyhow::Result<Response> {
+        tables::submit_request(request);
+        if let Some(Err(e)) = self.runner.extract_field(event, tables::TRIGGER_FIELD) {
+            anyhow::bail!("failed to inspect tables: {}", e);
+        }
+
+        tables::take_response().ok_or_else(|| {
+            anyhow::anyhow!(
+                "the table inspector did not run for this event (is table inspection enabled?)"
+            )
+        })?
+    }
+
+    /// Look up a table entry by key, in the context of `event`
+    pub fn table_entry(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        table: &str,
+        key: impl Into<TableKey>,
+    ) -> anyhow::Result<Option<TableEntryData>> {
+        let request = Request::Lookup {
+            table: table.to_string(),
+            key: key.into(),
+        };
+        match self.inspect_tables(event, request)? {
+            Response::Entry(entry) => Ok(entry),
+            _ => anyhow::bail!("unexpected response from the table inspector"),
+        }
+    }
+
+    /// Read a single field of a table entry, in the context of `event`
+    pub fn table_field(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        table: &str,
+        key: impl Into<TableKey>,
+        field: &str,
+    ) -> anyhow::Result<Option<FieldValue>> {
+        Ok(self
+            .table_entry(event, table, key)?
+            .and_then(|mut entry| entry.remove(field)))
+    }
+
+    /// Read all entries of a table, in the context of `event`
+    pub fn table_entries(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        table: &str,
+    ) -> anyhow::Result<Vec<TableEntryData>> {
+        let request = Request::Entries {
+            table: table.to_string(),
+        };
+        match self.inspect_tables(event, request)? {
+            Response::Entries(entries) => Ok(entries),
+            _ => anyhow::bail!("unexpected response from the table inspector"),
+        }
+    }
+
+    /// What other plugins can do with the fields of `table`
+    ///
+    /// The access is checked while processing the first event, so this fails
+    /// until [`CapturingTestDriver::next_event`] has returned an event.
+    pub fn table_access(&self, table: &str) -> anyhow::Result<&TableAccessReport> {
+        let reports = self
+            .access_reports
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("table access is only checked on the first event"))?;
+        reports
+            .iter()
+            .find(|r| r.table == table)
+            .ok_or_else(|| anyhow::anyhow!("table {} not found", table))
+    }
+
+    fn snapshot(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        tables: Option<Vec<String>>,
+    ) -> anyhow::Result<TablesSnapshot> {
+        match self.inspect_tables(event, Request::Snapshot { tables })? {
+            Response::Snapshot(snapshot) => Ok(snapshot),
+            _ => anyhow::bail!("unexpected response from the table inspector"),
+        }
+    }
+
+    /// Take a snapshot of all the tables, in the context of `event`
+    pub fn snapshot_tables(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+    ) -> anyhow::Result<TablesSnapshot> {
+        self.snapshot(event, None)
+    }
+
+    /// Take a snapshot of the named tables, in the context of `event`
+    pub fn snapshot_selected_tables(
+        &mut self,
+        event: &falco_plugin_runner::Event,
+        tables: &[&str],
+    ) -> anyhow::Result<TablesSnapshot> {
+        let tables = tables.iter().map(|t| t.to_string()).collect();
+        self.snapshot(event, Some(tables))
+    }
+}
 
 impl Debug for NativeTestDriver {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -22,6 +582,12 @@ impl Debug for NativeTestDriver {
     }
 }
 
+impl Debug for ClosedCapture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClosedCapture")
+    }
+}
+
 impl Debug for NativeCapturingTestDriver {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str("NativeCapturingTestDriver")
@@ -33,7 +599,12 @@ impl TestDriver for NativeTestDriver {
     type Plugin = NativePlugin;
 
     fn new() -> anyhow::Result<Self> {
-        Ok(Self(PluginRunner::new()))
+        Ok(Self {
+            runner: PluginRunner::new(),
+            shared: Arc::default(),
+            table_inspection: false,
+            seed: Vec::new(),
+        })
     }
 
     fn register_plugin(
@@ -41,7 +612,9 @@ impl TestDriver for NativeTestDriver {
         api: &'static falco_plugin::api::plugin_api,
         config: &CStr,
     ) -> anyhow::Result<Self::Plugin> {
-        self.0.register_plugin(api, config)?;
+        interpose::register(api, &self.shared, |api| {
+            self.runner.register_plugin(api, config)
+        })?;
         Ok(NativePlugin)
     }
 
@@ -53,8 +626,7 @@ impl TestDriver for NativeTestDriver {
         // no point in making the PluginRunner support raw pointers, just handle it here
         anyhow::ensure!(!api.is_null(), "null pointer in register_plugin");
         let api: &'static falco_plugin::api::plugin_api = &*api;
-        self.0.register_plugin(api, config)?;
-        Ok(NativePlugin)
+        self.register_plugin(api, config)
     }
 
     fn add_filterchecks(&mut self, _plugin: &Self::Plugin, _source: &CStr) -> anyhow::Result<()> {
@@ -62,9 +634,36 @@ impl TestDriver for NativeTestDriver {
         Ok(())
     }
 
-    fn start_capture(self, _name: &CStr, _config: &CStr) -> anyhow::Result<Self::Capturing> {
-        let capturing = self.0.start_capture()?;
-        Ok(NativeCapturingTestDriver(capturing))
+    /// Start the capture, passing `config` (unless empty) to the source plugins' `open`
+    fn start_capture(mut self, _name: &CStr, config: &CStr) -> anyhow::Result<Self::Capturing> {
+        if !config.is_empty() {
+            self.shared.set_open_params(Some(config.to_owned()));
+        }
+
+        // registered last, so that it can import the tables of all the other plugins
+        // (and not interposed, as it's part of the harness rather than the test)
+        let tables = if self.table_inspection {
+            self.runner.register_plugin(&tables::PLUGIN, c"")?;
+            tables::take_table_infos()
+        } else {
+            Vec::new()
+        };
+
+        // the seed is applied when the capture opens
+        tables::submit_mutations(std::mem::take(&mut self.seed));
+        let runner = self.runner.start_capture()?;
+        Ok(NativeCapturingTestDriver {
+            runner,
+            shared: self.shared,
+            state: ThreadState::default(),
+            event_states: BTreeMap::new(),
+            tables,
+            mutations: tables::take_mutations(),
+            mutation_errors: tables::take_mutation_errors(),
+            access_reports: None,
+            buffered: VecDeque::new(),
+            async_seen: 0,
+        })
     }
 }
 
@@ -73,12 +672,9 @@ impl CapturingTestDriver for NativeCapturingTestDriver {
     type Event = falco_plugin_runner::Event;
 
     fn next_event(&mut self) -> Result<Self::Event, ScapStatus> {
-        match self.0.next_event() {
-            Ok(evt) => Ok(evt),
-            Err(e) => match e.downcast_ref::<ScapStatus>() {
-                Some(ss) => Err(*ss),
-                None => Err(ScapStatus::Failure),
-            },
+        match self.buffered.pop_front() {
+            Some(evt) => Ok(evt),
+            None => self.pull_event(),
         }
     }
 
@@ -88,7 +684,41 @@ impl CapturingTestDriver for NativeCapturingTestDriver {
         event: &Self::Event,
     ) -> anyhow::Result<Option<String>> {
         let s = std::str::from_utf8(field_name.to_bytes())?;
-        match self.0.extract_field(event, s) {
+        // evt.* fields and proc.*, thread.* and fd.* fields come from the harness itself,
+        // just like sinsp would provide them
+        if let Ok(raw) = raw_event(event) {
+            let value = event_fields::extract_field(event, &raw, s).or_else(|| {
+                match event.evt_num.and_then(|num| self.event_states.get(&num)) {
+                    Some(state) => state.extract_field(s),
+                    None => EventState::unknown(raw.metadata.tid as i64).extract_field(s),
+                }
+            });
+            if let Some(value) = value {
+                return Ok(value);
+            }
+
+            // fields of plugins that can't see the event have no value, even if the
+            // plugin would provide one (which we report as a violation)
+            let source = event_source(event);
+            if let Some((plugin, Some(filter))) = self.shared.field_owner(s) {
+                if !filter.accepts(raw.event_type, source.as_deref()) {
+                    if let Some(Ok(_)) = self.runner.extract_field(event, s) {
+                        self.shared.add_violation(FilterViolation {
+                            plugin,
+                            kind: ViolationKind::FieldOnHiddenEvent {
+                                field: s.to_string(),
+                            },
+                            event_type: raw.event_type,
+                            source,
+                            evt_num: event.evt_num.unwrap_or_default(),
+                        });
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+
+        match self.runner.extract_field(event, s) {
             None => Ok(None),
             Some(Err(e)) => Err(anyhow::anyhow!("failed to extract field: {}", e)),
             Some(Ok(s)) => Ok(Some(s.to_string())),
@@ -96,7 +726,7 @@ impl CapturingTestDriver for NativeCapturingTestDriver {
     }
 
     fn get_metrics(&mut self) -> anyhow::Result<Vec<SinspMetric>> {
-        let metrics = self.0.get_metrics();
+        let metrics = self.runner.get_metrics();
         Ok(metrics
             .into_iter()
             .flat_map(|m| {
@@ -110,8 +740,14 @@ impl CapturingTestDriver for NativeCapturingTestDriver {
                     MetricValue::Int(v) => v as u64,
                 };
 
+                let metric_type = match m.metric_type {
+                    falco_plugin_runner::MetricType::Monotonic => MetricType::Counter,
+                    falco_plugin_runner::MetricType::NonMonotonic => MetricType::Gauge,
+                };
+
                 Some(SinspMetric {
                     name: m.name,
+                    metric_type,
                     value,
                 })
             })
@@ -120,3 +756,77 @@ impl CapturingTestDriver for NativeCapturingTestDriver {
 }
 
 pub type Driver = NativeTestDriver;
+
+#[cfg(test)]
+mod tests {
+    use super::NativeTestDriver;
+    use crate::{async_emitter_plugin, faulty_plugin, syscall_source_plugin};
+    use crate::{EventExt, TestDriver};
+    use falco_plugin::event::events::types::PPME_ASYNCEVENT_E;
+    use std::time::Duration;
+
+    #[test]
+    fn list_open_params() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        let err = driver.list_open_params("no-such-plugin").unwrap_err();
+        assert!(err.to_string().contains("no plugin called"), "{}", err);
+    }
+
+    #[test]
+    fn wait_for_async_event() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&async_emitter_plugin::PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let evt = driver
+            .wait_for_async_event("async", Duration::from_secs(5))
+            .unwrap();
+        let evt = evt.load::<PPME_ASYNCEVENT_E>().unwrap().params;
+        assert_eq!(evt.name, Some(c"async"));
+        assert_eq!(evt.data, Some(&b"hello world"[..]));
+
+        // the plugin only emits a single event
+        assert!(driver
+            .drain_async_events(Duration::from_secs(5))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn wait_for_missing_async_event() {
+        // the source never stops emitting events, and there are no async events
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&faulty_plugin::PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let res = driver.wait_for_async_event("async", Duration::from_millis(100));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn hold_async_events() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.hold_async_events();
+        driver
+            .register_plugin(&async_emitter_plugin::PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        // the event only reaches the capture once we let it through
+        let timeout = Duration::from_secs(5);
+        assert!(driver.drain_async_events(timeout).unwrap().is_empty());
+        assert_eq!(driver.release_async_events(), 1);
+
+        let events = driver.drain_async_events(timeout).unwrap();
+        assert_eq!(events.len(), 1);
+        let evt = events[0].load::<PPME_ASYNCEVENT_E>().unwrap().params;
+        assert_eq!(evt.data, Some(&b"hello world"[..]));
+    }
+}
@@ -0,0 +1,490 @@
+use crate::syscall_source_plugin;
+use falco_plugin::anyhow;
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::types::{
+    PPME_SOCKET_ACCEPT4_6_E, PPME_SOCKET_ACCEPT4_6_X, PPME_SOCKET_BIND_E, PPME_SOCKET_BIND_X,
+    PPME_SOCKET_CONNECT_E, PPME_SOCKET_CONNECT_X, PPME_SOCKET_RECVFROM_E, PPME_SOCKET_RECVFROM_X,
+    PPME_SOCKET_SENDTO_E, PPME_SOCKET_SENDTO_X, PPME_SOCKET_SOCKET_E, PPME_SOCKET_SOCKET_X,
+    PPME_SYSCALL_CLOSE_E, PPME_SYSCALL_CLOSE_X,
+};
+use falco_plugin::event::events::{Event, EventMetadata, EventToBytes, PayloadToBytes};
+use falco_plugin::event::fields::types::{
+    PT_ENUMFLAGS32_socket_family, PT_ERRNO, PT_FD, PT_FSPATH, PT_PORT, PT_SOCKADDR, PT_SOCKTUPLE,
+};
+use falco_plugin::extract::EventInput;
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::source::{EventBatch, SourcePlugin, SourcePluginInstance};
+use falco_plugin::tables::TablesInput;
+use falco_plugin::{static_plugin, FailureReason};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+const SOCK_STREAM: u32 = 1;
+const IPPROTO_TCP: u32 = 6;
+
+const SERVER_TID: u64 = 100;
+const CLIENT_TID: u64 = 200;
+
+/// Endpoints and payload sizes used to build the network event stream
+///
+/// All fields are optional, so an empty JSON object (`{}`) is a valid configuration.
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Client side of the IPv4 connection
+    pub client_v4: SocketAddrV4,
+    /// Server side of the IPv4 connection
+    pub server_v4: SocketAddrV4,
+    /// Client side of the IPv6 connection
+    pub client_v6: SocketAddrV6,
+    /// Server side of the IPv6 connection
+    pub server_v6: SocketAddrV6,
+    /// Path of the unix socket the server listens on
+    pub unix_path: String,
+    /// Number of bytes sent from the client to the server over each connection
+    pub payload_size: usize,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            client_v4: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000),
+            server_v4: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8080),
+            client_v6: SocketAddrV6::new(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), 40000, 0, 0),
+            server_v6: SocketAddrV6::new(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2), 8080, 0, 0),
+            unix_path: String::from("/run/rustlings.sock"),
+            payload_size: 16,
+        }
+    }
+}
+
+/// The addresses of a single connection, in the shape the event params expect
+enum Endpoints<'a> {
+    V4 {
+        client: SocketAddrV4,
+        server: SocketAddrV4,
+    },
+    V6 {
+        client: SocketAddrV6,
+        server: SocketAddrV6,
+    },
+    Unix {
+        path: &'a str,
+    },
+}
+
+impl<'a> Endpoints<'a> {
+    fn domain(&self) -> PT_ENUMFLAGS32_socket_family {
+        match self {
+            Endpoints::V4 { .. } => PT_ENUMFLAGS32_socket_family::AF_INET,
+            Endpoints::V6 { .. } => PT_ENUMFLAGS32_socket_family::AF_INET6,
+            Endpoints::Unix { .. } => PT_ENUMFLAGS32_socket_family::AF_UNIX,
+        }
+    }
+
+    fn proto(&self) -> u32 {
+        match self {
+            Endpoints::Unix { .. } => 0,
+            _ => IPPROTO_TCP,
+        }
+    }
+
+    fn server_addr(&self) -> PT_SOCKADDR<'a> {
+        match self {
+            Endpoints::V4 { server, .. } => PT_SOCKADDR::V4((*server.ip(), PT_PORT(server.port()))),
+            Endpoints::V6 { server, .. } => PT_SOCKADDR::V6((*server.ip(), PT_PORT(server.port()))),
+            Endpoints::Unix { path } => PT_SOCKADDR::Unix(PT_FSPATH::new(*path)),
+        }
+    }
+
+    fn tuple(&self) -> PT_SOCKTUPLE<'a> {
+        match self {
+            Endpoints::V4 { client, server } => PT_SOCKTUPLE::V4 {
+                source: (*client.ip(), PT_PORT(client.port())),
+                dest: (*server.ip(), PT_PORT(server.port())),
+            },
+            Endpoints::V6 { client, server } => PT_SOCKTUPLE::V6 {
+                source: (*client.ip(), PT_PORT(client.port())),
+                dest: (*server.ip(), PT_PORT(server.port())),
+            },
+            Endpoints::Unix { path } => PT_SOCKTUPLE::Unix {
+                source_ptr: 0xc11e47,
+                dest_ptr: 0x5e47e4,
+                path: PT_FSPATH::new(*path),
+            },
+        }
+    }
+}
+
+struct EventBuilder {
+    evts: VecDeque<Vec<u8>>,
+    ts: u64,
+}
+
+impl EventBuilder {
+    fn push<T: PayloadToBytes>(&mut self, tid: u64, payload: T) -> Result<(), Error> {
+        self.ts += 1;
+        let evt = Event {
+            metadata: EventMetadata { ts: self.ts, tid },
+            params: payload,
+        };
+
+        let mut buf = Vec::new();
+        evt.write(&mut buf)?;
+        self.evts.push_back(buf);
+        Ok(())
+    }
+
+    fn socket(&mut self, tid: u64, endpoints: &Endpoints, fd: i64) -> Result<(), Error> {
+        self.push(
+            tid,
+            PPME_SOCKET_SOCKET_E {
+                domain: Some(endpoints.domain()),
+                type_: Some(SOCK_STREAM),
+                proto: Some(endpoints.proto()),
+            },
+        )?;
+        self.push(
+            tid,
+            PPME_SOCKET_SOCKET_X {
+                fd: Some(PT_FD(fd)),
+            },
+        )
+    }
+
+    fn close(&mut self, tid: u64, fd: i64) -> Result<(), Error> {
+        self.push(
+            tid,
+            PPME_SYSCALL_CLOSE_E {
+                fd: Some(PT_FD(fd)),
+            },
+        )?;
+        self.push(
+            tid,
+            PPME_SYSCALL_CLOSE_X {
+                res: Some(PT_ERRNO(0)),
+            },
+        )
+    }
+
+    /// A full connection lifecycle: the server listens, the client connects
+    /// and sends `payload`, the server receives it, and both sides close
+    fn connection(&mut self, endpoints: &Endpoints, payload: &[u8]) -> Result<(), Error> {
+        let (listen_fd, client_fd, conn_fd) = (3, 4, 5);
+        let size = u32::try_from(payload.len())?;
+
+        self.socket(SERVER_TID, endpoints, listen_fd)?;
+        self.push(
+            SERVER_TID,
+            PPME_SOCKET_BIND_E {
+                fd: Some(PT_FD(listen_fd)),
+            },
+        )?;
+        self.push(
+            SERVER_TID,
+            PPME_SOCKET_BIND_X {
+                res: Some(PT_ERRNO(0)),
+                addr: Some(endpoints.server_addr()),
+            },
+        )?;
+
+        self.socket(CLIENT_TID, endpoints, client_fd)?;
+        self.push(
+            CLIENT_TID,
+            PPME_SOCKET_CONNECT_E {
+                fd: Some(PT_FD(client_fd)),
+                addr: Some(endpoints.server_addr()),
+            },
+        )?;
+        self.push(
+            CLIENT_TID,
+            PPME_SOCKET_CONNECT_X {
+                res: Some(PT_ERRNO(0)),
+                tuple: Some(endpoints.tuple()),
+                fd: Some(PT_FD(client_fd)),
+            },
+        )?;
+
+        self.push(SERVER_TID, PPME_SOCKET_ACCEPT4_6_E { flags: Some(0) })?;
+        self.push(
+            SERVER_TID,
+            PPME_SOCKET_ACCEPT4_6_X {
+                fd: Some(PT_FD(conn_fd)),
+                tuple: Some(endpoints.tuple()),
+                queuepct: Some(0),
+                queuelen: Some(0),
+                queuemax: Some(128),
+            },
+        )?;
+
+        self.push(
+            CLIENT_TID,
+            PPME_SOCKET_SENDTO_E {
+                fd: Some(PT_FD(client_fd)),
+                size: Some(size),
+                tuple: Some(endpoints.tuple()),
+            },
+        )?;
+        self.push(
+            CLIENT_TID,
+            PPME_SOCKET_SENDTO_X {
+                res: Some(PT_ERRNO(i64::from(size))),
+                data: Some(payload),
+            },
+        )?;
+
+        self.push(
+            SERVER_TID,
+            PPME_SOCKET_RECVFROM_E {
+                fd: Some(PT_FD(conn_fd)),
+                size: Some(size),
+            },
+        )?;
+        self.push(
+            SERVER_TID,
+            PPME_SOCKET_RECVFROM_X {
+                res: Some(PT_ERRNO(i64::from(size))),
+                data: Some(payload),
+                tuple: Some(endpoints.tuple()),
+            },
+        )?;
+
+        self.close(CLIENT_TID, client_fd)?;
+        self.close(SERVER_TID, conn_fd)?;
+        self.close(SERVER_TID, listen_fd)
+    }
+}
+
+/// Build the event stream: one connection over IPv4, one over IPv6 and one over
+/// a unix socket, in that order
+///
+/// Fails if the payload doesn't fit in a single event parameter.
+fn build_network_events(config: &NetworkConfig) -> Result<VecDeque<Vec<u8>>, Error> {
+    anyhow::ensure!(
+        config.payload_size <= u16::MAX as usize,
+        "payload_size must be at most {} bytes",
+        u16::MAX
+    );
+
+    let payload: Vec<u8> = (b'a'..=b'z').cycle().take(config.payload_size).collect();
+    let mut builder = EventBuilder {
+        evts: VecDeque::new(),
+        ts: 0,
+    };
+
+    builder.connection(
+        &Endpoints::V4 {
+            client: config.client_v4,
+            server: config.server_v4,
+        },
+        &payload,
+    )?;
+    builder.connection(
+        &Endpoints::V6 {
+            client: config.client_v6,
+            server: config.server_v6,
+        },
+        &payload,
+    )?;
+    builder.connection(
+        &Endpoints::Unix {
+            path: &config.unix_path,
+        },
+        &payload,
+    )?;
+
+    Ok(builder.evts)
+}
+
+struct NetworkSourcePlugin(VecDeque<Vec<u8>>);
+
+impl Plugin for NetworkSourcePlugin {
+    const NAME: &'static CStr = c"network";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Dummy network syscall source plugin.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<NetworkConfig>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self(build_network_events(&config)?))
+    }
+}
+
+impl SourcePlugin for NetworkSourcePlugin {
+    type Instance = NetworkSourcePluginInstance;
+    const EVENT_SOURCE: &'static CStr = c"syscall";
+    const PLUGIN_ID: u32 = 0;
+
+    fn open(&mut self, _params: Option<&str>) -> Result<Self::Instance, Error> {
+        Ok(NetworkSourcePluginInstance(self.0.clone()))
+    }
+
+    fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
+        syscall_source_plugin::event_to_string(event)
+    }
+}
+
+struct NetworkSourcePluginInstance(VecDeque<Vec<u8>>);
+
+impl SourcePluginInstance for NetworkSourcePluginInstance {
+    type Plugin = NetworkSourcePlugin;
+
+    fn next_batch(
+        &mut self,
+        _plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        match self.0.pop_front() {
+            Some(event) => {
+                batch.add(&*event)?;
+                Ok(())
+            }
+            None => Err(FailureReason::Eof)?,
+        }
+    }
+}
+
+static_plugin!(NETWORK_SOURCE_PLUGIN = NetworkSourcePlugin);
+
+/// A `syscall` source emitting socket, bind, connect, accept, sendto, recvfrom and close
+/// events for an IPv4, an IPv6 and a unix socket connection
+///
+/// Requires a JSON config (see [`NetworkConfig`]); `{}` uses the default endpoints.
+pub static PLUGIN: falco_plugin::api::plugin_api = NETWORK_SOURCE_PLUGIN;
+
+#[cfg(test)]
+mod tests {
+    use crate::native::NativeTestDriver;
+    use crate::{init_plugin, CapturingTestDriver, EventExt, TestDriver};
+    use falco_plugin::event::events::types::{
+        PPME_SOCKET_ACCEPT4_6_X, PPME_SOCKET_BIND_X, PPME_SOCKET_CONNECT_X, PPME_SOCKET_SENDTO_X,
+    };
+    use falco_plugin::event::fields::types::{PT_SOCKADDR, PT_SOCKTUPLE};
+    use falco_plugin_runner::Event;
+    use std::ffi::CStr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn capture(config: &CStr) -> Vec<Event> {
+        let (driver, _) = init_plugin::<NativeTestDriver>(&super::PLUGIN, config).unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = driver.next_event() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn sockaddr_params() {
+        let events = capture(c"{}");
+        assert_eq!(events.len(), 60);
+
+        let addrs: Vec<String> = events
+            .iter()
+            .filter_map(|evt| evt.load::<PPME_SOCKET_BIND_X>().ok())
+            .map(|evt| match evt.params.addr {
+                Some(PT_SOCKADDR::V4((ip, port))) => {
+                    assert_eq!(ip, Ipv4Addr::new(10, 0, 0, 2));
+                    format!("v4:{}", port.0)
+                }
+                Some(PT_SOCKADDR::V6((ip, port))) => {
+                    assert_eq!(ip, Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+                    format!("v6:{}", port.0)
+                }
+                Some(PT_SOCKADDR::Unix(path)) => {
+                    format!("unix:{}", String::from_utf8_lossy(path.as_bytes()))
+                }
+                _ => panic!("unexpected bind address"),
+            })
+            .collect();
+
+        assert_eq!(
+            addrs,
+            vec!["v4:8080", "v6:8080", "unix:/run/rustlings.sock"]
+        );
+    }
+
+    #[test]
+    fn socktuple_params() {
+        let events = capture(c"{}");
+
+        // connect, accept and sendto all carry the same tuple for each connection
+        let tuples: Vec<String> = events
+            .iter()
+            .filter_map(|evt| {
+                if let Ok(evt) = evt.load::<PPME_SOCKET_CONNECT_X>() {
+                    evt.params.tuple
+                } else if let Ok(evt) = evt.load::<PPME_SOCKET_ACCEPT4_6_X>() {
+                    evt.params.tuple
+                } else {
+                    None
+                }
+            })
+            .map(|tuple| match tuple {
+                PT_SOCKTUPLE::V4 { source, dest } => {
+                    assert_eq!(source.0, Ipv4Addr::new(10, 0, 0, 1));
+                    assert_eq!(dest.0, Ipv4Addr::new(10, 0, 0, 2));
+                    format!("v4:{}->{}", source.1 .0, dest.1 .0)
+                }
+                PT_SOCKTUPLE::V6 { source, dest } => {
+                    assert_eq!(source.0, Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+                    assert_eq!(dest.0, Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+                    format!("v6:{}->{}", source.1 .0, dest.1 .0)
+                }
+                PT_SOCKTUPLE::Unix { path, .. } => {
+                    format!("unix:{}", String::from_utf8_lossy(path.as_bytes()))
+                }
+                _ => panic!("unexpected tuple"),
+            })
+            .collect();
+
+        assert_eq!(
+            tuples,
+            vec![
+                "v4:40000->8080",
+                "v4:40000->8080",
+                "v6:40000->8080",
+                "v6:40000->8080",
+                "unix:/run/rustlings.sock",
+                "unix:/run/rustlings.sock",
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_endpoints_and_payload() {
+        let events = capture(c"{\"server_v4\": \"192.168.1.1:443\", \"payload_size\": 3}");
+
+        let bind = events
+            .iter()
+            .find_map(|evt| evt.load::<PPME_SOCKET_BIND_X>().ok())
+            .unwrap();
+        match bind.params.addr {
+            Some(PT_SOCKADDR::V4((ip, port))) => {
+                assert_eq!(ip, Ipv4Addr::new(192, 168, 1, 1));
+                assert_eq!(port.0, 443);
+            }
+            _ => panic!("expected an IPv4 address"),
+        }
+
+        let sent: Vec<&[u8]> = events
+            .iter()
+            .filter_map(|evt| evt.load::<PPME_SOCKET_SENDTO_X>().ok())
+            .filter_map(|evt| evt.params.data)
+            .collect();
+        assert_eq!(sent, vec![&b"abc"[..]; 3]);
+    }
+
+    #[test]
+    fn oversized_payload() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let res = driver.register_plugin(&super::PLUGIN, c"{\"payload_size\": 65536}");
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! Open parameters advertised by source plugins
+//!
+//! Source plugins can list the values they accept as open parameters (`list_open_params`),
+//! for Falco to show them to the user. The native driver queries the list right after
+//! the plugin is initialized, and passes the `config` given to
+//! [`start_capture`](crate::TestDriver::start_capture) to the plugins' `open`.
+
+use falco_plugin::serde::{Deserialize, Serialize};
+
+/// A single open parameter value a plugin accepts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct OpenParam {
+    pub value: String,
+    #[serde(default)]
+    pub desc: String,
+    /// If not empty, the value is a list of items separated by this string
+    #[serde(default)]
+    pub separator: String,
+}
+
+/// Parse the JSON returned by `list_open_params`
+///
+/// Plugins returning nothing (or an empty string) accept no particular values.
+pub(crate) fn parse_open_params(json: &str) -> Result<Vec<OpenParam>, serde_json::Error> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(json)
+}
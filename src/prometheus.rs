@@ -0,0 +1,534 @@
+//! Prometheus text rendering of plugin metrics
+//!
+//! Falco exports plugin metrics in the Prometheus text exposition format, qualified with
+//! the `falcosecurity` namespace and the `plugins` subsystem, with a `_total` suffix
+//! for monotonic counters and the original metric name in a `raw_name` label:
+//!
+//! ```text
+//! # HELP falcosecurity_plugins_events_total https://falco.org/docs/metrics/
+//! # TYPE falcosecurity_plugins_events_total counter
+//! falcosecurity_plugins_events_total{raw_name="events"} 42
+//! ```
+//!
+//! [`PrometheusExporter`] renders [`SinspMetric`]s that way, [`parse`] reads the format
+//! back (from the exporter or from a running Falco), and [`rates_per_event`] compares
+//! two snapshots of the same metrics.
+
+use crate::SinspMetric;
+use falco_plugin::anyhow;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The HELP text Falco uses for all its metrics
+pub const FALCO_HELP: &str = "https://falco.org/docs/metrics/";
+
+/// The Prometheus metric type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    /// A monotonic counter (`MetricType::Monotonic` in the plugin API)
+    Counter,
+    /// A value that can go down as well as up (`MetricType::NonMonotonic`)
+    Gauge,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        }
+    }
+}
+
+/// Renders plugin metrics the way Falco exports them
+///
+/// Monotonic metrics are rendered as counters, all others as gauges.
+#[derive(Debug, Clone)]
+pub struct PrometheusExporter {
+    namespace: String,
+    subsystem: String,
+    help: BTreeMap<String, String>,
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> Self {
+        Self {
+            namespace: String::from("falcosecurity"),
+            subsystem: String::from("plugins"),
+            help: BTreeMap::new(),
+        }
+    }
+}
+
+impl PrometheusExporter {
+    /// An exporter using Falco's namespace and subsystem
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a different namespace and subsystem (`falcosecurity` and `plugins` by default)
+    pub fn with_prefix(mut self, namespace: &str, subsystem: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self.subsystem = subsystem.to_string();
+        self
+    }
+
+    /// Use `help` instead of [`FALCO_HELP`] for the metric `name`
+    pub fn help(mut self, name: &str, help: &str) -> Self {
+        self.help.insert(name.to_string(), help.to_string());
+        self
+    }
+
+    /// The fully qualified name of a metric, as exported
+    pub fn exported_name(&self, name: &str, metric_type: MetricType) -> String {
+        let mut exported = [self.namespace.as_str(), self.subsystem.as_str(), name]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| sanitize(part))
+            .collect::<Vec<_>>()
+            .join("_");
+        if metric_type == MetricType::Counter && !exported.ends_with("_total") {
+            exported.push_str("_total");
+        }
+        exported
+    }
+
+    /// Render all the metrics, with HELP and TYPE lines
+    ///
+    /// Metrics are rendered in order, except that metrics exported under the same name
+    /// (e.g. reported by two plugins) are grouped under the first one, with a single
+    /// HELP and TYPE line, as Prometheus expects.
+    pub fn render(&self, metrics: &[SinspMetric]) -> String {
+        let mut families: Vec<(String, Vec<&SinspMetric>)> = Vec::new();
+        for metric in metrics {
+            let name = self.exported_name(&metric.name, metric.metric_type);
+            match families.iter_mut().find(|(family, _)| *family == name) {
+                Some((_, samples)) => samples.push(metric),
+                None => families.push((name, vec![metric])),
+            }
+        }
+
+        let mut out = String::new();
+        for (name, samples) in families {
+            let first = samples[0];
+            let help = self
+                .help
+                .get(&first.name)
+                .map_or(FALCO_HELP, String::as_str);
+
+            // writing to a String can't fail
+            let _ = writeln!(out, "# HELP {} {}", name, escape_help(help));
+            let _ = writeln!(out, "# TYPE {} {}", name, first.metric_type.as_str());
+            for metric in samples {
+                let _ = writeln!(
+                    out,
+                    "{}{{raw_name=\"{}\"}} {}",
+                    name,
+                    escape_label(&metric.name),
+                    metric.value
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Replace everything Prometheus doesn't allow in metric names with underscores
+fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
+
+/// A single sample read back from the Prometheus text format
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedMetric {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+    /// From the `# TYPE` line, if there was one
+    pub metric_type: Option<MetricType>,
+    /// From the `# HELP` line, if there was one
+    pub help: Option<String>,
+}
+
+impl ParsedMetric {
+    /// The `raw_name` label, i.e. the name the plugin reported
+    pub fn raw_name(&self) -> Option<&str> {
+        self.labels.get("raw_name").map(String::as_str)
+    }
+}
+
+/// Parse the Prometheus text exposition format
+///
+/// Only counters and gauges are understood; samples of other types are parsed
+/// without a `metric_type`. Timestamps after the value are ignored.
+pub fn parse(text: &str) -> anyhow::Result<Vec<ParsedMetric>> {
+    let mut types = BTreeMap::new();
+    let mut help = BTreeMap::new();
+    let mut samples = Vec::new();
+
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let context = || format!("line {}: {}", lineno + 1, line);
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut parts = comment.trim_start().splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("HELP"), Some(name), text) => {
+                    help.insert(name.to_string(), unescape(text.unwrap_or_default()));
+                }
+                (Some("TYPE"), Some(name), Some(ty)) => {
+                    let ty = match ty.trim() {
+                        "counter" => Some(MetricType::Counter),
+                        "gauge" => Some(MetricType::Gauge),
+                        _ => None,
+                    };
+                    types.insert(name.to_string(), ty);
+                }
+                // any other comment
+                _ => {}
+            }
+            continue;
+        }
+
+        let (name, labels, rest) = parse_sample(line)
+            .ok_or_else(|| anyhow::anyhow!("malformed sample at {}", context()))?;
+        let value = rest.split_whitespace().next().unwrap_or_default();
+        let value =
+            parse_value(value).ok_or_else(|| anyhow::anyhow!("invalid value at {}", context()))?;
+
+        samples.push(ParsedMetric {
+            metric_type: types.get(&name).copied().flatten(),
+            help: help.get(&name).cloned(),
+            name,
+            labels,
+            value,
+        });
+    }
+
+    Ok(samples)
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        _ => value.parse().ok(),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Split a sample line into its name, labels and whatever follows them
+fn parse_sample(line: &str) -> Option<(String, BTreeMap<String, String>, &str)> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let name = line[..name_end].to_string();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut labels = BTreeMap::new();
+    let mut rest = &line[name_end..];
+    if let Some(label_text) = rest.strip_prefix('{') {
+        let mut chars = label_text.char_indices().peekable();
+        loop {
+            // skip separators
+            while chars
+                .next_if(|(_, c)| *c == ',' || c.is_whitespace())
+                .is_some()
+            {}
+            match chars.peek() {
+                Some((i, '}')) => {
+                    rest = &label_text[i + 1..];
+                    break;
+                }
+                None => return None,
+                _ => {}
+            }
+
+            let mut key = String::new();
+            for (_, c) in chars.by_ref() {
+                if c == '=' {
+                    break;
+                }
+                key.push(c);
+            }
+            if chars.next().map(|(_, c)| c) != Some('"') {
+                return None;
+            }
+
+            let mut value = String::new();
+            loop {
+                match chars.next()?.1 {
+                    '"' => break,
+                    '\\' => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        other => value.push(other),
+                    },
+                    c => value.push(c),
+                }
+            }
+            labels.insert(key.trim().to_string(), value);
+        }
+    }
+
+    Some((name, labels, rest))
+}
+
+/// How much each metric changed per event between two snapshots
+///
+/// `events` is the number of events captured between the snapshots. Metrics missing
+/// from either snapshot are left out, and so are all metrics if no events were captured.
+/// Counters that went down (e.g. after a restart) get a negative rate.
+pub fn rates_per_event(
+    before: &[SinspMetric],
+    after: &[SinspMetric],
+    events: u64,
+) -> BTreeMap<String, f64> {
+    if events == 0 {
+        return BTreeMap::new();
+    }
+
+    after
+        .iter()
+        .filter_map(|new| {
+            let old = before.iter().find(|old| old.name == new.name)?;
+            let delta = new.value as f64 - old.value as f64;
+            Some((new.name.clone(), delta / events as f64))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, rates_per_event, MetricType, PrometheusExporter, FALCO_HELP};
+    use crate::SinspMetric;
+    use std::collections::BTreeMap;
+
+    fn metric(name: &str, metric_type: MetricType, value: u64) -> SinspMetric {
+        SinspMetric {
+            name: name.to_string(),
+            metric_type,
+            value,
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let text = concat!(
+            "# HELP m a \\\\ backslash\\nand a newline\n",
+            "m{path=\"C:\\\\tmp\",quote=\"say \\\"hi\\\"\",nl=\"a\\nb\"} 1\n",
+        );
+        let parsed = parse(text).unwrap();
+        assert_eq!(
+            parsed[0].help.as_deref(),
+            Some("a \\ backslash\nand a newline")
+        );
+        assert_eq!(parsed[0].labels["path"], "C:\\tmp");
+        assert_eq!(parsed[0].labels["quote"], "say \"hi\"");
+        assert_eq!(parsed[0].labels["nl"], "a\nb");
+    }
+
+    #[test]
+    fn labels() {
+        let text = concat!(
+            "no_labels 1\n",
+            "empty{} 2\n",
+            "spaced{ a=\"1\" , b=\"2\", } 3 1700000000000\n",
+            "braces{a=\"}{,\"} 4\n",
+        );
+        let parsed = parse(text).unwrap();
+        let labels: Vec<_> = parsed.iter().map(|m| m.labels.clone()).collect();
+        let pairs = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            labels,
+            vec![
+                pairs(&[]),
+                pairs(&[]),
+                pairs(&[("a", "1"), ("b", "2")]),
+                pairs(&[("a", "}{,")]),
+            ]
+        );
+        // the timestamp is ignored
+        let values: Vec<_> = parsed.iter().map(|m| m.value).collect();
+        assert_eq!(values, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn special_values() {
+        let parsed = parse("a +Inf\nb -Inf\nc NaN\nd 1e3\n").unwrap();
+        assert_eq!(parsed[0].value, f64::INFINITY);
+        assert_eq!(parsed[1].value, f64::NEG_INFINITY);
+        assert!(parsed[2].value.is_nan());
+        assert_eq!(parsed[3].value, 1000.0);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "no_value",
+            "{a=\"1\"} 1",
+            "unclosed{a=\"1\" 1",
+            "unquoted{a=1} 1",
+            "unterminated{a=\"1} 1",
+            "bad_value 1.2.3",
+        ] {
+            let err = parse(&format!("ok 1\n{}\n", line)).unwrap_err();
+            assert!(err.to_string().contains("line 2"), "{}: {}", line, err);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let metrics = [
+            metric("events", MetricType::Counter, 10),
+            metric("queue length", MetricType::Gauge, 3),
+        ];
+        let text = PrometheusExporter::new()
+            .help("queue length", "how \\ many\nevents wait")
+            .render(&metrics);
+        assert!(text.contains("# TYPE falcosecurity_plugins_events_total counter\n"));
+        assert!(text.contains("# TYPE falcosecurity_plugins_queue_length gauge\n"));
+
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "falcosecurity_plugins_events_total");
+        assert_eq!(parsed[0].raw_name(), Some("events"));
+        assert_eq!(parsed[0].value, 10.0);
+        assert_eq!(parsed[0].metric_type, Some(MetricType::Counter));
+        assert_eq!(parsed[0].help.as_deref(), Some(FALCO_HELP));
+        assert_eq!(parsed[1].name, "falcosecurity_plugins_queue_length");
+        assert_eq!(parsed[1].raw_name(), Some("queue length"));
+        assert_eq!(parsed[1].metric_type, Some(MetricType::Gauge));
+        assert_eq!(parsed[1].help.as_deref(), Some("how \\ many\nevents wait"));
+    }
+
+    #[test]
+    fn same_name_twice() {
+        let metrics = [
+            metric("events", MetricType::Counter, 1),
+            metric("other", MetricType::Gauge, 2),
+            metric("events", MetricType::Counter, 3),
+        ];
+        let text = PrometheusExporter::new().render(&metrics);
+        assert_eq!(
+            text.matches("# HELP falcosecurity_plugins_events_total ")
+                .count(),
+            1
+        );
+        assert_eq!(
+            text.matches("# TYPE falcosecurity_plugins_events_total ")
+                .count(),
+            1
+        );
+
+        // the samples are grouped under their family
+        let names: Vec<_> = parse(&text)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.name, m.value))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (String::from("falcosecurity_plugins_events_total"), 1.0),
+                (String::from("falcosecurity_plugins_events_total"), 3.0),
+                (String::from("falcosecurity_plugins_other"), 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn export_metrics() {
+        let snapshot = |events, last_value| {
+            [
+                metric("events", MetricType::Counter, events),
+                metric("rejected", MetricType::Counter, 0),
+                metric("last_value", MetricType::Gauge, last_value),
+            ]
+        };
+        let before = snapshot(0, 0);
+        let after = snapshot(10, 7);
+
+        // one event generated per event captured, no rejections
+        let rates = rates_per_event(&before, &after, 10);
+        assert_eq!(rates["events"], 1.0);
+        assert_eq!(rates["rejected"], 0.0);
+
+        let text = PrometheusExporter::new().render(&after);
+        assert!(text.contains("# TYPE falcosecurity_plugins_events_total counter\n"));
+        assert!(text.contains("# TYPE falcosecurity_plugins_last_value gauge\n"));
+
+        let parsed = parse(&text).unwrap();
+        let events = parsed
+            .iter()
+            .find(|m| m.name == "falcosecurity_plugins_events_total")
+            .unwrap();
+        assert_eq!(events.value, 10.0);
+        assert_eq!(events.metric_type, Some(MetricType::Counter));
+        assert_eq!(events.raw_name(), Some("events"));
+        assert_eq!(events.help.as_deref(), Some(FALCO_HELP));
+    }
+
+    #[test]
+    fn rates() {
+        let before = [
+            metric("events", MetricType::Counter, 5),
+            metric("gone", MetricType::Counter, 1),
+        ];
+        let after = [
+            metric("events", MetricType::Counter, 25),
+            metric("new", MetricType::Counter, 1),
+        ];
+        let rates = rates_per_event(&before, &after, 10);
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates["events"], 2.0);
+        assert!(rates_per_event(&before, &after, 0).is_empty());
+    }
+}
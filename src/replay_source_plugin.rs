@@ -0,0 +1,233 @@
+//! A `syscall` source replaying hand-built events, for the harness's own tests
+//!
+//! The events are passed (base64-encoded) in the config, so every test can use its own
+//! scenario. [`process_lifecycle`] builds the one most tests need.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::EventInput;
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::source::{EventBatch, SourcePlugin, SourcePluginInstance};
+use falco_plugin::tables::TablesInput;
+use falco_plugin::{static_plugin, FailureReason};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+
+/// Encode an event with the given (already encoded) parameters
+///
+/// Events may carry fewer parameters than their type defines; the missing ones
+/// decode as `None`, just like with events recorded by older drivers.
+pub(crate) fn event(ts: u64, tid: u64, event_type: EventType, params: &[Vec<u8>]) -> Vec<u8> {
+    let len = 26 + 2 * params.len() + params.iter().map(Vec::len).sum::<usize>();
+
+    let mut buf = Vec::with_capacity(len);
+    buf.extend_from_slice(&ts.to_le_bytes());
+    buf.extend_from_slice(&tid.to_le_bytes());
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    buf.extend_from_slice(&(event_type as u16).to_le_bytes());
+    buf.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    for param in params {
+        buf.extend_from_slice(&(param.len() as u16).to_le_bytes());
+    }
+    for param in params {
+        buf.extend_from_slice(param);
+    }
+    buf
+}
+
+/// Encode a string parameter (with its NUL terminator)
+pub(crate) fn str_param(s: &str) -> Vec<u8> {
+    let mut buf = s.as_bytes().to_vec();
+    buf.push(0);
+    buf
+}
+
+/// Encode a 64-bit integer parameter (fd, pid, errno and friends)
+pub(crate) fn i64_param(v: i64) -> Vec<u8> {
+    v.to_le_bytes().to_vec()
+}
+
+/// Encode a 32-bit unsigned parameter
+pub(crate) fn u32_param(v: u32) -> Vec<u8> {
+    v.to_le_bytes().to_vec()
+}
+
+/// The first parameters of clone and execve exit events, which share their layout:
+/// res, exe, args, tid, pid, ptid, cwd, fdlimit, pgft_maj, pgft_min, vm_size, vm_rss,
+/// vm_swap, comm
+fn process_params(
+    res: i64,
+    exe: &str,
+    args: &[u8],
+    ids: [i64; 3],
+    cwd: &str,
+    comm: &str,
+) -> Vec<Vec<u8>> {
+    let [tid, pid, ptid] = ids;
+    vec![
+        i64_param(res),
+        str_param(exe),
+        args.to_vec(),
+        i64_param(tid),
+        i64_param(pid),
+        i64_param(ptid),
+        str_param(cwd),
+        i64_param(1024),
+        0u64.to_le_bytes().to_vec(),
+        0u64.to_le_bytes().to_vec(),
+        u32_param(0),
+        u32_param(0),
+        u32_param(0),
+        str_param(comm),
+    ]
+}
+
+/// The shell (tid 100) has `/var/log/sh.log` open as fd 4 and forks a child (tid 101),
+/// which execs `cat /etc/passwd`, opens it as fd 3, reads the inherited fd 4, closes fd 3
+/// and exits
+///
+/// The events, in order:
+/// 1. `open` exit (shell, fd 4)
+/// 2. `clone` exit in the shell, returning the child tid
+/// 3. `clone` exit in the child, returning 0
+/// 4. `execve` exit (child)
+/// 5. `open` exit (child, fd 3)
+/// 6. `read` enter (child, fd 4)
+/// 7. `read` exit (child, 5 bytes)
+/// 8. `close` enter (child, fd 3)
+/// 9. `close` exit (child)
+/// 10. `procexit` (child)
+pub(crate) fn process_lifecycle() -> Vec<Vec<u8>> {
+    let shell_args = b"-c\0cat /etc/passwd\0";
+    vec![
+        event(
+            1,
+            100,
+            EventType::SYSCALL_OPEN_X,
+            &[i64_param(4), str_param("/var/log/sh.log")],
+        ),
+        event(
+            2,
+            100,
+            EventType::SYSCALL_CLONE_20_X,
+            &process_params(101, "/bin/sh", shell_args, [100, 100, 1], "/root", "sh"),
+        ),
+        event(
+            3,
+            101,
+            EventType::SYSCALL_CLONE_20_X,
+            &process_params(0, "/bin/sh", shell_args, [101, 101, 100], "/root", "sh"),
+        ),
+        event(
+            4,
+            101,
+            EventType::SYSCALL_EXECVE_19_X,
+            &process_params(
+                0,
+                "/bin/cat",
+                b"/etc/passwd\0",
+                [101, 101, 100],
+                "/root",
+                "cat",
+            ),
+        ),
+        event(
+            5,
+            101,
+            EventType::SYSCALL_OPEN_X,
+            &[i64_param(3), str_param("/etc/passwd")],
+        ),
+        event(
+            6,
+            101,
+            EventType::SYSCALL_READ_E,
+            &[i64_param(4), u32_param(100)],
+        ),
+        event(
+            7,
+            101,
+            EventType::SYSCALL_READ_X,
+            &[i64_param(5), b"hello".to_vec()],
+        ),
+        event(8, 101, EventType::SYSCALL_CLOSE_E, &[i64_param(3)]),
+        event(9, 101, EventType::SYSCALL_CLOSE_X, &[i64_param(0)]),
+        event(10, 101, EventType::PROCEXIT_1_E, &[i64_param(0)]),
+    ]
+}
+
+/// The config replaying `events`
+pub(crate) fn config(events: &[Vec<u8>]) -> CString {
+    let events: Vec<String> = events.iter().map(|e| BASE64.encode(e)).collect();
+    CString::new(json!({ "events": events }).to_string()).expect("JSON has no NUL bytes")
+}
+
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+pub(crate) struct ReplayConfig {
+    /// Base64-encoded events
+    events: Vec<String>,
+}
+
+struct ReplaySourcePlugin(VecDeque<Vec<u8>>);
+
+impl Plugin for ReplaySourcePlugin {
+    const NAME: &'static CStr = c"replay";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Replays syscall events from its config.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<ReplayConfig>;
+
+    fn new(_input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        let events = config
+            .events
+            .iter()
+            .map(|e| BASE64.decode(e))
+            .collect::<Result<_, _>>()?;
+        Ok(Self(events))
+    }
+}
+
+impl SourcePlugin for ReplaySourcePlugin {
+    type Instance = ReplaySourcePluginInstance;
+    const EVENT_SOURCE: &'static CStr = c"syscall";
+    const PLUGIN_ID: u32 = 0;
+
+    fn open(&mut self, _params: Option<&str>) -> Result<Self::Instance, Error> {
+        Ok(ReplaySourcePluginInstance(self.0.clone()))
+    }
+
+    fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
+        crate::syscall_source_plugin::event_to_string(event)
+    }
+}
+
+struct ReplaySourcePluginInstance(VecDeque<Vec<u8>>);
+
+impl SourcePluginInstance for ReplaySourcePluginInstance {
+    type Plugin = ReplaySourcePlugin;
+
+    fn next_batch(
+        &mut self,
+        _plugin: &mut Self::Plugin,
+        batch: &mut EventBatch,
+    ) -> Result<(), Error> {
+        match self.0.pop_front() {
+            Some(event) => {
+                batch.add(&*event)?;
+                Ok(())
+            }
+            None => Err(FailureReason::Eof)?,
+        }
+    }
+}
+
+static_plugin!(REPLAY_SOURCE_PLUGIN = ReplaySourcePlugin);
+
+/// A `syscall` source emitting the events from its config (see [`config`])
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = REPLAY_SOURCE_PLUGIN;
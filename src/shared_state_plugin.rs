@@ -0,0 +1,75 @@
+//! A `syscall` parse and extract plugin keeping its state in a static, for the harness's
+//! own tests
+//!
+//! The plugin counts the events it parsed twice: in its instance, and in a static that
+//! all instances share. `shared.parsed` fails as soon as the two disagree, i.e. as soon
+//! as another instance of the plugin parsed an event, which is what happens when two
+//! event sources run it concurrently.
+
+use falco_plugin::anyhow::{self, Error};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::{field, EventInput, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The events parsed by all the instances, reset by every new one
+static PARSED: AtomicU64 = AtomicU64::new(0);
+
+struct SharedStatePlugin {
+    parsed: u64,
+}
+
+impl Plugin for SharedStatePlugin {
+    const NAME: &'static CStr = c"shared-state";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Counts parsed events in a static.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        PARSED.store(0, Ordering::SeqCst);
+        Ok(Self { parsed: 0 })
+    }
+}
+
+impl SharedStatePlugin {
+    fn extract_parsed(&mut self, _req: ExtractRequest<Self>) -> Result<u64, Error> {
+        let parsed = PARSED.load(Ordering::SeqCst);
+        anyhow::ensure!(
+            parsed == self.parsed,
+            "parsed {} events, but the static says {}",
+            self.parsed,
+            parsed
+        );
+        Ok(parsed)
+    }
+}
+
+impl ExtractPlugin for SharedStatePlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field("shared.parsed", &Self::extract_parsed)];
+}
+
+impl ParsePlugin for SharedStatePlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+
+    fn parse_event(&mut self, _event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        self.parsed += 1;
+        PARSED.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+static_plugin!(SHARED_STATE_PLUGIN = SharedStatePlugin);
+
+/// Serves `shared.parsed` (the number of events parsed so far) for all `syscall` events,
+/// and fails if another instance parsed any (see the module docs)
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = SHARED_STATE_PLUGIN;
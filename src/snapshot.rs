@@ -0,0 +1,374 @@
+//! Table snapshots and diffs
+//!
+//! A [`TablesSnapshot`] holds the contents of some or all of the plugin tables at a point
+//! in the capture (see [`NativeCapturingTestDriver`](crate::native::NativeCapturingTestDriver)).
+//! It can be serialized (e.g. to JSON) and compared to a later snapshot with
+//! [`TablesSnapshot::diff`].
+//!
+//! Entries are keyed by their actual [`TableKey`], so they're ordered like in the table
+//! (e.g. `9` before `10`). The plugin API doesn't pass the keys to the table iteration
+//! callback, so the inspector reads each key from the field that holds it (like `number`
+//! in the histogram or `tid` in the threads table), after checking that looking the key up
+//! in the table gives back the same entry. Non-empty tables without such a field can't be
+//! snapshotted: they're listed in [`TablesSnapshot::errors`] instead, and left out of diffs.
+//!
+//! In JSON, entries are stored as a list of `[key, value]` pairs, since the keys
+//! aren't strings.
+
+use crate::tables::{FieldValue, TableEntryData, TableKey};
+use falco_plugin::serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// (De)serialize a map keyed by [`TableKey`] as a list of `[key, value]` pairs
+mod keyed {
+    use crate::tables::TableKey;
+    use falco_plugin::serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &BTreeMap<TableKey, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<TableKey, V>, D::Error> {
+        Ok(Vec::<(TableKey, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// The contents of a single table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct TableSnapshot {
+    /// The field holding the entry keys (`None` if the table is empty)
+    pub key_field: Option<String>,
+    /// All the entries, by key
+    #[serde(with = "keyed")]
+    pub entries: BTreeMap<TableKey, TableEntryData>,
+}
+
+/// The contents of a set of tables, by table name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct TablesSnapshot {
+    pub tables: BTreeMap<String, TableSnapshot>,
+    /// Why the tables missing from `tables` couldn't be snapshotted, by table name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+/// A field that differs between two snapshots of the same entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<FieldValue>,
+    pub new: Option<FieldValue>,
+}
+
+/// The differences between two snapshots of a table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct TableDiff {
+    #[serde(with = "keyed")]
+    pub added: BTreeMap<TableKey, TableEntryData>,
+    #[serde(with = "keyed")]
+    pub removed: BTreeMap<TableKey, TableEntryData>,
+    #[serde(with = "keyed")]
+    pub changed: BTreeMap<TableKey, Vec<FieldChange>>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The differences between two snapshots, by table name
+///
+/// Only tables with differences are included. A table missing from one of the snapshots
+/// is compared as if it was empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+pub struct SnapshotDiff {
+    pub tables: BTreeMap<String, TableDiff>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+fn diff_entries(old: &TableEntryData, new: &TableEntryData) -> Vec<FieldChange> {
+    let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            old: old.get(field).cloned(),
+            new: new.get(field).cloned(),
+        })
+        .collect()
+}
+
+impl TableSnapshot {
+    /// Compare this snapshot to a `newer` one of the same table
+    pub fn diff(&self, newer: &TableSnapshot) -> TableDiff {
+        let mut diff = TableDiff::default();
+
+        for (key, old) in &self.entries {
+            match newer.entries.get(key) {
+                None => {
+                    diff.removed.insert(*key, old.clone());
+                }
+                Some(new) => {
+                    let changes = diff_entries(old, new);
+                    if !changes.is_empty() {
+                        diff.changed.insert(*key, changes);
+                    }
+                }
+            }
+        }
+
+        for (key, new) in &newer.entries {
+            if !self.entries.contains_key(key) {
+                diff.added.insert(*key, new.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+impl TablesSnapshot {
+    /// Compare this snapshot to a `newer` one
+    pub fn diff(&self, newer: &TablesSnapshot) -> SnapshotDiff {
+        let empty = TableSnapshot {
+            key_field: None,
+            entries: BTreeMap::new(),
+        };
+
+        let mut names: Vec<&String> = self.tables.keys().chain(newer.tables.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let tables = names
+            .into_iter()
+            .filter_map(|name| {
+                let old = self.tables.get(name).unwrap_or(&empty);
+                let new = newer.tables.get(name).unwrap_or(&empty);
+                let diff = old.diff(new);
+                (!diff.is_empty()).then(|| (name.clone(), diff))
+            })
+            .collect();
+
+        SnapshotDiff { tables }
+    }
+}
+
+fn fmt_entry(f: &mut Formatter<'_>, entry: &TableEntryData) -> std::fmt::Result {
+    let fields: Vec<String> = entry
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    write!(f, "{{{}}}", fields.join(", "))
+}
+
+fn fmt_value(value: &Option<FieldValue>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("<NA>"),
+    }
+}
+
+/// One line per difference: `+table[key] {...}`, `-table[key] {...}`
+/// or `~table[key].field: old -> new`
+impl Display for SnapshotDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (table, diff) in &self.tables {
+            for (key, entry) in &diff.added {
+                write!(f, "+{}[{}] ", table, key)?;
+                fmt_entry(f, entry)?;
+                writeln!(f)?;
+            }
+            for (key, entry) in &diff.removed {
+                write!(f, "-{}[{}] ", table, key)?;
+                fmt_entry(f, entry)?;
+                writeln!(f)?;
+            }
+            for (key, changes) in &diff.changed {
+                for change in changes {
+                    writeln!(
+                        f,
+                        "~{}[{}].{}: {} -> {}",
+                        table,
+                        key,
+                        change.field,
+                        fmt_value(&change.old),
+                        fmt_value(&change.new)
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TableSnapshot, TablesSnapshot};
+    use crate::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use crate::replay_source_plugin::{self, process_lifecycle};
+    use crate::tables::{FieldValue, TableKey};
+    use crate::{histogram_plugin, CapturingTestDriver, TestDriver};
+    use std::collections::BTreeMap;
+
+    fn snapshot(entries: &[(TableKey, u64)]) -> TablesSnapshot {
+        let entries = entries
+            .iter()
+            .map(|(key, count)| {
+                let fields =
+                    BTreeMap::from([(String::from("count"), FieldValue::Unsigned(*count))]);
+                (*key, fields)
+            })
+            .collect();
+        let table = TableSnapshot {
+            key_field: Some(String::from("number")),
+            entries,
+        };
+
+        TablesSnapshot {
+            tables: BTreeMap::from([(String::from("histogram"), table)]),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn numeric_key_order() {
+        let before = snapshot(&[(TableKey::Unsigned(9), 1), (TableKey::Unsigned(10), 1)]);
+        let after = snapshot(&[(TableKey::Unsigned(10), 2), (TableKey::Unsigned(100), 1)]);
+
+        let keys: Vec<_> = after.tables["histogram"].entries.keys().copied().collect();
+        assert_eq!(keys, vec![TableKey::Unsigned(10), TableKey::Unsigned(100)]);
+
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.to_string(),
+            "+histogram[100] {count=1}\n\
+             -histogram[9] {count=1}\n\
+             ~histogram[10].count: 1 -> 2\n"
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let before = snapshot(&[(TableKey::Signed(-1), 1), (TableKey::Signed(2), 3)]);
+
+        let json = serde_json::to_string(&before).unwrap();
+        let parsed: TablesSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, before);
+
+        let diff = before.diff(&snapshot(&[(TableKey::Signed(2), 4)]));
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(
+            serde_json::from_str::<super::SnapshotDiff>(&json).unwrap(),
+            diff
+        );
+    }
+
+    /// Start capturing the process lifecycle, counted by the histogram plugin
+    fn start() -> NativeCapturingTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let config = replay_source_plugin::config(&process_lifecycle());
+        driver
+            .register_plugin(&replay_source_plugin::PLUGIN, &config)
+            .unwrap();
+        driver
+            .register_plugin(&histogram_plugin::PLUGIN, c"")
+            .unwrap();
+        driver.enable_table_inspection();
+        driver.start_capture(c"", c"").unwrap()
+    }
+
+    #[test]
+    fn capture_diff() {
+        let mut driver = start();
+
+        let event = driver.next_event().unwrap();
+        let mut before = driver
+            .snapshot_selected_tables(&event, &["histogram"])
+            .unwrap();
+
+        for _ in 1..10 {
+            let event = driver.next_event().unwrap();
+            let tid = driver
+                .event_field_as_string(c"thread.tid", &event)
+                .unwrap()
+                .unwrap();
+            let tid = TableKey::Unsigned(tid.parse().unwrap());
+            let after = driver
+                .snapshot_selected_tables(&event, &["histogram"])
+                .unwrap();
+            assert_eq!(
+                after.tables["histogram"].key_field.as_deref(),
+                Some("number")
+            );
+
+            // each event touches exactly one entry: the one for its thread
+            let diff = before.diff(&after);
+            let histogram = &diff.tables["histogram"];
+            assert!(histogram.removed.is_empty());
+            if histogram.added.is_empty() {
+                assert_eq!(histogram.changed.len(), 1, "{}", diff);
+                let changes = &histogram.changed[&tid];
+                assert_eq!(changes.len(), 1, "{}", diff);
+                assert_eq!(changes[0].field, "count");
+            } else {
+                assert!(histogram.changed.is_empty(), "{}", diff);
+                assert_eq!(histogram.added[&tid]["count"], FieldValue::Unsigned(1));
+            }
+
+            before = after;
+        }
+
+        // snapshots survive a round trip through JSON
+        let json = serde_json::to_string(&before).unwrap();
+        let parsed = serde_json::from_str(&json).unwrap();
+        assert!(before.diff(&parsed).is_empty());
+    }
+
+    #[test]
+    fn table_without_key_field() {
+        let mut driver = start();
+        let event = driver.next_event().unwrap();
+
+        // `unkeyed` has no field holding its keys, which doesn't keep the other
+        // tables from being snapshotted
+        let snapshot = driver.snapshot_tables(&event).unwrap();
+        assert!(!snapshot.tables.contains_key("unkeyed"));
+        let error = &snapshot.errors["unkeyed"];
+        assert!(error.contains("no field holds the key"), "{}", error);
+        assert_eq!(
+            snapshot.tables["histogram"].entries[&TableKey::Unsigned(100)]["count"],
+            FieldValue::Unsigned(1)
+        );
+
+        let snapshot = driver
+            .snapshot_selected_tables(&event, &["unkeyed"])
+            .unwrap();
+        assert!(snapshot.tables.is_empty());
+        assert!(snapshot.errors.contains_key("unkeyed"));
+    }
+}
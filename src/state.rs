@@ -0,0 +1,496 @@
+//! A (very) small subset of the sinsp thread state engine
+//!
+//! Tracks threads and file descriptors from syscall events, so that the native driver
+//! can serve `proc.*`, `thread.*` and `fd.*` fields alongside the plugin-provided ones.
+//!
+//! Like in sinsp, fields describe the state right after an event was parsed: an `open`
+//! exit event already knows its fd, and a `close` exit event still does. As the driver
+//! can extract fields from any event, at any time, it keeps an [`EventState`] for each
+//! event rather than looking at the (by then possibly different) current state.
+
+use crate::event::render_socktuple;
+use falco_plugin::event::events::types::{
+    PPME_PROCEXIT_1_E, PPME_SOCKET_ACCEPT4_6_X, PPME_SOCKET_BIND_E, PPME_SOCKET_CONNECT_E,
+    PPME_SOCKET_CONNECT_X, PPME_SOCKET_RECVFROM_E, PPME_SOCKET_SENDTO_E, PPME_SOCKET_SOCKET_X,
+    PPME_SYSCALL_CLONE_20_X, PPME_SYSCALL_CLOSE_E, PPME_SYSCALL_CLOSE_X, PPME_SYSCALL_EXECVE_19_X,
+    PPME_SYSCALL_OPEN_X, PPME_SYSCALL_READ_E, PPME_SYSCALL_WRITE_E,
+};
+use falco_plugin::event::events::RawEvent;
+use falco_plugin::event::fields::types::{PT_FD, PT_PID};
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+
+/// What we know about a single thread
+#[derive(Debug, Default, Clone)]
+pub struct ThreadInfo {
+    pub tid: i64,
+    pub pid: i64,
+    pub ptid: i64,
+    pub comm: String,
+    pub exe: String,
+    pub exepath: String,
+    pub cwd: String,
+    /// The arguments, without the program name (like sinsp's `proc.args`)
+    pub args: Vec<String>,
+}
+
+/// What we know about a single file descriptor
+#[derive(Debug, Default, Clone)]
+pub struct FdInfo {
+    pub name: String,
+}
+
+/// The syscall a thread is in: the type of its enter event, and the fd passed to it
+#[derive(Debug)]
+struct PendingSyscall {
+    enter_type: u16,
+    fd: Option<i64>,
+}
+
+/// Threads and (per-process) file descriptors seen so far
+#[derive(Debug, Default)]
+pub struct ThreadState {
+    threads: BTreeMap<i64, ThreadInfo>,
+    fds: BTreeMap<(i64, i64), FdInfo>,
+    pending: BTreeMap<i64, PendingSyscall>,
+}
+
+/// The thread and fd of a single event, as they were right after it was processed
+#[derive(Debug, Default, Clone)]
+pub struct EventState {
+    pub tid: i64,
+    pub thread: Option<ThreadInfo>,
+    pub ppid: Option<i64>,
+    pub fd: Option<i64>,
+    pub fd_info: Option<FdInfo>,
+}
+
+fn cstr_to_string(s: &CStr) -> String {
+    s.to_string_lossy().into_owned()
+}
+
+/// Split a NUL-separated argument list (as found in clone and execve events)
+fn split_args(args: &[u8]) -> Vec<String> {
+    args.split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+/// Get the file descriptor an event operates on, if it carries one
+fn event_fd(event: &RawEvent) -> Option<i64> {
+    let fd: Option<PT_FD> = if let Ok(ev) = event.load::<PPME_SYSCALL_OPEN_X>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SYSCALL_READ_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SYSCALL_WRITE_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SYSCALL_CLOSE_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_SOCKET_X>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_BIND_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_CONNECT_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_CONNECT_X>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_ACCEPT4_6_X>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_SENDTO_E>() {
+        ev.params.fd
+    } else if let Ok(ev) = event.load::<PPME_SOCKET_RECVFROM_E>() {
+        ev.params.fd
+    } else {
+        None
+    };
+
+    fd.map(|fd| fd.0).filter(|fd| *fd >= 0)
+}
+
+/// The process details carried by clone and execve exit events
+struct ProcessParams<'a> {
+    pid: Option<PT_PID>,
+    ptid: Option<PT_PID>,
+    comm: Option<&'a CStr>,
+    exe: Option<&'a CStr>,
+    args: Option<&'a [u8]>,
+    cwd: Option<&'a CStr>,
+}
+
+impl ProcessParams<'_> {
+    fn apply(&self, thread: &mut ThreadInfo) {
+        if let Some(pid) = self.pid {
+            thread.pid = pid.0;
+        }
+        if let Some(ptid) = self.ptid {
+            thread.ptid = ptid.0;
+        }
+        if let Some(comm) = self.comm {
+            thread.comm = cstr_to_string(comm);
+        }
+        if let Some(exe) = self.exe {
+            thread.exe = cstr_to_string(exe);
+            thread.exepath = thread.exe.clone();
+        }
+        if let Some(args) = self.args {
+            // like in sinsp, the args param doesn't include the program name
+            thread.args = split_args(args);
+        }
+        if let Some(cwd) = self.cwd {
+            thread.cwd = cstr_to_string(cwd);
+        }
+    }
+}
+
+impl ThreadState {
+    /// Look up a thread by its thread id
+    pub fn thread(&self, tid: i64) -> Option<&ThreadInfo> {
+        self.threads.get(&tid)
+    }
+
+    /// Look up a file descriptor in the process owning thread `tid`
+    pub fn fd(&self, tid: i64, fd: i64) -> Option<&FdInfo> {
+        let pid = self.thread(tid)?.pid;
+        self.fds.get(&(pid, fd))
+    }
+
+    /// Iterate over all known threads, ordered by thread id
+    pub fn threads(&self) -> impl Iterator<Item = &ThreadInfo> {
+        self.threads.values()
+    }
+
+    fn thread_mut(&mut self, tid: i64) -> &mut ThreadInfo {
+        self.threads.entry(tid).or_insert_with(|| ThreadInfo {
+            tid,
+            pid: tid,
+            ..Default::default()
+        })
+    }
+
+    fn set_fd_name(&mut self, tid: i64, fd: i64, name: String) {
+        let pid = self.thread_mut(tid).pid;
+        self.fds.insert((pid, fd), FdInfo { name });
+    }
+
+    /// Give a new process a copy of its parent's fds (threads share them anyway)
+    fn inherit_fds(&mut self, parent_pid: i64, child_pid: i64) {
+        let has_fds = self.fds.keys().any(|(pid, _)| *pid == child_pid);
+        if parent_pid == child_pid || has_fds {
+            return;
+        }
+
+        let inherited: Vec<_> = self
+            .fds
+            .iter()
+            .filter(|((pid, _), _)| *pid == parent_pid)
+            .map(|((_, fd), info)| ((child_pid, *fd), info.clone()))
+            .collect();
+        self.fds.extend(inherited);
+    }
+
+    /// The fd an event operates on: its own fd param or, for an exit event
+    /// without one, the fd passed to the matching enter event
+    fn syscall_fd(&mut self, tid: i64, event: &RawEvent) -> Option<i64> {
+        let fd = event_fd(event);
+
+        // enter events have even type numbers and the matching exit events follow them
+        if event.event_type % 2 == 0 {
+            self.pending.insert(
+                tid,
+                PendingSyscall {
+                    enter_type: event.event_type,
+                    fd,
+                },
+            );
+            return fd;
+        }
+
+        let pending = self.pending.remove(&tid);
+        fd.or_else(|| {
+            pending
+                .filter(|p| p.enter_type + 1 == event.event_type)
+                .and_then(|p| p.fd)
+        })
+    }
+
+    fn process_clone(&mut self, tid: i64, ev: PPME_SYSCALL_CLONE_20_X<'_>) {
+        let params = ProcessParams {
+            pid: ev.pid,
+            ptid: ev.ptid,
+            comm: ev.comm,
+            exe: ev.exe,
+            args: ev.args,
+            cwd: ev.cwd,
+        };
+
+        match ev.res.map(|res| res.0) {
+            // the parent describes itself and learns the child tid from the return value;
+            // the child inherits everything from it, until the child's own event shows up
+            Some(child_tid) if child_tid > 0 => {
+                let parent = self.thread_mut(tid);
+                params.apply(parent);
+
+                let mut child = parent.clone();
+                let parent_pid = parent.pid;
+                child.tid = child_tid;
+                child.pid = child_tid;
+                child.ptid = tid;
+                self.threads.entry(child_tid).or_insert(child);
+                self.inherit_fds(parent_pid, child_tid);
+            }
+            Some(0) => {
+                let child_tid = ev.tid.map(|tid| tid.0).unwrap_or(tid);
+                let child = self.thread_mut(child_tid);
+                params.apply(child);
+
+                let (child_pid, ptid) = (child.pid, child.ptid);
+                if let Some(parent_pid) = self.thread(ptid).map(|p| p.pid) {
+                    self.inherit_fds(parent_pid, child_pid);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply everything an event adds to the state
+    fn process_additions(&mut self, tid: i64, fd: Option<i64>, event: &RawEvent) {
+        if let Ok(ev) = event.load::<PPME_SYSCALL_CLONE_20_X>() {
+            self.process_clone(tid, ev.params);
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_EXECVE_19_X>() {
+            let ev = ev.params;
+            if ev.res.map(|res| res.0) == Some(0) {
+                let params = ProcessParams {
+                    pid: ev.pid,
+                    ptid: ev.ptid,
+                    comm: ev.comm,
+                    exe: ev.exe,
+                    args: ev.args,
+                    cwd: ev.cwd,
+                };
+                params.apply(self.thread_mut(tid));
+            }
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_OPEN_X>() {
+            if let (Some(fd), Some(name)) = (fd, ev.params.name) {
+                let name = String::from_utf8_lossy(name.as_bytes()).into_owned();
+                self.set_fd_name(tid, fd, name);
+            }
+        } else if event.load::<PPME_SOCKET_SOCKET_X>().is_ok() {
+            if let Some(fd) = fd {
+                self.set_fd_name(tid, fd, String::new());
+            }
+        } else if let Ok(ev) = event.load::<PPME_SOCKET_CONNECT_X>() {
+            if let (Some(fd), Some(tuple)) = (fd, ev.params.tuple) {
+                self.set_fd_name(tid, fd, render_socktuple(&tuple));
+            }
+        } else if let Ok(ev) = event.load::<PPME_SOCKET_ACCEPT4_6_X>() {
+            if let (Some(fd), Some(tuple)) = (fd, ev.params.tuple) {
+                self.set_fd_name(tid, fd, render_socktuple(&tuple));
+            }
+        }
+    }
+
+    /// Apply everything an event removes from the state
+    ///
+    /// This happens after taking the event's [`EventState`], so that e.g. `fd.*` fields
+    /// are still available for the `close` exit event.
+    fn process_removals(&mut self, tid: i64, fd: Option<i64>, event: &RawEvent) {
+        if let Ok(ev) = event.load::<PPME_SYSCALL_CLOSE_X>() {
+            let succeeded = ev.params.res.is_some_and(|res| res.0 >= 0);
+            if let (Some(fd), true) = (fd, succeeded) {
+                if let Some(pid) = self.thread(tid).map(|t| t.pid) {
+                    self.fds.remove(&(pid, fd));
+                }
+            }
+        } else if event.load::<PPME_PROCEXIT_1_E>().is_ok() {
+            self.pending.remove(&tid);
+            if let Some(thread) = self.threads.remove(&tid) {
+                // the fds belong to the process, which is gone with its main thread
+                if thread.pid == tid {
+                    self.fds.retain(|(pid, _), _| *pid != tid);
+                }
+            }
+        }
+    }
+
+    fn event_state(&self, tid: i64, fd: Option<i64>) -> EventState {
+        let thread = self.thread(tid).cloned();
+        let ppid = thread
+            .as_ref()
+            .filter(|t| t.ptid > 0)
+            .map(|t| self.thread(t.ptid).map(|p| p.pid).unwrap_or(t.ptid));
+
+        EventState {
+            tid,
+            thread,
+            ppid,
+            fd,
+            fd_info: fd.and_then(|fd| self.fd(tid, fd)).cloned(),
+        }
+    }
+
+    /// Update the state according to a single event, returning the event's view of it
+    pub fn process_event(&mut self, event: &RawEvent) -> EventState {
+        let tid = event.metadata.tid as i64;
+        let fd = self.syscall_fd(tid, event);
+
+        self.process_additions(tid, fd, event);
+        let state = self.event_state(tid, fd);
+        self.process_removals(tid, fd, event);
+
+        state
+    }
+}
+
+impl EventState {
+    /// The state of an event the engine hasn't processed: only the thread id is known
+    pub fn unknown(tid: i64) -> Self {
+        Self {
+            tid,
+            ..Default::default()
+        }
+    }
+
+    /// Extract a `proc.*`, `thread.*` or `fd.*` field
+    ///
+    /// Returns `None` for fields the state engine doesn't know about, `Some(None)` for known
+    /// fields without a value for this event (e.g. `fd.name` for an event without an fd).
+    pub fn extract_field(&self, field: &str) -> Option<Option<String>> {
+        let thread = self.thread.as_ref();
+        // like sinsp, treat details we haven't seen yet as missing rather than empty
+        let text = |value: &String| Some(value.clone()).filter(|v| !v.is_empty());
+
+        let value = match field {
+            "thread.tid" => Some(self.tid.to_string()),
+            "proc.pid" => thread.map(|t| t.pid.to_string()),
+            "proc.ppid" => self.ppid.map(|ppid| ppid.to_string()),
+            "proc.name" => thread.and_then(|t| text(&t.comm)),
+            "proc.exe" => thread.and_then(|t| text(&t.exe)),
+            "proc.exepath" => thread.and_then(|t| text(&t.exepath)),
+            "proc.cwd" => thread.and_then(|t| text(&t.cwd)),
+            "proc.args" => thread.and_then(|t| text(&t.args.join(" "))),
+            "proc.cmdline" => thread.and_then(|t| {
+                let mut cmdline = text(&t.comm)?;
+                for arg in &t.args {
+                    cmdline.push(' ');
+                    cmdline.push_str(arg);
+                }
+                Some(cmdline)
+            }),
+            "fd.num" => self.fd.map(|fd| fd.to_string()),
+            "fd.name" => self.fd_info.as_ref().map(|fd| fd.name.clone()),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::native::NativeTestDriver;
+    use crate::replay_source_plugin::{self, process_lifecycle};
+    use crate::{network_source_plugin, CapturingTestDriver, TestDriver};
+    use std::ffi::CString;
+
+    const FIELDS: [&str; 10] = [
+        "thread.tid",
+        "proc.pid",
+        "proc.ppid",
+        "proc.name",
+        "proc.exe",
+        "proc.cwd",
+        "proc.args",
+        "proc.cmdline",
+        "fd.num",
+        "fd.name",
+    ];
+
+    #[test]
+    fn process_lifecycle_fields() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let config = replay_source_plugin::config(&process_lifecycle());
+        driver
+            .register_plugin(&replay_source_plugin::PLUGIN, &config)
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        // read the whole capture first: the fields must describe each event as it was
+        // processed, not the final state
+        let mut events = Vec::new();
+        while let Ok(event) = driver.next_event() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 10);
+
+        let mut lines = Vec::new();
+        for event in &events {
+            let values: Vec<String> = FIELDS
+                .iter()
+                .map(|field| {
+                    let field = CString::new(*field).unwrap();
+                    driver
+                        .event_field_as_string(&field, event)
+                        .unwrap()
+                        .unwrap_or_else(|| String::from("<NA>"))
+                })
+                .collect();
+            lines.push(values.join("|"));
+        }
+
+        let sh = "sh|/bin/sh|/root|-c cat /etc/passwd|sh -c cat /etc/passwd";
+        let cat = "cat|/bin/cat|/root|/etc/passwd|cat /etc/passwd";
+        assert_eq!(
+            lines,
+            vec![
+                // open in a shell we know nothing about yet
+                String::from("100|100|<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|4|/var/log/sh.log"),
+                // the parent's clone tells us about the shell
+                format!("100|100|1|{}|<NA>|<NA>", sh),
+                format!("101|101|100|{}|<NA>|<NA>", sh),
+                format!("101|101|100|{}|<NA>|<NA>", cat),
+                format!("101|101|100|{}|3|/etc/passwd", cat),
+                // fd 4 was inherited from the shell
+                format!("101|101|100|{}|4|/var/log/sh.log", cat),
+                // the exit event uses the fd from the enter event
+                format!("101|101|100|{}|4|/var/log/sh.log", cat),
+                format!("101|101|100|{}|3|/etc/passwd", cat),
+                // the fd is still there for the close exit event itself
+                format!("101|101|100|{}|3|/etc/passwd", cat),
+                format!("101|101|100|{}|<NA>|<NA>", cat),
+            ]
+        );
+
+        let state = driver.thread_state();
+        assert!(state.thread(101).is_none());
+        assert_eq!(state.fd(100, 4).unwrap().name, "/var/log/sh.log");
+        assert!(state.fd(100, 3).is_none());
+    }
+
+    #[test]
+    fn socket_fd_names() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver
+            .register_plugin(&network_source_plugin::PLUGIN, c"{}")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut names = Vec::new();
+        while let Ok(event) = driver.next_event() {
+            let evt_type = driver.event_field_as_string(c"evt.type", &event).unwrap();
+            let dir = driver.event_field_as_string(c"evt.dir", &event).unwrap();
+            if evt_type.as_deref() == Some("connect") && dir.as_deref() == Some("<") {
+                names.extend(driver.event_field_as_string(c"fd.name", &event).unwrap());
+            }
+        }
+
+        assert_eq!(
+            names,
+            vec![
+                "10.0.0.1:40000->10.0.0.2:8080",
+                "fd00::1:40000->fd00::2:8080",
+                "c11e47->5e47e4 /run/rustlings.sock",
+            ]
+        );
+    }
+}
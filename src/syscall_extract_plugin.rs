@@ -0,0 +1,67 @@
+//! A `syscall` extract plugin serving the fd of a few event types, for the harness's own tests
+//!
+//! Same as the `extract_fields_syscall_events` solution, so that the harness doesn't
+//! depend on the exercises.
+
+use falco_plugin::anyhow::{self, Error};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType::{
+    SYSCALL_CLOSE_E, SYSCALL_OPEN_X, SYSCALL_READ_E,
+};
+use falco_plugin::event::events::types::{
+    EventType, PPME_SYSCALL_CLOSE_E, PPME_SYSCALL_OPEN_X, PPME_SYSCALL_READ_E,
+};
+use falco_plugin::event::fields::types::PT_FD;
+use falco_plugin::extract::{field, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::TablesInput;
+use std::ffi::CStr;
+
+struct SyscallExtractPlugin;
+
+impl Plugin for SyscallExtractPlugin {
+    const NAME: &'static CStr = c"syscall-extract";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Extracts the fd of syscall events.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(_input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+fn unwrap_fd(fd: Option<PT_FD>) -> Result<u64, Error> {
+    match fd {
+        Some(fd) => Ok(fd.0 as u64),
+        None => anyhow::bail!("fd not present"),
+    }
+}
+
+impl SyscallExtractPlugin {
+    fn extract_fd(&mut self, req: ExtractRequest<Self>) -> Result<u64, Error> {
+        let event = req.event.event()?;
+        if let Ok(ev) = event.load::<PPME_SYSCALL_OPEN_X>() {
+            unwrap_fd(ev.params.fd)
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_READ_E>() {
+            unwrap_fd(ev.params.fd)
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_CLOSE_E>() {
+            unwrap_fd(ev.params.fd)
+        } else {
+            anyhow::bail!("could not find fd field")
+        }
+    }
+}
+
+impl ExtractPlugin for SyscallExtractPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[SYSCALL_OPEN_X, SYSCALL_READ_E, SYSCALL_CLOSE_E];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field("rustlings.fd", &Self::extract_fd)];
+}
+
+static_plugin!(SYSCALL_EXTRACT_PLUGIN = SyscallExtractPlugin);
+
+/// Serves `rustlings.fd` for `open` exit, `read` enter and `close` enter events
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = SYSCALL_EXTRACT_PLUGIN;
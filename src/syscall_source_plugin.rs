@@ -59,6 +59,18 @@ fn build_syscall_events() -> VecDeque<Vec<u8>> {
     evts
 }
 
+/// Render a syscall event using the SDK's own representation
+///
+/// Shared with the other syscall fixture plugins.
+pub(crate) fn event_to_string(event: &EventInput) -> Result<CString, Error> {
+    let event = event.event()?;
+    let event = event.load_any()?;
+    let mut writer = CStringWriter::default();
+    write!(&mut writer, "{:?}", event)?;
+
+    Ok(writer.into_cstring())
+}
+
 // Metadata for our plugin, you know the drill :)
 impl Plugin for SyscallSourcePlugin {
     const NAME: &'static CStr = c"syscall";
@@ -82,12 +94,7 @@ impl SourcePlugin for SyscallSourcePlugin {
     }
 
     fn event_to_string(&mut self, event: &EventInput) -> Result<CString, Error> {
-        let event = event.event()?;
-        let event = event.load_any()?;
-        let mut writer = CStringWriter::default();
-        write!(&mut writer, "{:?}", event)?;
-
-        Ok(writer.into_cstring())
+        event_to_string(event)
     }
 }
 
@@ -0,0 +1,67 @@
+//! A plugin exporting a table with fields of every visibility, for the harness's own tests
+//!
+//! The `access` table has a public, a read-only and a private field, plus a nested
+//! table. The plugin doesn't touch the table after creating it.
+
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::EventInput;
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::export::{Entry, Private, Public, Readonly};
+use falco_plugin::tables::{export, TablesInput};
+use std::ffi::CStr;
+
+#[derive(Entry)]
+struct NestedEntry {
+    value: Public<u64>,
+}
+
+type NestedTable = export::Table<u64, NestedEntry>;
+
+#[derive(Entry)]
+struct AccessEntry {
+    public: Public<u64>,
+    readonly: Readonly<u64>,
+    private: Private<u64>,
+    nested: Readonly<Box<NestedTable>>,
+}
+
+type AccessTable = export::Table<u64, AccessEntry>;
+
+struct TableAccessPlugin {
+    /// Only kept alive, the table stays empty
+    _table: Box<AccessTable>,
+}
+
+impl Plugin for TableAccessPlugin {
+    const NAME: &'static CStr = c"table-access";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Exports a table with fields of every visibility.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        let Some(input) = input else {
+            falco_plugin::anyhow::bail!("Table input not provided");
+        };
+
+        let table = input.add_table(AccessTable::new(c"access")?)?;
+        Ok(Self { _table: table })
+    }
+}
+
+impl ParsePlugin for TableAccessPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+
+    fn parse_event(&mut self, _event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(TABLE_ACCESS_PLUGIN = TableAccessPlugin);
+
+/// Exports the `access` table (see the module docs)
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = TABLE_ACCESS_PLUGIN;
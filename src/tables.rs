@@ -0,0 +1,963 @@
+//! Table inspection for tests
+//!
+//! Tables live inside plugins and are only reachable through the plugin API, so when asked to
+//! ([`NativeTestDriver::enable_table_inspection`](crate::native::NativeTestDriver::enable_table_inspection)),
+//! the native driver registers one extra plugin (the inspector) right before starting
+//! the capture. Being registered last, it can import every table the other plugins export.
+//!
+//! Just like in a real plugin, reading table entries requires an event being processed,
+//! which is why the inspection methods on
+//! [`NativeCapturingTestDriver`](crate::native::NativeCapturingTestDriver) take an event.
+//! Behind the scenes, they extract [`TRIGGER_FIELD`] from that event, which makes
+//! the inspector run the pending request with a valid table reader.
+//!
+//! Writes need a table writer, which the framework only hands out when the capture opens
+//! and while parsing events. Entries seeded before the capture starts are written when
+//! it opens, so they're in place before any plugin sees the first event. Mutations made
+//! during the capture are queued by the driver and applied by the inspector when it parses
+//! the next event. As it's registered last, this happens *after* all the other plugins
+//! have parsed that event: the changes are visible when extracting fields from it
+//! and to every plugin processing the events that follow.
+
+use crate::access::{FieldAccess, FieldAccessReport, TableAccessReport};
+use crate::snapshot::{TableSnapshot, TablesSnapshot};
+use falco_plugin::anyhow::{self, Context, Error};
+use falco_plugin::api::{
+    ss_plugin_state_type, ss_plugin_state_type_SS_PLUGIN_ST_BOOL,
+    ss_plugin_state_type_SS_PLUGIN_ST_INT16, ss_plugin_state_type_SS_PLUGIN_ST_INT32,
+    ss_plugin_state_type_SS_PLUGIN_ST_INT64, ss_plugin_state_type_SS_PLUGIN_ST_INT8,
+    ss_plugin_state_type_SS_PLUGIN_ST_STRING, ss_plugin_state_type_SS_PLUGIN_ST_TABLE,
+    ss_plugin_state_type_SS_PLUGIN_ST_UINT16, ss_plugin_state_type_SS_PLUGIN_ST_UINT32,
+    ss_plugin_state_type_SS_PLUGIN_ST_UINT64, ss_plugin_state_type_SS_PLUGIN_ST_UINT8,
+};
+use falco_plugin::base::Plugin;
+use falco_plugin::event::events::types::EventType;
+use falco_plugin::extract::{field, EventInput, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+use falco_plugin::listen::{CaptureListenInput, CaptureListenPlugin};
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::serde::{Deserialize, Serialize};
+use falco_plugin::static_plugin;
+use falco_plugin::tables::import::{Field, RuntimeEntry, Table};
+use falco_plugin::tables::{TableReader, TableWriter, TablesInput};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::fmt::{Display, Formatter};
+use std::ops::ControlFlow;
+
+/// The field the driver extracts to make the inspector process a request
+pub(crate) const TRIGGER_FIELD: &str = "harness.tables";
+
+/// The type of a table key or field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    Bool,
+    String,
+    Table,
+    Other(ss_plugin_state_type),
+}
+
+impl StateType {
+    fn from_raw(raw: ss_plugin_state_type) -> Self {
+        match raw {
+            ss_plugin_state_type_SS_PLUGIN_ST_INT8 => StateType::I8,
+            ss_plugin_state_type_SS_PLUGIN_ST_INT16 => StateType::I16,
+            ss_plugin_state_type_SS_PLUGIN_ST_INT32 => StateType::I32,
+            ss_plugin_state_type_SS_PLUGIN_ST_INT64 => StateType::I64,
+            ss_plugin_state_type_SS_PLUGIN_ST_UINT8 => StateType::U8,
+            ss_plugin_state_type_SS_PLUGIN_ST_UINT16 => StateType::U16,
+            ss_plugin_state_type_SS_PLUGIN_ST_UINT32 => StateType::U32,
+            ss_plugin_state_type_SS_PLUGIN_ST_UINT64 => StateType::U64,
+            ss_plugin_state_type_SS_PLUGIN_ST_BOOL => StateType::Bool,
+            ss_plugin_state_type_SS_PLUGIN_ST_STRING => StateType::String,
+            ss_plugin_state_type_SS_PLUGIN_ST_TABLE => StateType::Table,
+            other => StateType::Other(other),
+        }
+    }
+}
+
+/// The schema of a single table field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: String,
+    pub field_type: StateType,
+    pub read_only: bool,
+}
+
+/// A table registered with the plugin framework, with its key type and field schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    pub name: String,
+    pub key_type: StateType,
+    pub fields: Vec<FieldInfo>,
+    /// Why the table can't be inspected (e.g. its key type isn't supported), if it can't
+    pub error: Option<String>,
+}
+
+/// A table key
+///
+/// Only integer keys are supported; the value is converted to the actual key type
+/// of the table (failing if it doesn't fit). Tables with other keys are listed by
+/// [`tables`](crate::native::NativeCapturingTestDriver::tables) with an error, and
+/// can't be inspected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TableKey {
+    Unsigned(u64),
+    Signed(i64),
+}
+
+macro_rules! impl_table_key_from {
+    ($variant:ident as $repr:ty: $($ty:ty),*) => {
+        $(
+            impl From<$ty> for TableKey {
+                fn from(key: $ty) -> Self {
+                    TableKey::$variant(key as $repr)
+                }
+            }
+        )*
+    };
+}
+
+impl_table_key_from!(Unsigned as u64: u8, u16, u32, u64);
+impl_table_key_from!(Signed as i64: i8, i16, i32, i64);
+
+impl TableKey {
+    fn convert<K: TryFrom<u64> + TryFrom<i64>>(self) -> anyhow::Result<K> {
+        let key = match self {
+            TableKey::Unsigned(key) => K::try_from(key).ok(),
+            TableKey::Signed(key) => K::try_from(key).ok(),
+        };
+        key.ok_or_else(|| anyhow::anyhow!("key {} out of range for the table key type", self))
+    }
+}
+
+impl Display for TableKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableKey::Unsigned(key) => write!(f, "{}", key),
+            TableKey::Signed(key) => write!(f, "{}", key),
+        }
+    }
+}
+
+/// The value of a table field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FieldValue {
+    Unsigned(u64),
+    Signed(i64),
+    Bool(bool),
+    String(String),
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Unsigned(v) => write!(f, "{}", v),
+            FieldValue::Signed(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::String(v) => f.write_str(v),
+        }
+    }
+}
+
+impl FieldValue {
+    fn convert<T: TryFrom<u64> + TryFrom<i64>>(&self) -> anyhow::Result<T> {
+        let value = match self {
+            FieldValue::Unsigned(v) => T::try_from(*v).ok(),
+            FieldValue::Signed(v) => T::try_from(*v).ok(),
+            _ => anyhow::bail!("{:?} is not an integer", self),
+        };
+        value.ok_or_else(|| anyhow::anyhow!("value {} out of range for the field type", self))
+    }
+
+    /// The value as a table key, if it's an integer
+    fn as_key(&self) -> Option<TableKey> {
+        match self {
+            FieldValue::Unsigned(v) => Some(TableKey::Unsigned(*v)),
+            FieldValue::Signed(v) => Some(TableKey::Signed(*v)),
+            _ => None,
+        }
+    }
+}
+
+/// The readable fields of a single table entry, by name
+pub type TableEntryData = BTreeMap<String, FieldValue>;
+
+/// A request for the inspector plugin, sent by the driver
+pub(crate) enum Request {
+    Lookup {
+        table: String,
+        key: TableKey,
+    },
+    Entries {
+        table: String,
+    },
+    /// Snapshot the named tables, or all of them
+    Snapshot {
+        tables: Option<Vec<String>>,
+    },
+}
+
+/// A change to a table, queued by the driver and applied by the inspector
+#[derive(Debug)]
+pub(crate) enum Mutation {
+    /// Create an entry with the given field values, replacing any existing one
+    Insert {
+        table: String,
+        key: TableKey,
+        fields: TableEntryData,
+    },
+    /// Set fields of an existing entry
+    Update {
+        table: String,
+        key: TableKey,
+        fields: TableEntryData,
+    },
+    /// Remove an entry
+    Remove { table: String, key: TableKey },
+}
+
+/// The inspector's answer to a [`Request`]
+pub(crate) enum Response {
+    Entry(Option<TableEntryData>),
+    Entries(Vec<TableEntryData>),
+    Snapshot(TablesSnapshot),
+}
+
+// The runner calls into plugins synchronously, on the thread driving the capture,
+// so thread-local storage is enough to pass data between the driver and the inspector
+// (and keeps tests running in parallel isolated from each other)
+thread_local! {
+    static REQUEST: RefCell<Option<Request>> = const { RefCell::new(None) };
+    static RESPONSE: RefCell<Option<anyhow::Result<Response>>> = const { RefCell::new(None) };
+    static TABLE_INFOS: RefCell<Option<Vec<TableInfo>>> = const { RefCell::new(None) };
+    static MUTATIONS: RefCell<Vec<Mutation>> = const { RefCell::new(Vec::new()) };
+    static MUTATION_ERRORS: RefCell<Vec<Error>> = const { RefCell::new(Vec::new()) };
+    static ACCESS_REPORTS: RefCell<Option<Vec<TableAccessReport>>> = const { RefCell::new(None) };
+}
+
+/// Queue a request for the inspector, to be picked up when extracting [`TRIGGER_FIELD`]
+pub(crate) fn submit_request(request: Request) {
+    REQUEST.with_borrow_mut(|r| *r = Some(request));
+}
+
+/// Get the inspector's response to the last request, if it has processed it
+pub(crate) fn take_response() -> Option<anyhow::Result<Response>> {
+    RESPONSE.with_borrow_mut(Option::take)
+}
+
+/// Queue mutations for the inspector, to be applied when the capture opens
+/// or, once it's open, when the inspector parses the next event
+pub(crate) fn submit_mutations(mutations: Vec<Mutation>) {
+    MUTATIONS.with_borrow_mut(|m| m.extend(mutations));
+}
+
+/// Take back the mutations the inspector hasn't applied (e.g. because there was no event)
+pub(crate) fn take_mutations() -> Vec<Mutation> {
+    MUTATIONS.with_borrow_mut(std::mem::take)
+}
+
+/// Get the errors from applying mutations since the last call
+pub(crate) fn take_mutation_errors() -> Vec<Error> {
+    MUTATION_ERRORS.with_borrow_mut(std::mem::take)
+}
+
+/// Get the table access reports, once the inspector has probed the tables
+pub(crate) fn take_access_reports() -> Option<Vec<TableAccessReport>> {
+    ACCESS_REPORTS.with_borrow_mut(Option::take)
+}
+
+/// Get the tables found by the most recently initialized inspector
+pub(crate) fn take_table_infos() -> Vec<TableInfo> {
+    TABLE_INFOS
+        .with_borrow_mut(Option::take)
+        .unwrap_or_default()
+}
+
+struct InspectorTag;
+type DynEntry = RuntimeEntry<InspectorTag>;
+type DynTable<K> = Table<K, DynEntry>;
+
+/// A handle to a field of any supported type
+enum AnyField {
+    I8(Field<i8, DynEntry>),
+    I16(Field<i16, DynEntry>),
+    I32(Field<i32, DynEntry>),
+    I64(Field<i64, DynEntry>),
+    U8(Field<u8, DynEntry>),
+    U16(Field<u16, DynEntry>),
+    U32(Field<u32, DynEntry>),
+    U64(Field<u64, DynEntry>),
+    Bool(Field<bool, DynEntry>),
+    String(Field<CStr, DynEntry>),
+}
+
+impl AnyField {
+    /// Get a handle to a field, or `None` if we don't support its type (e.g. nested tables)
+    fn get<K>(
+        input: &TablesInput,
+        table: &DynTable<K>,
+        info: &FieldInfo,
+    ) -> Result<Option<Self>, Error> {
+        let name = CString::new(info.name.as_str())?;
+        let name = name.as_c_str();
+
+        Ok(Some(match info.field_type {
+            StateType::I8 => AnyField::I8(table.get_field(input, name)?),
+            StateType::I16 => AnyField::I16(table.get_field(input, name)?),
+            StateType::I32 => AnyField::I32(table.get_field(input, name)?),
+            StateType::I64 => AnyField::I64(table.get_field(input, name)?),
+            StateType::U8 => AnyField::U8(table.get_field(input, name)?),
+            StateType::U16 => AnyField::U16(table.get_field(input, name)?),
+            StateType::U32 => AnyField::U32(table.get_field(input, name)?),
+            StateType::U64 => AnyField::U64(table.get_field(input, name)?),
+            StateType::Bool => AnyField::Bool(table.get_field(input, name)?),
+            StateType::String => AnyField::String(table.get_field(input, name)?),
+            StateType::Table | StateType::Other(_) => return Ok(None),
+        }))
+    }
+
+    fn read(&self, r: &impl TableReader, entry: &DynEntry) -> Result<FieldValue, Error> {
+        Ok(match self {
+            AnyField::I8(f) => FieldValue::Signed(entry.read_field(r, f)?.into()),
+            AnyField::I16(f) => FieldValue::Signed(entry.read_field(r, f)?.into()),
+            AnyField::I32(f) => FieldValue::Signed(entry.read_field(r, f)?.into()),
+            AnyField::I64(f) => FieldValue::Signed(entry.read_field(r, f)?),
+            AnyField::U8(f) => FieldValue::Unsigned(entry.read_field(r, f)?.into()),
+            AnyField::U16(f) => FieldValue::Unsigned(entry.read_field(r, f)?.into()),
+            AnyField::U32(f) => FieldValue::Unsigned(entry.read_field(r, f)?.into()),
+            AnyField::U64(f) => FieldValue::Unsigned(entry.read_field(r, f)?),
+            AnyField::Bool(f) => FieldValue::Bool(entry.read_field(r, f)?),
+            AnyField::String(f) => {
+                FieldValue::String(entry.read_field(r, f)?.to_string_lossy().into_owned())
+            }
+        })
+    }
+
+    fn write(
+        &self,
+        w: &impl TableWriter,
+        entry: &DynEntry,
+        value: &FieldValue,
+    ) -> Result<(), Error> {
+        match (self, value) {
+            (AnyField::I8(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::I16(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::I32(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::I64(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::U8(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::U16(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::U32(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::U64(f), _) => entry.write_field(w, f, &value.convert()?),
+            (AnyField::Bool(f), FieldValue::Bool(v)) => entry.write_field(w, f, v),
+            (AnyField::String(f), FieldValue::String(v)) => {
+                entry.write_field(w, f, CString::new(v.as_str())?.as_c_str())
+            }
+            (AnyField::Bool(_), _) => anyhow::bail!("{:?} is not a boolean", value),
+            (AnyField::String(_), _) => anyhow::bail!("{:?} is not a string", value),
+        }
+    }
+}
+
+/// A handle to a table with any supported key type
+enum AnyTable {
+    I8(DynTable<i8>),
+    I16(DynTable<i16>),
+    I32(DynTable<i32>),
+    I64(DynTable<i64>),
+    U8(DynTable<u8>),
+    U16(DynTable<u16>),
+    U32(DynTable<u32>),
+    U64(DynTable<u64>),
+}
+
+/// Run `$body` with `$t` bound to the table handle, whatever its key type
+macro_rules! with_table {
+    ($table:expr, |$t:ident| $body:expr) => {
+        match $table {
+            AnyTable::I8($t) => $body,
+            AnyTable::I16($t) => $body,
+            AnyTable::I32($t) => $body,
+            AnyTable::I64($t) => $body,
+            AnyTable::U8($t) => $body,
+            AnyTable::U16($t) => $body,
+            AnyTable::U32($t) => $body,
+            AnyTable::U64($t) => $body,
+        }
+    };
+}
+
+/// An imported table, with handles to all the fields we know how to read
+struct InspectedTable {
+    info: TableInfo,
+    table: AnyTable,
+    fields: Vec<(String, AnyField)>,
+}
+
+impl InspectedTable {
+    fn import(input: &TablesInput, name: &CStr, key_type: StateType) -> Result<Self, Error> {
+        let table = match key_type {
+            StateType::I8 => AnyTable::I8(input.get_table(name)?),
+            StateType::I16 => AnyTable::I16(input.get_table(name)?),
+            StateType::I32 => AnyTable::I32(input.get_table(name)?),
+            StateType::I64 => AnyTable::I64(input.get_table(name)?),
+            StateType::U8 => AnyTable::U8(input.get_table(name)?),
+            StateType::U16 => AnyTable::U16(input.get_table(name)?),
+            StateType::U32 => AnyTable::U32(input.get_table(name)?),
+            StateType::U64 => AnyTable::U64(input.get_table(name)?),
+            other => anyhow::bail!(
+                "unsupported key type {:?}: only integer keys are supported",
+                other
+            ),
+        };
+
+        let field_infos: Vec<FieldInfo> = with_table!(&table, |t| t
+            .list_fields(input)
+            .iter()
+            .map(|f| FieldInfo {
+                // SAFETY: field names are NUL-terminated strings owned by the framework
+                name: unsafe { CStr::from_ptr(f.name) }
+                    .to_string_lossy()
+                    .into_owned(),
+                field_type: StateType::from_raw(f.field_type),
+                read_only: f.read_only != 0,
+            })
+            .collect());
+
+        let mut fields = Vec::new();
+        for info in &field_infos {
+            if let Some(field) = with_table!(&table, |t| AnyField::get(input, t, info)?) {
+                fields.push((info.name.clone(), field));
+            }
+        }
+
+        Ok(Self {
+            info: TableInfo {
+                name: name.to_string_lossy().into_owned(),
+                key_type,
+                fields: field_infos,
+                error: None,
+            },
+            table,
+            fields,
+        })
+    }
+
+    fn read_entry(&self, r: &impl TableReader, entry: &DynEntry) -> Result<TableEntryData, Error> {
+        self.fields
+            .iter()
+            .map(|(name, field)| Ok((name.clone(), field.read(r, entry)?)))
+            .collect()
+    }
+
+    fn lookup(&self, r: &impl TableReader, key: TableKey) -> Result<Option<TableEntryData>, Error> {
+        with_table!(&self.table, |t| match t.get_entry(r, &key.convert()?) {
+            Ok(entry) => Ok(Some(self.read_entry(r, &entry)?)),
+            Err(_) => Ok(None),
+        })
+    }
+
+    /// Find the field holding the key of every entry
+    ///
+    /// The iteration callback only gets the entries, not their keys, so this looks for
+    /// a field of the key type whose value, looked up in the table, gives back the entry
+    /// for every single one of them. Fails if there's no such field (the snapshot then
+    /// reports the error for this table only).
+    fn find_key_field(
+        &self,
+        r: &impl TableReader,
+        entries: &[TableEntryData],
+    ) -> Result<Option<String>, Error> {
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        self.info
+            .fields
+            .iter()
+            .filter(|info| info.field_type == self.info.key_type)
+            .map(|info| &info.name)
+            .find(|name| {
+                entries.iter().all(|data| {
+                    let Some(key) = data.get(*name).and_then(FieldValue::as_key) else {
+                        return false;
+                    };
+                    matches!(self.lookup(r, key), Ok(Some(found)) if &found == data)
+                })
+            })
+            .map(|name| Some(name.clone()))
+            .ok_or_else(|| anyhow::anyhow!("no field holds the key of every entry"))
+    }
+
+    fn snapshot(&self, r: &impl TableReader) -> Result<TableSnapshot, Error> {
+        let entries = self.entries(r)?;
+        let key_field = self.find_key_field(r, &entries)?;
+        let entries = match &key_field {
+            Some(field) => entries
+                .into_iter()
+                .filter_map(|data| Some((data.get(field)?.as_key()?, data)))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        Ok(TableSnapshot { key_field, entries })
+    }
+
+    /// Try to read and write every field, on a new entry that's never inserted in the table
+    fn probe_access(&self, r: &impl TableReader, w: &impl TableWriter) -> TableAccessReport {
+        let entry = with_table!(&self.table, |t| t.create_entry(w));
+        let fields = self
+            .info
+            .fields
+            .iter()
+            .map(|info| {
+                let field = self.fields.iter().find(|(n, _)| *n == info.name);
+                let access = match (&entry, field) {
+                    (Ok(entry), Some((_, field))) => match field.read(r, entry) {
+                        Err(_) => FieldAccess::Unreadable,
+                        Ok(value) => match field.write(w, entry, &value) {
+                            Ok(()) => FieldAccess::Writable,
+                            Err(_) => FieldAccess::ReadOnly,
+                        },
+                    },
+                    _ => FieldAccess::Unprobed,
+                };
+
+                FieldAccessReport {
+                    name: info.name.clone(),
+                    field_type: info.field_type,
+                    declared_read_only: info.read_only,
+                    access,
+                }
+            })
+            .collect();
+
+        TableAccessReport {
+            table: self.info.name.clone(),
+            fields,
+        }
+    }
+
+    fn write_entry(
+        &self,
+        w: &impl TableWriter,
+        entry: &DynEntry,
+        data: &TableEntryData,
+    ) -> Result<(), Error> {
+        for (name, value) in data {
+            let (_, field) = self
+                .fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| anyhow::anyhow!("field {} not found", name))?;
+            field
+                .write(w, entry, value)
+                .with_context(|| format!("failed to write field {}", name))?;
+        }
+
+        Ok(())
+    }
+
+    fn insert(
+        &self,
+        r: &impl TableReader,
+        w: &impl TableWriter,
+        key: TableKey,
+        data: &TableEntryData,
+    ) -> Result<(), Error> {
+        with_table!(&self.table, |t| {
+            let entry = t.create_entry(w)?;
+            self.write_entry(w, &entry, data)?;
+            t.insert(r, w, &key.convert()?, entry)?;
+            Ok(())
+        })
+    }
+
+    fn update(
+        &self,
+        r: &impl TableReader,
+        w: &impl TableWriter,
+        key: TableKey,
+        data: &TableEntryData,
+    ) -> Result<(), Error> {
+        with_table!(&self.table, |t| {
+            let entry = t
+                .get_entry(r, &key.convert()?)
+                .map_err(|_| anyhow::anyhow!("entry {} not found", key))?;
+            self.write_entry(w, &entry, data)
+        })
+    }
+
+    fn remove(
+        &self,
+        r: &impl TableReader,
+        w: &impl TableWriter,
+        key: TableKey,
+    ) -> Result<(), Error> {
+        with_table!(&self.table, |t| {
+            t.erase(r, w, &key.convert()?)?;
+            Ok(())
+        })
+    }
+
+    fn entries(&self, r: &impl TableReader) -> Result<Vec<TableEntryData>, Error> {
+        let mut entries = Vec::new();
+        let mut error = None;
+        with_table!(&self.table, |t| t.iter_entries_mut(r, |entry| {
+            match self.read_entry(r, entry) {
+                Ok(data) => {
+                    entries.push(data);
+                    ControlFlow::Continue(())
+                }
+                Err(e) => {
+                    error = Some(e);
+                    ControlFlow::Break(())
+                }
+            }
+        })?);
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(entries),
+        }
+    }
+}
+
+/// The inspector plugin, importing all tables exported by other plugins
+struct TableInspectorPlugin {
+    tables: Vec<InspectedTable>,
+    probed: bool,
+}
+
+impl TableInspectorPlugin {
+    fn table(&self, name: &str) -> Result<&InspectedTable, Error> {
+        self.tables
+            .iter()
+            .find(|t| t.info.name == name)
+            .ok_or_else(|| anyhow::anyhow!("table {} not found", name))
+    }
+
+    fn execute(&mut self, request: Request, r: &impl TableReader) -> Result<Response, Error> {
+        match request {
+            Request::Lookup { table, key } => {
+                let entry = self.table(&table)?.lookup(r, key)?;
+                Ok(Response::Entry(entry))
+            }
+            Request::Entries { table } => {
+                let entries = self.table(&table)?.entries(r)?;
+                Ok(Response::Entries(entries))
+            }
+            Request::Snapshot { tables } => {
+                for name in tables.iter().flatten() {
+                    self.table(name)?;
+                }
+
+                let mut snapshot = TablesSnapshot::default();
+                for table in &self.tables {
+                    let name = &table.info.name;
+                    if tables.as_ref().is_some_and(|t| !t.contains(name)) {
+                        continue;
+                    }
+                    // one table we can't snapshot doesn't spoil the others
+                    match table.snapshot(r) {
+                        Ok(contents) => {
+                            snapshot.tables.insert(name.clone(), contents);
+                        }
+                        Err(e) => {
+                            snapshot.errors.insert(name.clone(), format!("{:#}", e));
+                        }
+                    }
+                }
+                Ok(Response::Snapshot(snapshot))
+            }
+        }
+    }
+
+    fn apply(
+        &self,
+        mutation: Mutation,
+        r: &impl TableReader,
+        w: &impl TableWriter,
+    ) -> Result<(), Error> {
+        match mutation {
+            Mutation::Insert { table, key, fields } => self
+                .table(&table)?
+                .insert(r, w, key, &fields)
+                .with_context(|| format!("failed to insert {}[{}]", table, key)),
+            Mutation::Update { table, key, fields } => self
+                .table(&table)?
+                .update(r, w, key, &fields)
+                .with_context(|| format!("failed to update {}[{}]", table, key)),
+            Mutation::Remove { table, key } => self
+                .table(&table)?
+                .remove(r, w, key)
+                .with_context(|| format!("failed to remove {}[{}]", table, key)),
+        }
+    }
+
+    /// Process the pending request (if any); the actual field value is meaningless
+    fn extract_trigger(&mut self, req: ExtractRequest<Self>) -> Result<u64, Error> {
+        if let Some(request) = REQUEST.with_borrow_mut(Option::take) {
+            let response = self.execute(request, req.table_reader);
+            RESPONSE.with_borrow_mut(|r| *r = Some(response));
+        }
+
+        Ok(0)
+    }
+}
+
+impl Plugin for TableInspectorPlugin {
+    const NAME: &'static CStr = c"harness_table_inspector";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Gives the test harness access to all tables.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = ();
+
+    fn new(input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+        let Some(input) = input else {
+            anyhow::bail!("Table input not provided");
+        };
+
+        let mut tables = Vec::new();
+        let mut infos = Vec::new();
+        for info in input.list_tables() {
+            // SAFETY: table names are NUL-terminated strings owned by the framework
+            let name = unsafe { CStr::from_ptr(info.name) };
+            let key_type = StateType::from_raw(info.key_type);
+            match InspectedTable::import(input, name, key_type) {
+                Ok(table) => {
+                    infos.push(table.info.clone());
+                    tables.push(table);
+                }
+                // we can still list tables we can't access, along with the reason
+                Err(e) => infos.push(TableInfo {
+                    name: name.to_string_lossy().into_owned(),
+                    key_type,
+                    fields: Vec::new(),
+                    error: Some(format!("{:#}", e)),
+                }),
+            }
+        }
+
+        TABLE_INFOS.with_borrow_mut(|t| *t = Some(infos));
+
+        Ok(Self {
+            tables,
+            probed: false,
+        })
+    }
+}
+
+impl ExtractPlugin for TableInspectorPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+    type ExtractContext = ();
+    const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] =
+        &[field(TRIGGER_FIELD, &Self::extract_trigger)];
+}
+
+impl ParsePlugin for TableInspectorPlugin {
+    const EVENT_TYPES: &'static [EventType] = &[];
+    const EVENT_SOURCES: &'static [&'static str] = &[];
+
+    /// Probe the table access on the first event, then apply all the queued mutations,
+    /// recording any errors for the driver
+    fn parse_event(&mut self, _event: &EventInput, parse_input: &ParseInput) -> Result<(), Error> {
+        if !self.probed {
+            self.probed = true;
+            let reports = self
+                .tables
+                .iter()
+                .map(|t| t.probe_access(&parse_input.reader, &parse_input.writer))
+                .collect();
+            ACCESS_REPORTS.with_borrow_mut(|r| *r = Some(reports));
+        }
+
+        for mutation in take_mutations() {
+            if let Err(e) = self.apply(mutation, &parse_input.reader, &parse_input.writer) {
+                MUTATION_ERRORS.with_borrow_mut(|errors| errors.push(e));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CaptureListenPlugin for TableInspectorPlugin {
+    /// Apply the mutations queued before the capture started (see [`submit_mutations`])
+    fn capture_open(&mut self, listen_input: &CaptureListenInput) -> Result<(), Error> {
+        for mutation in take_mutations() {
+            if let Err(e) = self.apply(mutation, &listen_input.reader, &listen_input.writer) {
+                MUTATION_ERRORS.with_borrow_mut(|errors| errors.push(e));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn capture_close(&mut self, _listen_input: &CaptureListenInput) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+static_plugin!(TABLE_INSPECTOR_PLUGIN = TableInspectorPlugin);
+
+pub(crate) static PLUGIN: falco_plugin::api::plugin_api = TABLE_INSPECTOR_PLUGIN;
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldValue, StateType};
+    use crate::native::{NativeCapturingTestDriver, NativeTestDriver};
+    use crate::replay_source_plugin::{self, process_lifecycle};
+    use crate::{histogram_plugin, CapturingTestDriver, TestDriver};
+
+    /// Set up a capture of the process lifecycle (2 events from tid 100, then 8 from
+    /// tid 101), counted by the histogram plugin
+    fn driver() -> NativeTestDriver {
+        let mut driver = NativeTestDriver::new().unwrap();
+        let config = replay_source_plugin::config(&process_lifecycle());
+        driver
+            .register_plugin(&replay_source_plugin::PLUGIN, &config)
+            .unwrap();
+        driver
+            .register_plugin(&histogram_plugin::PLUGIN, c"")
+            .unwrap();
+        driver.enable_table_inspection();
+        driver
+    }
+
+    fn start() -> NativeCapturingTestDriver {
+        driver().start_capture(c"", c"").unwrap()
+    }
+
+    #[test]
+    fn inspect_table() {
+        let mut driver = start();
+
+        // the table schema is available right after the capture starts
+        let histogram = driver
+            .tables()
+            .iter()
+            .find(|t| t.name == "histogram")
+            .unwrap();
+        assert_eq!(histogram.key_type, StateType::U64);
+        assert_eq!(histogram.error, None);
+        let mut fields: Vec<&str> = histogram.fields.iter().map(|f| f.name.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, vec!["count", "number"]);
+
+        let mut event = driver.next_event().unwrap();
+        for _ in 1..10 {
+            event = driver.next_event().unwrap();
+        }
+
+        let mut entries: Vec<(FieldValue, FieldValue)> = driver
+            .table_entries(&event, "histogram")
+            .unwrap()
+            .into_iter()
+            .map(|mut entry| {
+                (
+                    entry.remove("number").unwrap(),
+                    entry.remove("count").unwrap(),
+                )
+            })
+            .collect();
+        entries.sort_by_key(|(number, _)| number.to_string());
+        assert_eq!(
+            entries,
+            vec![
+                (FieldValue::Unsigned(100), FieldValue::Unsigned(2)),
+                (FieldValue::Unsigned(101), FieldValue::Unsigned(8)),
+            ]
+        );
+
+        // looking up a single entry gives the same result
+        let count = driver
+            .table_field(&event, "histogram", 101u64, "count")
+            .unwrap();
+        assert_eq!(count, Some(FieldValue::Unsigned(8)));
+        let missing = driver
+            .table_field(&event, "histogram", 102u64, "count")
+            .unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn seed_table() {
+        let mut driver = driver();
+        // pretend the child already had 100 events; the entry gets written when
+        // the capture opens, before any event is parsed
+        driver.seed_table_entry(
+            "histogram",
+            101u64,
+            [
+                ("number", FieldValue::Unsigned(101)),
+                ("count", FieldValue::Unsigned(100)),
+            ],
+        );
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        // the first event of the child
+        let mut event = driver.next_event().unwrap();
+        for _ in 1..3 {
+            event = driver.next_event().unwrap();
+        }
+        assert!(driver.take_table_errors().is_empty());
+        let count = driver
+            .table_field(&event, "histogram", 101u64, "count")
+            .unwrap();
+        assert_eq!(count, Some(FieldValue::Unsigned(101)));
+
+        // the entry is removed after the plugin parsed the next event...
+        driver.remove_table_entry("histogram", 101u64).unwrap();
+        let event = driver.next_event().unwrap();
+        assert!(driver.take_table_errors().is_empty());
+        let count = driver
+            .table_field(&event, "histogram", 101u64, "count")
+            .unwrap();
+        assert_eq!(count, None);
+
+        // ...so it starts counting from scratch with the one after
+        let event = driver.next_event().unwrap();
+        let count = driver
+            .table_field(&event, "histogram", 101u64, "count")
+            .unwrap();
+        assert_eq!(count, Some(FieldValue::Unsigned(1)));
+    }
+
+    #[test]
+    fn string_keys() {
+        let mut driver = start();
+        driver.next_event().unwrap();
+
+        // the table is listed, with the reason it can't be inspected
+        let sources = driver
+            .tables()
+            .iter()
+            .find(|t| t.name == "sources")
+            .unwrap();
+        assert_eq!(sources.key_type, StateType::String);
+        assert!(sources.fields.is_empty());
+        let error = sources.error.as_deref().unwrap();
+        assert!(
+            error.contains("only integer keys are supported"),
+            "{}",
+            error
+        );
+
+        let event = driver.next_event().unwrap();
+        assert!(driver.table_entries(&event, "sources").is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use falco_plugin::anyhow::Error;
+use falco_plugin::base::{Json, Plugin};
+use falco_plugin::event::events::types::EventType::{
+    PROCEXIT_1_E, SYSCALL_CLONE_20_X, SYSCALL_CLOSE_E, SYSCALL_CLOSE_X, SYSCALL_EXECVE_19_X,
+    SYSCALL_OPEN_X,
+};
+use falco_plugin::event::events::types::{
+    EventType, PPME_PROCEXIT_1_E, PPME_SYSCALL_CLONE_20_X, PPME_SYSCALL_CLOSE_E,
+    PPME_SYSCALL_CLOSE_X, PPME_SYSCALL_EXECVE_19_X, PPME_SYSCALL_OPEN_X,
+};
+use falco_plugin::extract::EventInput;
+use falco_plugin::parse::{ParseInput, ParsePlugin};
+use falco_plugin::schemars::JsonSchema;
+use falco_plugin::serde::Deserialize;
+use falco_plugin::static_plugin;
+use falco_plugin::tables::export::{Entry, Public, Readonly};
+use falco_plugin::tables::{export, TablesInput};
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+
+/// An entry in the `file_descriptors` table nested in each thread, keyed by fd number
+#[derive(Entry)]
+struct FdEntry {
+    name: Public<CString>,
+    openflags: Public<u32>,
+}
+
+type FdTable = export::Table<i64, FdEntry>;
+
+/// An entry in the `threads` table, keyed by thread id, using the sinsp field names
+#[derive(Entry)]
+struct ThreadEntry {
+    tid: Public<i64>,
+    pid: Public<i64>,
+    ptid: Public<i64>,
+    comm: Public<CString>,
+    exe: Public<CString>,
+    exepath: Public<CString>,
+    cwd: Public<CString>,
+    uid: Public<u32>,
+    gid: Public<u32>,
+    file_descriptors: Readonly<Box<FdTable>>,
+}
+
+type ThreadTable = export::Table<i64, ThreadEntry>;
+
+/// A file descriptor to pre-populate a thread with
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+pub struct FdConfig {
+    pub fd: i64,
+    pub name: String,
+    #[serde(default)]
+    pub openflags: u32,
+}
+
+/// A thread to pre-populate the table with
+///
+/// Only `tid` is required; `pid` defaults to `tid` and `exepath` defaults to `exe`.
+#[derive(JsonSchema, Deserialize)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+pub struct ThreadConfig {
+    pub tid: i64,
+    pub pid: Option<i64>,
+    #[serde(default)]
+    pub ptid: i64,
+    #[serde(default)]
+    pub comm: String,
+    #[serde(default)]
+    pub exe: String,
+    pub exepath: Option<String>,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+    #[serde(default)]
+    pub fds: Vec<FdConfig>,
+}
+
+/// The initial contents of the `threads` table
+///
+/// An empty JSON object (`{}`) starts with an empty table, to be filled from events.
+#[derive(JsonSchema, Deserialize, Default)]
+#[schemars(crate = "falco_plugin::schemars")]
+#[serde(crate = "falco_plugin::serde")]
+#[serde(default)]
+pub struct ThreadsConfig {
+    pub threads: Vec<ThreadConfig>,
+}
+
+struct ThreadsTablePlugin {
+    threads: Box<ThreadTable>,
+    /// The name and open flags of every fd, by tid and fd number
+    ///
+    /// Exported tables can't be walked by key, so this copy is what clone
+    /// uses to pass the fds on to the child.
+    fds: BTreeMap<i64, BTreeMap<i64, (CString, u32)>>,
+    /// The fd passed to the close call in progress in each thread
+    closing: BTreeMap<i64, i64>,
+}
+
+impl ThreadsTablePlugin {
+    /// Get the entry for `tid`, creating an empty one if the thread is not known yet
+    fn thread_entry(&mut self, tid: i64) -> Result<export::TableEntry<ThreadEntry>, Error> {
+        if let Some(entry) = self.threads.lookup(&tid) {
+            return Ok(entry);
+        }
+
+        let mut entry = self.threads.create_entry()?;
+        *entry.tid = tid;
+        *entry.pid = tid;
+        self.threads.insert(&tid, entry);
+        Ok(self.threads.lookup(&tid).expect("entry was just inserted"))
+    }
+
+    fn add_fd(&mut self, tid: i64, fd: i64, name: &CStr, openflags: u32) -> Result<(), Error> {
+        let thread = self.thread_entry(tid)?;
+        let mut fd_entry = thread.file_descriptors.create_entry()?;
+        *fd_entry.name = name.to_owned();
+        *fd_entry.openflags = openflags;
+        thread.file_descriptors.insert(&fd, fd_entry);
+
+        self.fds
+            .entry(tid)
+            .or_default()
+            .insert(fd, (name.to_owned(), openflags));
+        Ok(())
+    }
+
+    fn remove_fd(&mut self, tid: i64, fd: i64) {
+        if let Some(thread) = self.threads.lookup(&tid) {
+            thread.file_descriptors.erase(&fd);
+        }
+        if let Some(fds) = self.fds.get_mut(&tid) {
+            fds.remove(&fd);
+        }
+    }
+
+    fn remove_thread(&mut self, tid: i64) {
+        self.threads.erase(&tid);
+        self.fds.remove(&tid);
+        self.closing.remove(&tid);
+    }
+
+    fn add_configured_thread(&mut self, config: ThreadConfig) -> Result<(), Error> {
+        let mut entry = self.thread_entry(config.tid)?;
+        *entry.pid = config.pid.unwrap_or(config.tid);
+        *entry.ptid = config.ptid;
+        *entry.comm = CString::new(config.comm)?;
+        *entry.exepath = CString::new(config.exepath.unwrap_or_else(|| config.exe.clone()))?;
+        *entry.exe = CString::new(config.exe)?;
+        *entry.cwd = CString::new(config.cwd)?;
+        *entry.uid = config.uid;
+        *entry.gid = config.gid;
+
+        for fd in config.fds {
+            self.add_fd(config.tid, fd.fd, &CString::new(fd.name)?, fd.openflags)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_clone(&mut self, event: PPME_SYSCALL_CLONE_20_X) -> Result<(), Error> {
+        // the parent sees the child tid as the return value, only the child
+        // (with a zero return value) has the full picture
+        if event.res.map(|res| res.0) != Some(0) {
+            return Ok(());
+        }
+        let Some(tid) = event.tid else {
+            return Ok(());
+        };
+
+        let tid = tid.0;
+        let mut entry = self.thread_entry(tid)?;
+        if let Some(pid) = event.pid {
+            *entry.pid = pid.0;
+        }
+        if let Some(ptid) = event.ptid {
+            *entry.ptid = ptid.0;
+        }
+        if let Some(comm) = event.comm {
+            *entry.comm = comm.to_owned();
+        }
+        if let Some(exe) = event.exe {
+            *entry.exe = exe.to_owned();
+            *entry.exepath = exe.to_owned();
+        }
+        if let Some(cwd) = event.cwd {
+            *entry.cwd = cwd.to_owned();
+        }
+        if let Some(uid) = event.uid {
+            *entry.uid = uid;
+        }
+        if let Some(gid) = event.gid {
+            *entry.gid = gid;
+        }
+        let ptid = *entry.ptid;
+
+        // the child starts with a copy of its parent's fd table
+        if !self.fds.contains_key(&tid) {
+            let inherited = self.fds.get(&ptid).cloned().unwrap_or_default();
+            for (fd, (name, openflags)) in inherited {
+                self.add_fd(tid, fd, &name, openflags)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_execve(&mut self, tid: i64, event: PPME_SYSCALL_EXECVE_19_X) -> Result<(), Error> {
+        if event.res.map(|res| res.0) != Some(0) {
+            return Ok(());
+        }
+
+        let mut entry = self.thread_entry(tid)?;
+        if let Some(pid) = event.pid {
+            *entry.pid = pid.0;
+        }
+        if let Some(ptid) = event.ptid {
+            *entry.ptid = ptid.0;
+        }
+        if let Some(comm) = event.comm {
+            *entry.comm = comm.to_owned();
+        }
+        if let Some(exe) = event.exe {
+            *entry.exe = exe.to_owned();
+            *entry.exepath = exe.to_owned();
+        }
+        if let Some(cwd) = event.cwd {
+            *entry.cwd = cwd.to_owned();
+        }
+
+        Ok(())
+    }
+}
+
+impl Plugin for ThreadsTablePlugin {
+    const NAME: &'static CStr = c"threads";
+    const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+    const DESCRIPTION: &'static CStr = c"Provides a sinsp-like threads table.";
+    const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+    type ConfigType = Json<ThreadsConfig>;
+
+    fn new(input: Option<&TablesInput>, Json(config): Self::ConfigType) -> Result<Self, Error> {
+        let Some(input) = input else {
+            falco_plugin::anyhow::bail!("Table input not provided");
+        };
+
+        let threads = input.add_table(ThreadTable::new(c"threads")?)?;
+        let mut plugin = Self {
+            threads,
+            fds: BTreeMap::new(),
+            closing: BTreeMap::new(),
+        };
+        for thread in config.threads {
+            plugin.add_configured_thread(thread)?;
+        }
+
+        Ok(plugin)
+    }
+}
+
+impl ParsePlugin for ThreadsTablePlugin {
+    const EVENT_TYPES: &'static [EventType] = &[
+        SYSCALL_CLONE_20_X,
+        SYSCALL_EXECVE_19_X,
+        SYSCALL_OPEN_X,
+        SYSCALL_CLOSE_E,
+        SYSCALL_CLOSE_X,
+        PROCEXIT_1_E,
+    ];
+    const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+
+    fn parse_event(&mut self, event: &EventInput, _parse_input: &ParseInput) -> Result<(), Error> {
+        let event = event.event()?;
+        let tid = event.metadata.tid as i64;
+
+        if let Ok(ev) = event.load::<PPME_SYSCALL_CLONE_20_X>() {
+            self.parse_clone(ev.params)
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_EXECVE_19_X>() {
+            self.parse_execve(tid, ev.params)
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_OPEN_X>() {
+            match (ev.params.fd, ev.params.name) {
+                (Some(fd), Some(name)) if fd.0 >= 0 => {
+                    let name = CString::new(name.as_bytes())?;
+                    let flags = ev.params.flags.map(|f| f.bits()).unwrap_or_default();
+                    self.add_fd(tid, fd.0, &name, flags)
+                }
+                _ => Ok(()),
+            }
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_CLOSE_E>() {
+            // the fd is only gone once the close has succeeded
+            if let Some(fd) = ev.params.fd {
+                self.closing.insert(tid, fd.0);
+            }
+            Ok(())
+        } else if let Ok(ev) = event.load::<PPME_SYSCALL_CLOSE_X>() {
+            let fd = self.closing.remove(&tid);
+            if let (Some(fd), Some(res)) = (fd, ev.params.res) {
+                if res.0 >= 0 {
+                    self.remove_fd(tid, fd);
+                }
+            }
+            Ok(())
+        } else if event.load::<PPME_PROCEXIT_1_E>().is_ok() {
+            self.remove_thread(tid);
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+static_plugin!(THREADS_TABLE_PLUGIN = ThreadsTablePlugin);
+
+/// A parse plugin exporting a sinsp-like `threads` table, with a nested
+/// `file_descriptors` table in each entry
+///
+/// The table is seeded from the JSON config (see [`ThreadsConfig`]) and kept up to date
+/// from clone, execve, open, close and procexit events on the `syscall` source. Cloned
+/// threads start with a copy of their parent's fds.
+/// Register it before any plugin that imports `threads`.
+pub static PLUGIN: falco_plugin::api::plugin_api = THREADS_TABLE_PLUGIN;
+
+#[cfg(test)]
+mod tests {
+    use crate::native::NativeTestDriver;
+    use crate::{replay_source_plugin, CapturingTestDriver, TestDriver};
+    use falco_plugin::anyhow::{anyhow, Error};
+    use falco_plugin::base::Plugin;
+    use falco_plugin::event::events::types::EventType;
+    use falco_plugin::extract::{field, ExtractFieldInfo, ExtractPlugin, ExtractRequest};
+    use falco_plugin::static_plugin;
+    use falco_plugin::tables::import::{Entry, Field, Table, TableMetadata};
+    use falco_plugin::tables::TablesInput;
+    use std::ffi::{CStr, CString};
+    use std::ops::ControlFlow;
+    use std::sync::Arc;
+
+    type ImportedFds = Table<i64, ImportedFd>;
+    type ImportedFd = Entry<Arc<ImportedFdMetadata>>;
+
+    #[derive(TableMetadata)]
+    #[entry_type(ImportedFd)]
+    struct ImportedFdMetadata {
+        name: Field<CStr, ImportedFd>,
+    }
+
+    type ImportedThreads = Table<i64, ImportedThread>;
+    type ImportedThread = Entry<Arc<ImportedThreadMetadata>>;
+
+    #[derive(TableMetadata)]
+    #[entry_type(ImportedThread)]
+    struct ImportedThreadMetadata {
+        comm: Field<CStr, ImportedThread>,
+        file_descriptors: Field<ImportedFds, ImportedThread>,
+    }
+
+    /// A plugin importing `threads`, the way plugins written for Falco do
+    struct ThreadsImporter {
+        threads: ImportedThreads,
+    }
+
+    impl Plugin for ThreadsImporter {
+        const NAME: &'static CStr = c"threads_importer";
+        const PLUGIN_VERSION: &'static CStr = c"0.0.1";
+        const DESCRIPTION: &'static CStr = c"Reads the threads table.";
+        const CONTACT: &'static CStr = c"https://github.com/falcosecurity/plugin-sdk-rs";
+        type ConfigType = ();
+
+        fn new(input: Option<&TablesInput>, _config: Self::ConfigType) -> Result<Self, Error> {
+            let input = input.ok_or_else(|| anyhow!("Table input not provided"))?;
+            Ok(Self {
+                threads: input.get_table(c"threads")?,
+            })
+        }
+    }
+
+    impl ThreadsImporter {
+        fn thread(&self, req: &ExtractRequest<Self>) -> Result<ImportedThread, Error> {
+            let tid = req.event.event()?.metadata.tid as i64;
+            self.threads.get_entry(req.table_reader, &tid)
+        }
+
+        fn extract_comm(&mut self, req: ExtractRequest<Self>) -> Result<CString, Error> {
+            let thread = self.thread(&req)?;
+            Ok(thread.get_comm(req.table_reader)?.to_owned())
+        }
+
+        /// The names of all the thread's fds, sorted and separated by commas
+        fn extract_fds(&mut self, req: ExtractRequest<Self>) -> Result<CString, Error> {
+            let r = req.table_reader;
+            let fds = self.thread(&req)?.get_file_descriptors(r)?;
+
+            let mut names = Vec::new();
+            fds.iter_entries_mut(r, |fd| {
+                if let Ok(name) = fd.get_name(r) {
+                    names.push(name.to_string_lossy().into_owned());
+                }
+                ControlFlow::Continue(())
+            })?;
+            names.sort();
+
+            Ok(CString::new(names.join(","))?)
+        }
+    }
+
+    impl ExtractPlugin for ThreadsImporter {
+        const EVENT_TYPES: &'static [EventType] = &[];
+        const EVENT_SOURCES: &'static [&'static str] = &["syscall"];
+        type ExtractContext = ();
+        const EXTRACT_FIELDS: &'static [ExtractFieldInfo<Self>] = &[
+            field("importer.comm", &Self::extract_comm),
+            field("importer.fds", &Self::extract_fds),
+        ];
+    }
+
+    static_plugin!(THREADS_IMPORTER = ThreadsImporter);
+
+    #[test]
+    fn importer_initializes_and_reads_threads() {
+        let config = c"{\"threads\": [{\"tid\": 100, \"comm\": \"sh\", \"fds\": [{\"fd\": 0, \"name\": \"/dev/null\"}]}]}";
+
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.register_plugin(&super::PLUGIN, config).unwrap();
+        driver
+            .register_plugin(
+                &replay_source_plugin::PLUGIN,
+                &replay_source_plugin::config(&replay_source_plugin::process_lifecycle()),
+            )
+            .unwrap();
+        driver.register_plugin(&THREADS_IMPORTER, c"").unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        let mut fields = Vec::new();
+        for _ in 0..9 {
+            let event = driver.next_event().unwrap();
+            let comm = driver
+                .event_field_as_string(c"importer.comm", &event)
+                .unwrap();
+            let fds = driver
+                .event_field_as_string(c"importer.fds", &event)
+                .unwrap();
+            fields.push((comm.unwrap(), fds.unwrap()));
+        }
+
+        let expected = [
+            // the configured shell opens its log file
+            ("sh", "/dev/null,/var/log/sh.log"),
+            ("sh", "/dev/null,/var/log/sh.log"),
+            // the child inherits the shell's fds
+            ("sh", "/dev/null,/var/log/sh.log"),
+            ("cat", "/dev/null,/var/log/sh.log"),
+            ("cat", "/dev/null,/etc/passwd,/var/log/sh.log"),
+            ("cat", "/dev/null,/etc/passwd,/var/log/sh.log"),
+            ("cat", "/dev/null,/etc/passwd,/var/log/sh.log"),
+            // the fd is still there until the close succeeds
+            ("cat", "/dev/null,/etc/passwd,/var/log/sh.log"),
+            ("cat", "/dev/null,/var/log/sh.log"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(comm, fds)| (comm.to_string(), fds.to_string()))
+            .collect();
+        assert_eq!(fields, expected);
+
+        // the thread is gone once it exits
+        let exit = driver.next_event().unwrap();
+        assert!(driver
+            .event_field_as_string(c"importer.comm", &exit)
+            .is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Plugin API call tracing
+//!
+//! When enabled on the native driver, every call the framework makes into a plugin
+//! is recorded as a [`TraceRecord`], with the time it took and its result.
+//! Calls only made by the harness itself (e.g. to inspect tables) are not recorded.
+
+use falco_plugin::api::{
+    ss_plugin_rc, ss_plugin_rc_SS_PLUGIN_EOF, ss_plugin_rc_SS_PLUGIN_FAILURE,
+    ss_plugin_rc_SS_PLUGIN_NOT_SUPPORTED, ss_plugin_rc_SS_PLUGIN_SUCCESS,
+    ss_plugin_rc_SS_PLUGIN_TIMEOUT,
+};
+use std::time::Duration;
+
+/// A call into the plugin API, with its most interesting arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiCall {
+    GetInitSchema,
+    Init,
+    Destroy,
+    SetConfig,
+    Open {
+        params: Option<String>,
+    },
+    Close,
+    ListOpenParams,
+    /// `batch_size` is the number of events the plugin returned
+    NextBatch {
+        batch_size: u32,
+    },
+    EventToString {
+        event_type: u16,
+    },
+    /// `fields` are the requested field names, with the argument (if any) in brackets
+    ExtractFields {
+        event_type: u16,
+        evt_num: u64,
+        fields: Vec<String>,
+    },
+    ParseEvent {
+        event_type: u16,
+        evt_num: u64,
+    },
+    AsyncStart,
+    AsyncStop,
+    GetMetrics,
+    CaptureOpen,
+    CaptureClose,
+}
+
+/// The outcome of a plugin API call
+///
+/// Calls that don't return a status code are considered successful unless they
+/// return a null pointer where a value is expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallResult {
+    Success,
+    Failure,
+    Timeout,
+    Eof,
+    NotSupported,
+    Other(ss_plugin_rc),
+}
+
+impl CallResult {
+    pub(crate) fn from_rc(rc: ss_plugin_rc) -> Self {
+        match rc {
+            ss_plugin_rc_SS_PLUGIN_SUCCESS => CallResult::Success,
+            ss_plugin_rc_SS_PLUGIN_FAILURE => CallResult::Failure,
+            ss_plugin_rc_SS_PLUGIN_TIMEOUT => CallResult::Timeout,
+            ss_plugin_rc_SS_PLUGIN_EOF => CallResult::Eof,
+            ss_plugin_rc_SS_PLUGIN_NOT_SUPPORTED => CallResult::NotSupported,
+            other => CallResult::Other(other),
+        }
+    }
+
+    pub(crate) fn from_ptr<T>(ptr: *const T) -> Self {
+        if ptr.is_null() {
+            CallResult::Failure
+        } else {
+            CallResult::Success
+        }
+    }
+}
+
+/// A single recorded plugin API call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The name of the plugin, as reported by `get_name`
+    pub plugin: String,
+    pub call: ApiCall,
+    pub duration: Duration,
+    pub result: CallResult,
+}
+
+/// Helpers for asserting on a recorded trace
+pub trait TraceExt {
+    /// All the calls made into `plugin`, in order
+    fn calls_to(&self, plugin: &str) -> Vec<&ApiCall>;
+
+    /// The event types `plugin` was asked to extract fields from
+    fn extracted_event_types(&self, plugin: &str) -> Vec<u16>;
+
+    /// The event types `plugin` was asked to parse
+    fn parsed_event_types(&self, plugin: &str) -> Vec<u16>;
+}
+
+impl TraceExt for [TraceRecord] {
+    fn calls_to(&self, plugin: &str) -> Vec<&ApiCall> {
+        self.iter()
+            .filter(|r| r.plugin == plugin)
+            .map(|r| &r.call)
+            .collect()
+    }
+
+    fn extracted_event_types(&self, plugin: &str) -> Vec<u16> {
+        self.calls_to(plugin)
+            .into_iter()
+            .filter_map(|call| match call {
+                ApiCall::ExtractFields { event_type, .. } => Some(*event_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn parsed_event_types(&self, plugin: &str) -> Vec<u16> {
+        self.calls_to(plugin)
+            .into_iter()
+            .filter_map(|call| match call {
+                ApiCall::ParseEvent { event_type, .. } => Some(*event_type),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiCall, CallResult, TraceExt};
+    use crate::native::NativeTestDriver;
+    use crate::{syscall_extract_plugin, syscall_source_plugin};
+    use crate::{CapturingTestDriver, TestDriver};
+    use falco_plugin::event::events::types::EventType;
+
+    #[test]
+    fn trace_plugin_calls() {
+        let mut driver = NativeTestDriver::new().unwrap();
+        driver.enable_tracing();
+        driver
+            .register_plugin(&syscall_source_plugin::PLUGIN, c"")
+            .unwrap();
+        driver
+            .register_plugin(&syscall_extract_plugin::PLUGIN, c"")
+            .unwrap();
+        let mut driver = driver.start_capture(c"", c"").unwrap();
+
+        while let Ok(evt) = driver.next_event() {
+            driver.event_field_as_string(c"rustlings.fd", &evt).unwrap();
+        }
+
+        let trace = driver.trace();
+        let calls = trace.calls_to("syscall-extract");
+        assert_eq!(calls.first(), Some(&&ApiCall::Init));
+        assert!(trace
+            .iter()
+            .filter(|r| r.call == ApiCall::Init)
+            .all(|r| r.result == CallResult::Success));
+
+        // the plugin is only ever asked about the event types it declared
+        assert_eq!(
+            trace.extracted_event_types("syscall-extract"),
+            vec![
+                EventType::SYSCALL_OPEN_X as u16,
+                EventType::SYSCALL_READ_E as u16,
+                EventType::SYSCALL_CLOSE_E as u16,
+            ]
+        );
+        for call in calls {
+            if let ApiCall::ExtractFields { fields, .. } = call {
+                assert_eq!(fields, &vec![String::from("rustlings.fd")]);
+            }
+        }
+    }
+}